@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX blocks_creation_time_idx;
+DROP INDEX blocks_miner_creation_time_idx;
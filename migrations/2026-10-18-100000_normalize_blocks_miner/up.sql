@@ -0,0 +1,13 @@
+-- Miner accounts used to be stored as JSON strings, including the surrounding quotes.
+UPDATE blocks
+SET miner = trim(both '"' from miner),
+    predicate = trim(both '"' from predicate)
+WHERE miner LIKE '"%' OR predicate LIKE '"%';
+
+CREATE INDEX blocks_miner_creation_time_idx
+  ON blocks
+  USING btree (miner, creation_time DESC);
+
+CREATE INDEX blocks_creation_time_idx
+  ON blocks
+  USING btree (creation_time DESC, chain_id);
@@ -0,0 +1,28 @@
+CREATE TABLE hashrate_buckets (
+  chain_id bigint NOT NULL,
+  bucket timestamp with time zone NOT NULL,
+  blocks bigint NOT NULL,
+  difficulty numeric NOT NULL,
+  first_block_time timestamp with time zone NOT NULL,
+  last_block_time timestamp with time zone NOT NULL
+);
+
+ALTER TABLE ONLY hashrate_buckets
+    ADD CONSTRAINT hashrate_buckets_pkey PRIMARY KEY (chain_id, bucket);
+
+CREATE INDEX hashrate_buckets_bucket_idx
+  ON hashrate_buckets
+  USING btree (bucket DESC);
+
+-- Blocks indexed before targets were decoded have a target of 1 and are skipped, the
+-- targets subcommand of the indexer fetches their headers again and fills these buckets.
+INSERT INTO hashrate_buckets
+SELECT chain_id,
+       date_trunc('hour', creation_time),
+       count(*),
+       sum(power(2::numeric, 256) / target),
+       min(creation_time),
+       max(creation_time)
+FROM blocks
+WHERE target > 1
+GROUP BY chain_id, date_trunc('hour', creation_time);
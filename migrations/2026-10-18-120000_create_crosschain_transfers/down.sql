@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP VIEW crosschain_transfers;
+DROP INDEX transfers_pact_id_idx;
@@ -0,0 +1,36 @@
+CREATE INDEX transfers_pact_id_idx
+  ON transfers
+  USING btree (pact_id)
+  WHERE pact_id IS NOT NULL;
+
+-- Links the step 0 leg (sender -> "") of a cross-chain transfer with its continuation
+-- on the target chain ("" -> receiver), which shares the same pact id. Until the
+-- continuation lands, receiver and target chain come from the TRANSFER_XCHAIN event.
+CREATE VIEW crosschain_transfers AS
+SELECT source.pact_id,
+       source.module_name,
+       source.chain_id AS source_chain_id,
+       COALESCE(target.chain_id, (xchain.params ->> 3)::bigint) AS target_chain_id,
+       source.from_account AS sender,
+       COALESCE(target.to_account, xchain.params ->> 1) AS receiver,
+       source.amount,
+       source.request_key AS source_request_key,
+       target.request_key AS target_request_key,
+       source.height AS source_height,
+       target.height AS target_height,
+       source.creation_time AS initiation_time,
+       target.creation_time AS completion_time,
+       CASE WHEN target.pact_id IS NULL THEN 'pending' ELSE 'completed' END AS status
+FROM transfers source
+LEFT JOIN events xchain
+  ON xchain.request_key = source.request_key
+  AND xchain.block = source.block
+  AND xchain.module = source.module_name
+  AND xchain.name = 'TRANSFER_XCHAIN'
+LEFT JOIN transfers target
+  ON target.pact_id = source.pact_id
+  AND target.from_account = ''
+  AND target.chain_id <> source.chain_id
+WHERE source.pact_id IS NOT NULL
+  AND source.to_account = ''
+  AND source.from_account <> '';
@@ -0,0 +1,21 @@
+CREATE TABLE stuck_crosschain_transfers (
+  pact_id character varying PRIMARY KEY,
+  request_key character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  height bigint NOT NULL,
+  module_name character varying NOT NULL,
+  sender character varying NOT NULL,
+  receiver character varying,
+  target_chain_id bigint,
+  amount numeric NOT NULL,
+  initiation_time timestamp with time zone NOT NULL,
+  detected_at timestamp with time zone NOT NULL
+);
+
+CREATE INDEX stuck_crosschain_transfers_sender_idx
+  ON stuck_crosschain_transfers
+  USING btree (sender, initiation_time DESC);
+
+CREATE INDEX stuck_crosschain_transfers_receiver_idx
+  ON stuck_crosschain_transfers
+  USING btree (receiver, initiation_time DESC);
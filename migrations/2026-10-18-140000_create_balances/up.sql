@@ -0,0 +1,24 @@
+CREATE TABLE balances (
+  account character varying NOT NULL,
+  module character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  balance numeric NOT NULL
+);
+
+ALTER TABLE ONLY balances
+    ADD CONSTRAINT balances_pkey PRIMARY KEY (account, module, chain_id);
+
+CREATE INDEX balances_module_balance_idx
+  ON balances
+  USING btree (module, balance DESC);
+
+INSERT INTO balances
+SELECT account, module_name, chain_id, sum(amount)
+FROM (
+  SELECT to_account AS account, module_name, chain_id, amount
+  FROM transfers WHERE to_account <> ''
+  UNION ALL
+  SELECT from_account AS account, module_name, chain_id, -amount
+  FROM transfers WHERE from_account <> ''
+) AS deltas
+GROUP BY account, module_name, chain_id;
@@ -0,0 +1,11 @@
+CREATE TABLE balance_mismatches (
+  account character varying NOT NULL,
+  module character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  indexed_balance numeric NOT NULL,
+  node_balance numeric,
+  checked_at timestamp with time zone NOT NULL
+);
+
+ALTER TABLE ONLY balance_mismatches
+    ADD CONSTRAINT balance_mismatches_pkey PRIMARY KEY (account, module, chain_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE token_module_hashes;
+DROP TABLE tokens;
@@ -0,0 +1,60 @@
+CREATE TABLE tokens (
+  module character varying NOT NULL,
+  namespace character varying,
+  first_seen_height bigint NOT NULL,
+  first_seen_chain_id bigint NOT NULL,
+  first_seen_time timestamp with time zone NOT NULL,
+  precision integer,
+  transfer_count bigint NOT NULL DEFAULT 0,
+  volume numeric NOT NULL DEFAULT 0
+);
+
+ALTER TABLE ONLY tokens
+    ADD CONSTRAINT tokens_pkey PRIMARY KEY (module);
+
+CREATE TABLE token_module_hashes (
+  module character varying NOT NULL,
+  module_hash character varying NOT NULL,
+  first_seen_height bigint NOT NULL,
+  first_seen_chain_id bigint NOT NULL,
+  first_seen_time timestamp with time zone NOT NULL,
+  transfer_count bigint NOT NULL DEFAULT 0
+);
+
+ALTER TABLE ONLY token_module_hashes
+    ADD CONSTRAINT token_module_hashes_pkey PRIMARY KEY (module, module_hash);
+
+INSERT INTO tokens
+SELECT DISTINCT ON (module_name)
+       module_name,
+       CASE WHEN position('.' in module_name) > 0 THEN split_part(module_name, '.', 1) END,
+       height,
+       chain_id,
+       creation_time
+FROM transfers
+ORDER BY module_name, creation_time;
+
+INSERT INTO token_module_hashes
+SELECT DISTINCT ON (module_name, module_hash)
+       module_name, module_hash, height, chain_id, creation_time
+FROM transfers
+ORDER BY module_name, module_hash, creation_time;
+
+UPDATE tokens
+SET transfer_count = stats.transfer_count, volume = stats.volume
+FROM (
+  SELECT module_name, count(*) AS transfer_count, sum(amount) AS volume
+  FROM transfers
+  GROUP BY module_name
+) AS stats
+WHERE tokens.module = stats.module_name;
+
+UPDATE token_module_hashes
+SET transfer_count = stats.transfer_count
+FROM (
+  SELECT module_name, module_hash, count(*) AS transfer_count
+  FROM transfers
+  GROUP BY module_name, module_hash
+) AS stats
+WHERE token_module_hashes.module = stats.module_name
+  AND token_module_hashes.module_hash = stats.module_hash;
@@ -0,0 +1,44 @@
+-- Hourly mints and burns per token and chain. Cross-chain transfers burn on the source
+-- chain and mint on the target chain within the module's transfer-crosschain pact, they
+-- are kept apart so they don't count as supply changes.
+CREATE TABLE supply_snapshots (
+  module character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  bucket timestamp with time zone NOT NULL,
+  minted numeric NOT NULL,
+  burned numeric NOT NULL,
+  crosschain_in numeric NOT NULL,
+  crosschain_out numeric NOT NULL
+);
+
+ALTER TABLE ONLY supply_snapshots
+    ADD CONSTRAINT supply_snapshots_pkey PRIMARY KEY (module, chain_id, bucket);
+
+INSERT INTO supply_snapshots
+SELECT module_name,
+       chain_id,
+       date_trunc('hour', creation_time),
+       sum(CASE WHEN from_account = '' AND NOT crosschain THEN amount ELSE 0 END),
+       sum(CASE WHEN to_account = '' AND NOT crosschain THEN amount ELSE 0 END),
+       sum(CASE WHEN from_account = '' AND crosschain THEN amount ELSE 0 END),
+       sum(CASE WHEN to_account = '' AND crosschain THEN amount ELSE 0 END)
+FROM (
+  SELECT transfers.module_name, transfers.chain_id, transfers.creation_time,
+         transfers.from_account, transfers.to_account, transfers.amount,
+         (transfers.pact_id IS NOT NULL AND (
+           tx.continuation -> 'continuation' ->> 'def'
+             = transfers.module_name || '.transfer-crosschain'
+           OR EXISTS (
+             SELECT 1 FROM events xchain
+             WHERE xchain.block = transfers.block
+               AND xchain.request_key = transfers.request_key
+               AND xchain.module = transfers.module_name
+               AND xchain.name = 'TRANSFER_XCHAIN'
+           )
+         )) IS TRUE AS crosschain
+  FROM transfers
+  LEFT JOIN transactions tx
+    ON tx.block = transfers.block AND tx.request_key = transfers.request_key
+  WHERE transfers.from_account = '' OR transfers.to_account = ''
+) AS supply_transfers
+GROUP BY module_name, chain_id, date_trunc('hour', creation_time);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE nft_sales;
+DROP TABLE nft_balances;
+DROP TABLE nft_tokens;
@@ -0,0 +1,74 @@
+-- Marmalade tokens are created per chain. Rows can be inserted by a MINT before the
+-- TOKEN event is indexed, in which case the metadata is filled in later.
+CREATE TABLE nft_tokens (
+  chain_id bigint NOT NULL,
+  token_id character varying NOT NULL,
+  module character varying,
+  precision integer,
+  uri character varying,
+  policies jsonb,
+  supply numeric NOT NULL,
+  block character varying,
+  height bigint,
+  request_key character varying,
+  creation_time timestamp with time zone
+);
+
+ALTER TABLE ONLY nft_tokens
+    ADD CONSTRAINT nft_tokens_pkey PRIMARY KEY (chain_id, token_id);
+
+CREATE INDEX nft_tokens_block_idx
+  ON nft_tokens
+  USING btree (block);
+
+-- Balances are taken from RECONCILE events, the event that set the balance is kept so
+-- that events indexed out of order don't overwrite newer balances. Events are compared
+-- by height, position of their transaction in the block and index.
+CREATE TABLE nft_balances (
+  chain_id bigint NOT NULL,
+  token_id character varying NOT NULL,
+  account character varying NOT NULL,
+  balance numeric NOT NULL,
+  block character varying NOT NULL,
+  request_key character varying NOT NULL,
+  height bigint NOT NULL,
+  idx bigint NOT NULL
+);
+
+ALTER TABLE ONLY nft_balances
+    ADD CONSTRAINT nft_balances_pkey PRIMARY KEY (chain_id, token_id, account);
+
+CREATE INDEX nft_balances_account_idx
+  ON nft_balances
+  USING btree (account);
+
+CREATE INDEX nft_balances_block_idx
+  ON nft_balances
+  USING btree (block);
+
+-- A sale is identified by the pact id of the SALE (OFFER in marmalade v1) step, the BUY
+-- event is emitted by the continuation of the same pact.
+CREATE TABLE nft_sales (
+  chain_id bigint NOT NULL,
+  sale_id character varying NOT NULL,
+  token_id character varying NOT NULL,
+  seller character varying NOT NULL,
+  amount numeric NOT NULL,
+  timeout bigint,
+  buyer character varying,
+  offer_block character varying,
+  offer_request_key character varying,
+  offer_height bigint,
+  offer_time timestamp with time zone,
+  buy_block character varying,
+  buy_request_key character varying,
+  buy_height bigint,
+  buy_time timestamp with time zone
+);
+
+ALTER TABLE ONLY nft_sales
+    ADD CONSTRAINT nft_sales_pkey PRIMARY KEY (chain_id, sale_id);
+
+CREATE INDEX nft_sales_token_id_idx
+  ON nft_sales
+  USING btree (token_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE liquidity_changes;
+DROP TABLE pool_reserves;
+DROP TABLE swaps;
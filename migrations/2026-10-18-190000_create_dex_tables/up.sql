@@ -0,0 +1,74 @@
+-- Swaps of the kaddex and kdswap exchanges. Pairs are named `token0:token1` with the tokens
+-- sorted like the exchange contracts do, the price is the price of token0 in token1.
+CREATE TABLE swaps (
+  block character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  height bigint NOT NULL,
+  idx bigint NOT NULL,
+  request_key character varying NOT NULL,
+  creation_time timestamp with time zone NOT NULL,
+  module character varying NOT NULL,
+  pair character varying NOT NULL,
+  trader character varying NOT NULL,
+  receiver character varying NOT NULL,
+  token_in character varying NOT NULL,
+  amount_in numeric NOT NULL,
+  token_out character varying NOT NULL,
+  amount_out numeric NOT NULL,
+  price numeric
+);
+
+ALTER TABLE ONLY swaps
+    ADD CONSTRAINT swaps_pkey PRIMARY KEY (block, idx, request_key);
+
+CREATE INDEX swaps_pair_creation_time_idx
+  ON swaps
+  USING btree (pair, creation_time);
+
+CREATE INDEX swaps_trader_idx
+  ON swaps
+  USING btree (trader);
+
+-- Reserves of a pair after every UPDATE event.
+CREATE TABLE pool_reserves (
+  block character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  height bigint NOT NULL,
+  idx bigint NOT NULL,
+  request_key character varying NOT NULL,
+  creation_time timestamp with time zone NOT NULL,
+  module character varying NOT NULL,
+  pair character varying NOT NULL,
+  reserve0 numeric NOT NULL,
+  reserve1 numeric NOT NULL
+);
+
+ALTER TABLE ONLY pool_reserves
+    ADD CONSTRAINT pool_reserves_pkey PRIMARY KEY (block, idx, request_key);
+
+CREATE INDEX pool_reserves_pair_creation_time_idx
+  ON pool_reserves
+  USING btree (pair, creation_time);
+
+-- ADD_LIQUIDITY and REMOVE_LIQUIDITY events, `kind` is either `add` or `remove`.
+CREATE TABLE liquidity_changes (
+  block character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  height bigint NOT NULL,
+  idx bigint NOT NULL,
+  request_key character varying NOT NULL,
+  creation_time timestamp with time zone NOT NULL,
+  module character varying NOT NULL,
+  pair character varying NOT NULL,
+  kind character varying NOT NULL,
+  account character varying NOT NULL,
+  amount0 numeric NOT NULL,
+  amount1 numeric NOT NULL
+);
+
+ALTER TABLE ONLY liquidity_changes
+    ADD CONSTRAINT liquidity_changes_pkey PRIMARY KEY (block, idx, request_key);
+
+CREATE INDEX liquidity_changes_pair_idx
+  ON liquidity_changes
+  USING btree (pair);
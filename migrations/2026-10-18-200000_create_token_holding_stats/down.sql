@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE token_holding_stats_history;
+DROP TABLE token_holding_stats;
@@ -0,0 +1,38 @@
+-- Holding statistics per token, computed by `indexer holding-stats` from the holding
+-- periods of all accounts. Durations are in days and weighted by amount: half of the
+-- amount sent by holders was held for less than `median_days`. Shares are the part of
+-- the held amount that was acquired more than 30, 90 or 365 days before.
+CREATE TABLE token_holding_stats (
+  module character varying NOT NULL,
+  computed_at timestamp with time zone NOT NULL,
+  holders bigint NOT NULL,
+  held_amount double precision NOT NULL,
+  p25_days double precision,
+  median_days double precision,
+  p75_days double precision,
+  p90_days double precision,
+  held_30d_share double precision NOT NULL,
+  held_90d_share double precision NOT NULL,
+  held_365d_share double precision NOT NULL
+);
+
+ALTER TABLE ONLY token_holding_stats
+    ADD CONSTRAINT token_holding_stats_pkey PRIMARY KEY (module);
+
+-- The same statistics per week: durations of the amounts sent during the week and
+-- shares of the amount held at the end of the week.
+CREATE TABLE token_holding_stats_history (
+  module character varying NOT NULL,
+  period_start timestamp with time zone NOT NULL,
+  held_amount double precision NOT NULL,
+  p25_days double precision,
+  median_days double precision,
+  p75_days double precision,
+  p90_days double precision,
+  held_30d_share double precision NOT NULL,
+  held_90d_share double precision NOT NULL,
+  held_365d_share double precision NOT NULL
+);
+
+ALTER TABLE ONLY token_holding_stats_history
+    ADD CONSTRAINT token_holding_stats_history_pkey PRIMARY KEY (module, period_start);
@@ -0,0 +1,23 @@
+-- Transfers aggregated per sender, receiver and token. Mints, burns and cross-chain legs
+-- have an empty account and transfers to self are left out.
+CREATE TABLE wallet_connections (
+  module character varying NOT NULL,
+  from_account character varying NOT NULL,
+  to_account character varying NOT NULL,
+  total_transfers bigint NOT NULL,
+  total_amount numeric NOT NULL,
+  last_transfer_time timestamp with time zone NOT NULL
+);
+
+ALTER TABLE ONLY wallet_connections
+    ADD CONSTRAINT wallet_connections_pkey PRIMARY KEY (module, from_account, to_account);
+
+CREATE INDEX wallet_connections_to_account_idx
+  ON wallet_connections
+  USING btree (module, to_account);
+
+INSERT INTO wallet_connections
+SELECT module_name, from_account, to_account, count(*), sum(amount), max(creation_time)
+FROM transfers
+WHERE from_account <> '' AND to_account <> '' AND from_account <> to_account
+GROUP BY module_name, from_account, to_account;
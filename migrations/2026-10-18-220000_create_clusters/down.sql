@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE cluster_links;
+DROP TABLE clusters;
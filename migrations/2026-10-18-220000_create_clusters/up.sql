@@ -0,0 +1,28 @@
+-- Accounts grouped into entities, `cluster_id` is the first account of the entity by name.
+-- Only accounts linked to at least one other account are stored.
+CREATE TABLE clusters (
+  account character varying NOT NULL,
+  cluster_id character varying NOT NULL
+);
+
+ALTER TABLE ONLY clusters
+    ADD CONSTRAINT clusters_pkey PRIMARY KEY (account);
+
+CREATE INDEX clusters_cluster_id_idx
+  ON clusters
+  USING btree (cluster_id);
+
+-- Evidence that two accounts are controlled by the same entity
+CREATE TABLE cluster_links (
+  account character varying NOT NULL,
+  linked_account character varying NOT NULL,
+  evidence character varying NOT NULL,
+  detail character varying
+);
+
+ALTER TABLE ONLY cluster_links
+    ADD CONSTRAINT cluster_links_pkey PRIMARY KEY (account, linked_account, evidence);
+
+CREATE INDEX cluster_links_linked_account_idx
+  ON cluster_links
+  USING btree (linked_account);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX transactions_sender_creation_time_idx;
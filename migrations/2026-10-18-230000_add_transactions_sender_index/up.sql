@@ -0,0 +1,3 @@
+CREATE INDEX transactions_sender_creation_time_idx
+  ON transactions
+  USING btree (sender, creation_time DESC);
@@ -0,0 +1,53 @@
+-- Transactions and transferred amounts per scope, chain and minute. `network` rows (with an
+-- empty id) count all transactions and the amount of coin transferred, without gas, rewards
+-- and incoming cross-chain legs. `module` rows count the transactions transferring the
+-- module and its amount. `account` rows count the transactions in which the account sent or
+-- received a transfer and the amount of coin.
+CREATE TABLE activity_rollups (
+  scope character varying NOT NULL,
+  id character varying NOT NULL,
+  chain_id bigint NOT NULL,
+  bucket timestamp with time zone NOT NULL,
+  transaction_count bigint NOT NULL,
+  total_amount numeric NOT NULL
+);
+
+ALTER TABLE ONLY activity_rollups
+    ADD CONSTRAINT activity_rollups_pkey PRIMARY KEY (scope, id, chain_id, bucket);
+
+INSERT INTO activity_rollups
+SELECT 'network', '', chain_id, bucket, sum(transaction_count), sum(total_amount)
+FROM (
+  SELECT chain_id, date_trunc('minute', creation_time) AS bucket,
+         count(*) AS transaction_count, 0 AS total_amount
+  FROM transactions
+  GROUP BY chain_id, date_trunc('minute', creation_time)
+  UNION ALL
+  SELECT transfers.chain_id, date_trunc('minute', transfers.creation_time), 0, sum(amount)
+  FROM transfers JOIN blocks ON blocks.hash = transfers.block
+  WHERE module_name = 'coin'
+    AND to_account <> blocks.miner
+    AND NOT (from_account = '' AND pact_id IS NOT NULL)
+  GROUP BY transfers.chain_id, date_trunc('minute', transfers.creation_time)
+) AS activity
+GROUP BY chain_id, bucket;
+
+INSERT INTO activity_rollups
+SELECT 'module', module_name, chain_id, date_trunc('minute', creation_time),
+       count(DISTINCT request_key), sum(amount)
+FROM transfers
+GROUP BY module_name, chain_id, date_trunc('minute', creation_time);
+
+INSERT INTO activity_rollups
+SELECT 'account', account, chain_id, date_trunc('minute', creation_time),
+       count(DISTINCT request_key), sum(amount)
+FROM (
+  SELECT from_account AS account, chain_id, creation_time, request_key,
+         CASE WHEN module_name = 'coin' THEN amount ELSE 0 END AS amount
+  FROM transfers WHERE from_account <> ''
+  UNION ALL
+  SELECT to_account, chain_id, creation_time, request_key,
+         CASE WHEN module_name = 'coin' THEN amount ELSE 0 END
+  FROM transfers WHERE to_account <> '' AND to_account <> from_account
+) AS activity
+GROUP BY account, chain_id, date_trunc('minute', creation_time);
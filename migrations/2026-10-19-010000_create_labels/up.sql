@@ -0,0 +1,20 @@
+-- Known owners of accounts, eg. `Binance` with the `exchange` category. Every source can
+-- label an account once, `confidence` goes from 0 to 1.
+CREATE TABLE labels (
+  account character varying NOT NULL,
+  label character varying NOT NULL,
+  category character varying NOT NULL,
+  source character varying NOT NULL,
+  confidence double precision NOT NULL
+);
+
+ALTER TABLE ONLY labels
+    ADD CONSTRAINT labels_pkey PRIMARY KEY (account, source);
+
+CREATE INDEX labels_label_idx
+  ON labels
+  USING btree (label);
+
+CREATE INDEX labels_category_idx
+  ON labels
+  USING btree (category);
@@ -0,0 +1,35 @@
+-- First transfer or transaction of every account, on any chain. `first_block` is kept to
+-- look the account up again when that block is removed by a reorg.
+CREATE TABLE accounts (
+  account character varying NOT NULL,
+  first_height bigint NOT NULL,
+  first_chain_id bigint NOT NULL,
+  first_time timestamp with time zone NOT NULL,
+  first_block character varying NOT NULL,
+  first_request_key character varying NOT NULL
+);
+
+ALTER TABLE ONLY accounts
+    ADD CONSTRAINT accounts_pkey PRIMARY KEY (account);
+
+CREATE INDEX accounts_first_time_idx
+  ON accounts
+  USING btree (first_time);
+
+CREATE INDEX accounts_first_block_idx
+  ON accounts
+  USING btree (first_block);
+
+INSERT INTO accounts
+SELECT DISTINCT ON (account) account, height, chain_id, creation_time, block, request_key
+FROM (
+  SELECT from_account AS account, height, chain_id, creation_time, block, request_key
+  FROM transfers WHERE from_account <> ''
+  UNION ALL
+  SELECT to_account, height, chain_id, creation_time, block, request_key
+  FROM transfers WHERE to_account <> ''
+  UNION ALL
+  SELECT sender, height, chain_id, creation_time, block, request_key
+  FROM transactions WHERE sender <> ''
+) AS seen
+ORDER BY account, creation_time, chain_id;
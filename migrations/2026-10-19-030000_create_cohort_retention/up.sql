@@ -0,0 +1,14 @@
+-- Accounts first seen in the day, week or month starting at `cohort_start` that sent or
+-- received a transfer of the module `period` intervals later. Periods without active
+-- accounts aren't stored, except period 0 which records the size of every cohort.
+CREATE TABLE cohort_retention (
+  module character varying NOT NULL,
+  cohort_interval character varying NOT NULL,
+  cohort_start timestamp with time zone NOT NULL,
+  period bigint NOT NULL,
+  cohort_size bigint NOT NULL,
+  active_accounts bigint NOT NULL
+);
+
+ALTER TABLE ONLY cohort_retention
+    ADD CONSTRAINT cohort_retention_pkey PRIMARY KEY (module, cohort_interval, cohort_start, period);
@@ -0,0 +1,4 @@
+-- Position of the transaction in the payload of its block. Events of a block are ordered
+-- by transaction position, then by index within the transaction.
+ALTER TABLE transactions
+ADD COLUMN position bigint;
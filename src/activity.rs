@@ -0,0 +1,260 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::analytics::ActivityPeriod;
+use crate::db::DbError;
+use crate::models::ActivityRollup;
+use crate::repository::ActivityRollupsRepository;
+
+/// Weeks start on Monday, 1970-01-05 is the first Monday after the epoch
+const WEEK_OFFSET_SECS: i64 = 4 * 24 * 3600;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActivityScope {
+    Network,
+    Module(String),
+    Account(String),
+}
+
+impl ActivityScope {
+    pub fn new(scope: &str, id: Option<&str>) -> Option<Self> {
+        match (scope, id) {
+            ("network", None) => Some(ActivityScope::Network),
+            ("module", Some(id)) if !id.is_empty() => Some(ActivityScope::Module(id.to_string())),
+            ("account", Some(id)) if !id.is_empty() => Some(ActivityScope::Account(id.to_string())),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            ActivityScope::Network => "network",
+            ActivityScope::Module(_) => "module",
+            ActivityScope::Account(_) => "account",
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            ActivityScope::Network => "",
+            ActivityScope::Module(id) | ActivityScope::Account(id) => id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum ActivityInterval {
+    Minute,
+    Hour,
+    Day,
+    Week,
+}
+
+impl FromStr for ActivityInterval {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "minute" => Ok(ActivityInterval::Minute),
+            "hour" => Ok(ActivityInterval::Hour),
+            "day" => Ok(ActivityInterval::Day),
+            "week" => Ok(ActivityInterval::Week),
+            _ => Err(format!("Unknown interval: {}", value)),
+        }
+    }
+}
+
+impl ActivityInterval {
+    pub fn duration(&self) -> Duration {
+        match self {
+            ActivityInterval::Minute => Duration::minutes(1),
+            ActivityInterval::Hour => Duration::hours(1),
+            ActivityInterval::Day => Duration::days(1),
+            ActivityInterval::Week => Duration::weeks(1),
+        }
+    }
+
+    fn start(&self, time: NaiveDateTime) -> NaiveDateTime {
+        let timestamp = time.timestamp();
+        let offset = match self {
+            ActivityInterval::Week => WEEK_OFFSET_SECS,
+            _ => 0,
+        };
+        let start = timestamp - (timestamp - offset).rem_euclid(self.duration().num_seconds());
+        NaiveDateTime::from_timestamp_opt(start, 0).unwrap()
+    }
+}
+
+/// Returns the number of transactions and the amount transferred per interval, for intervals
+/// with activity only.
+pub fn activity_series(
+    repository: &ActivityRollupsRepository,
+    scope: &ActivityScope,
+    interval: ActivityInterval,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    chain_id: Option<i64>,
+) -> Result<Vec<ActivityPeriod>, DbError> {
+    let rollups = repository.find_by_range(scope.name(), scope.id(), from, to, chain_id)?;
+    Ok(aggregate_rollups(&rollups, interval))
+}
+
+fn aggregate_rollups(
+    rollups: &[ActivityRollup],
+    interval: ActivityInterval,
+) -> Vec<ActivityPeriod> {
+    let mut grouped: BTreeMap<NaiveDateTime, (i64, BigDecimal)> = BTreeMap::new();
+    for rollup in rollups {
+        let (count, amount) = grouped
+            .entry(interval.start(rollup.bucket))
+            .or_insert_with(|| (0, BigDecimal::zero()));
+        *count += rollup.transaction_count;
+        *amount += &rollup.total_amount;
+    }
+    grouped
+        .into_iter()
+        // Reverted blocks leave empty buckets behind
+        .filter(|(_, (count, amount))| *count != 0 || !amount.is_zero())
+        .map(
+            |(start_time, (transaction_count, total_amount))| ActivityPeriod {
+                start_time,
+                end_time: start_time + interval.duration(),
+                transaction_count,
+                total_amount,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::{Transaction, Transfer};
+    use crate::repository::{BlocksRepository, TransactionsRepository, TransfersRepository};
+    use crate::test_utils::{make_block, make_transaction, make_transfer, time};
+    use serial_test::serial;
+
+    fn make_rollup(bucket: &str, transaction_count: i64, total_amount: i64) -> ActivityRollup {
+        ActivityRollup {
+            bucket: time(bucket),
+            transaction_count,
+            total_amount: BigDecimal::from(total_amount),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_rollups() {
+        let rollups = vec![
+            // Sunday
+            make_rollup("2023-10-01 23:59:00", 2, 10),
+            // Monday
+            make_rollup("2023-10-02 00:00:00", 1, 5),
+            make_rollup("2023-10-03 12:30:00", 3, 0),
+            make_rollup("2023-10-09 08:00:00", 0, 0),
+        ];
+        let weeks = aggregate_rollups(&rollups, ActivityInterval::Week);
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].start_time, time("2023-09-25 00:00:00"));
+        assert_eq!(weeks[0].transaction_count, 2);
+        assert_eq!(weeks[1].start_time, time("2023-10-02 00:00:00"));
+        assert_eq!(weeks[1].end_time, time("2023-10-09 00:00:00"));
+        assert_eq!(weeks[1].transaction_count, 4);
+        assert_eq!(weeks[1].total_amount, BigDecimal::from(5));
+
+        let days = aggregate_rollups(&rollups, ActivityInterval::Day);
+        assert_eq!(days.len(), 3);
+    }
+
+    #[test]
+    fn test_activity_scope() {
+        assert_eq!(
+            ActivityScope::new("network", None),
+            Some(ActivityScope::Network)
+        );
+        assert_eq!(ActivityScope::new("network", Some("coin")), None);
+        assert_eq!(ActivityScope::new("module", None), None);
+        assert_eq!(
+            ActivityScope::new("account", Some("k:abc")),
+            Some(ActivityScope::Account("k:abc".to_string()))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_apply_and_revert_blocks() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transactions_repository = TransactionsRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let repository = ActivityRollupsRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 10, "block-10")])
+            .unwrap();
+        let creation_time = time("2023-10-01 00:10:30");
+        let mut conn = pool.get().unwrap();
+        transactions_repository
+            .insert_batch_with_conn(
+                &[
+                    Transaction {
+                        creation_time,
+                        ..make_transaction("block-10", 10, "request-key-10-0")
+                    },
+                    Transaction {
+                        creation_time,
+                        ..make_transaction("block-10", 10, "request-key-10-2")
+                    },
+                ],
+                &mut conn,
+            )
+            .unwrap();
+        let transfer = |idx: i64, from: &str, to: &str, amount: &str| Transfer {
+            creation_time,
+            ..make_transfer("block-10", 10, idx, from, to, amount)
+        };
+        transfers_repository
+            .insert_batch(&[
+                transfer(0, "alice", "bob", "10.0"),
+                // Gas
+                transfer(1, "alice", "miner", "1.0"),
+                // Incoming leg of a cross-chain transfer
+                Transfer {
+                    pact_id: Some("pact-1".to_string()),
+                    ..transfer(2, "", "bob", "5.0")
+                },
+            ])
+            .unwrap();
+        repository
+            .apply_blocks_with_conn(&["block-10".to_string()], 1, &mut conn)
+            .unwrap();
+
+        let (from, to) = (time("2023-10-01 00:00:00"), time("2023-10-02 00:00:00"));
+        let series = |scope: &ActivityScope| {
+            activity_series(&repository, scope, ActivityInterval::Minute, from, to, None).unwrap()
+        };
+        let network = series(&ActivityScope::Network);
+        assert_eq!(network.len(), 1);
+        assert_eq!(network[0].start_time, time("2023-10-01 00:10:00"));
+        assert_eq!(network[0].transaction_count, 2);
+        assert_eq!(network[0].total_amount, BigDecimal::from(10));
+        let bob = series(&ActivityScope::Account("bob".to_string()));
+        assert_eq!(bob.len(), 1);
+        assert_eq!(bob[0].start_time, time("2023-10-01 00:10:00"));
+        assert_eq!(bob[0].transaction_count, 2);
+        assert_eq!(bob[0].total_amount, BigDecimal::from(15));
+
+        repository
+            .revert_block_with_conn("block-10", &mut conn)
+            .unwrap();
+        assert!(series(&ActivityScope::Network).is_empty());
+        assert!(series(&ActivityScope::Account("bob".to_string())).is_empty());
+
+        repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        transactions_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
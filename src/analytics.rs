@@ -1,13 +1,22 @@
 // analytics.rs
-use chrono::{Duration, NaiveDateTime};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use bigdecimal::BigDecimal;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use crate::db::DbError;
 use crate::models::*;
+use crate::repository::{AnalyticsRepository, WalletConnectionsRepository};
+
+/// Maximum number of accounts in a wallet graph
+pub const MAX_GRAPH_NODES: usize = 500;
+
+/// Default maximum number of connections in a wallet network
+pub const MAX_NETWORK_EDGES: i64 = 10000;
 
 /// Represents a period during which a token was held
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct HoldingPeriod {
     pub address: String,
     pub token_id: String,
@@ -18,8 +27,13 @@ pub struct HoldingPeriod {
 }
 
 /// Represents a connection between two wallets
+#[derive(Queryable, QueryableByName, Selectable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::wallet_connections)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct WalletConnection {
+    #[diesel(column_name = from_account)]
     pub from_address: String,
+    #[diesel(column_name = to_account)]
     pub to_address: String,
     pub total_transfers: i64,
     pub total_amount: BigDecimal,
@@ -27,9 +41,364 @@ pub struct WalletConnection {
 }
 
 /// Represents transaction activity in a time period
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ActivityPeriod {
     pub start_time: NaiveDateTime,
     pub end_time: NaiveDateTime,
     pub transaction_count: i64,
     pub total_amount: BigDecimal,
-}
\ No newline at end of file
+}
+
+/// Selects the connections of a wallet graph. Connections are read from the
+/// `wallet_connections` table, or aggregated from the transfers made between `from` and `to`
+/// when any of them is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphFilter {
+    pub module: String,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub min_amount: Option<BigDecimal>,
+}
+
+/// An account of a wallet graph, `depth` is its distance to the account the graph is built
+/// for, if any
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphNode {
+    pub account: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WalletGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<WalletConnection>,
+}
+
+/// Returns the accounts connected to the account by transfers of the module, up to `depth`
+/// transfers away, with the connections between them.
+pub fn wallet_graph(
+    repository: &WalletConnectionsRepository,
+    account: &str,
+    depth: u32,
+    filter: &GraphFilter,
+) -> Result<WalletGraph, DbError> {
+    expand_graph(account, depth, MAX_GRAPH_NODES, |accounts| {
+        repository.find_connections(Some(accounts), filter, None)
+    })
+}
+
+/// Returns the `limit` largest connections of the module with the accounts they connect,
+/// ordered by name.
+pub fn wallet_network(
+    repository: &WalletConnectionsRepository,
+    filter: &GraphFilter,
+    limit: i64,
+) -> Result<WalletGraph, DbError> {
+    let edges = repository.find_connections(None, filter, Some(limit))?;
+    let nodes = edges
+        .iter()
+        .flat_map(|edge| [edge.from_address.as_str(), edge.to_address.as_str()])
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .map(|account| GraphNode {
+            account: account.to_string(),
+            depth: None,
+        })
+        .collect();
+    Ok(WalletGraph { nodes, edges })
+}
+
+/// Breadth-first expansion from the account: `connections` returns the connections of the
+/// accounts of the current level, largest first, and their counterparts make up the next
+/// level. Once `max_nodes` accounts are reached no account is added, so the largest
+/// connections are kept. Connections between accounts of the last level aren't loaded.
+pub fn expand_graph<F>(
+    account: &str,
+    depth: u32,
+    max_nodes: usize,
+    mut connections: F,
+) -> Result<WalletGraph, DbError>
+where
+    F: FnMut(&[String]) -> Result<Vec<WalletConnection>, DbError>,
+{
+    let mut nodes = vec![GraphNode {
+        account: account.to_string(),
+        depth: Some(0),
+    }];
+    let mut depths = HashMap::from([(account.to_string(), 0)]);
+    let mut edges: HashMap<(String, String), WalletConnection> = HashMap::new();
+    let mut level = vec![account.to_string()];
+    for current in 0..depth {
+        if level.is_empty() {
+            break;
+        }
+        let mut next = vec![];
+        for connection in connections(&level)? {
+            for counterpart in [&connection.from_address, &connection.to_address] {
+                if !depths.contains_key(counterpart) && nodes.len() < max_nodes {
+                    depths.insert(counterpart.clone(), current + 1);
+                    nodes.push(GraphNode {
+                        account: counterpart.clone(),
+                        depth: Some(current + 1),
+                    });
+                    next.push(counterpart.clone());
+                }
+            }
+            if depths.contains_key(&connection.from_address)
+                && depths.contains_key(&connection.to_address)
+            {
+                let key = (
+                    connection.from_address.clone(),
+                    connection.to_address.clone(),
+                );
+                edges.entry(key).or_insert(connection);
+            }
+        }
+        level = next;
+    }
+    let mut edges = edges.into_values().collect::<Vec<WalletConnection>>();
+    edges.sort_by(|a, b| (&a.from_address, &a.to_address).cmp(&(&b.from_address, &b.to_address)));
+    Ok(WalletGraph { nodes, edges })
+}
+
+/// Returns the holding periods of the account for the module, oldest first.
+pub fn holding_periods(
+    repository: &AnalyticsRepository,
+    account: &str,
+    module: &str,
+) -> Result<Vec<HoldingPeriod>, DbError> {
+    let transfers = repository.find_account_transfers(account, module)?;
+    Ok(replay_holding_periods(account, module, &transfers))
+}
+
+/// Replays the transfers of the account, on all chains and ordered by time, with FIFO lots:
+/// every received amount opens a lot and every sent amount is taken from the oldest open
+/// lots. A lot is disposed of when nothing is left of it. Cross-chain transfers from the
+/// account to itself only move tokens between chains, so they are skipped.
+pub fn replay_holding_periods(
+    account: &str,
+    module: &str,
+    transfers: &[Transfer],
+) -> Vec<HoldingPeriod> {
+    replay_lots(account, module, transfers, |_, _, _| {})
+}
+
+/// Same as `replay_holding_periods`, `on_disposal` is called with the acquisition time, the
+/// disposal time and the amount every time an amount is taken from a lot.
+pub fn replay_lots<F>(
+    account: &str,
+    module: &str,
+    transfers: &[Transfer],
+    mut on_disposal: F,
+) -> Vec<HoldingPeriod>
+where
+    F: FnMut(NaiveDateTime, NaiveDateTime, &BigDecimal),
+{
+    let crosschain_out = transfers
+        .iter()
+        .filter(|transfer| transfer.from_account == account && transfer.to_account.is_empty())
+        .filter_map(|transfer| transfer.pact_id.as_deref())
+        .collect::<HashSet<&str>>();
+    let crosschain_self = transfers
+        .iter()
+        .filter(|transfer| transfer.from_account.is_empty() && transfer.to_account == account)
+        .filter_map(|transfer| transfer.pact_id.as_deref())
+        .filter(|pact_id| crosschain_out.contains(pact_id))
+        .collect::<HashSet<&str>>();
+
+    let mut lots: Vec<HoldingPeriod> = vec![];
+    // Lots before this index are fully disposed of
+    let mut first_open = 0;
+    for transfer in transfers.iter() {
+        if transfer.amount <= BigDecimal::zero()
+            || transfer.from_account == transfer.to_account
+            || transfer
+                .pact_id
+                .as_deref()
+                .is_some_and(|pact_id| crosschain_self.contains(pact_id))
+        {
+            continue;
+        }
+        if transfer.to_account == account {
+            lots.push(HoldingPeriod {
+                address: account.to_string(),
+                token_id: module.to_string(),
+                acquisition_time: transfer.creation_time,
+                disposal_time: None,
+                acquisition_amount: transfer.amount.clone(),
+                current_amount: transfer.amount.clone(),
+            });
+        } else if transfer.from_account == account {
+            let mut remaining = transfer.amount.clone();
+            while remaining > BigDecimal::zero() && first_open < lots.len() {
+                let lot = &mut lots[first_open];
+                if lot.current_amount > remaining {
+                    on_disposal(lot.acquisition_time, transfer.creation_time, &remaining);
+                    lot.current_amount -= &remaining;
+                    remaining = BigDecimal::zero();
+                } else {
+                    on_disposal(
+                        lot.acquisition_time,
+                        transfer.creation_time,
+                        &lot.current_amount,
+                    );
+                    remaining -= &lot.current_amount;
+                    lot.current_amount = BigDecimal::zero();
+                    lot.disposal_time = Some(transfer.creation_time);
+                    first_open += 1;
+                }
+            }
+            if remaining > BigDecimal::zero() {
+                log::debug!(
+                    "{} sent {} more {} than received in {}",
+                    account,
+                    remaining,
+                    module,
+                    transfer.request_key
+                );
+            }
+        }
+    }
+    lots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repository::{BlocksRepository, TransactionsRepository, TransfersRepository};
+    use crate::test_utils::{self, time};
+    use serial_test::serial;
+
+    fn make_transfer(
+        from: &str,
+        to: &str,
+        amount: i64,
+        creation_time: &str,
+        pact_id: Option<&str>,
+    ) -> Transfer {
+        Transfer {
+            creation_time: time(creation_time),
+            pact_id: pact_id.map(|e| e.to_string()),
+            ..test_utils::make_transfer("block", 0, 0, from, to, &amount.to_string())
+        }
+    }
+
+    #[test]
+    fn test_replay_holding_periods() {
+        let transfers = vec![
+            make_transfer("bob", "alice", 10, "2023-01-01 00:00:00", None),
+            make_transfer("bob", "alice", 5, "2023-02-01 00:00:00", None),
+            // Moving tokens to another chain isn't a disposal
+            make_transfer("alice", "", 15, "2023-02-15 00:00:00", Some("pact-1")),
+            make_transfer("", "alice", 15, "2023-02-15 00:01:00", Some("pact-1")),
+            make_transfer("alice", "carol", 12, "2023-03-01 00:00:00", None),
+            // Sending to another account through a cross-chain transfer is
+            make_transfer("alice", "", 1, "2023-04-01 00:00:00", Some("pact-2")),
+        ];
+        let periods = replay_holding_periods("alice", "coin", &transfers);
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods[0].acquisition_time, time("2023-01-01 00:00:00"));
+        assert_eq!(periods[0].acquisition_amount, BigDecimal::from(10));
+        assert_eq!(periods[0].current_amount, BigDecimal::from(0));
+        assert_eq!(periods[0].disposal_time, Some(time("2023-03-01 00:00:00")));
+        assert_eq!(periods[1].acquisition_amount, BigDecimal::from(5));
+        assert_eq!(periods[1].current_amount, BigDecimal::from(2));
+        assert_eq!(periods[1].disposal_time, None);
+    }
+
+    #[test]
+    #[serial]
+    fn test_holding_periods_follow_tx_position() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transactions_repository = TransactionsRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let analytics_repository = AnalyticsRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[test_utils::make_block(0, 10, "block-10")])
+            .unwrap();
+        // Alice receives in the first transaction of the block and sends in the second one,
+        // the request keys and event indexes sort the other way around
+        let transaction = |request_key: &str, position: i64| Transaction {
+            position: Some(position),
+            ..test_utils::make_transaction("block-10", 10, request_key)
+        };
+        let mut conn = pool.get().unwrap();
+        transactions_repository
+            .insert_batch_with_conn(
+                &[
+                    transaction("request-key-b", 0),
+                    transaction("request-key-a", 1),
+                ],
+                &mut conn,
+            )
+            .unwrap();
+        let transfer = |request_key: &str, idx: i64, from: &str, to: &str, amount: &str| Transfer {
+            creation_time: time("2023-01-01 00:00:00"),
+            request_key: request_key.to_string(),
+            ..test_utils::make_transfer("block-10", 10, idx, from, to, amount)
+        };
+        transfers_repository
+            .insert_batch(&[
+                transfer("request-key-b", 1, "bob", "alice", "10.0"),
+                transfer("request-key-a", 0, "alice", "carol", "4.0"),
+            ])
+            .unwrap();
+
+        let periods = holding_periods(&analytics_repository, "alice", "coin").unwrap();
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].acquisition_amount, BigDecimal::from(10));
+        assert_eq!(periods[0].current_amount, BigDecimal::from(6));
+
+        transfers_repository.delete_all().unwrap();
+        transactions_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+
+    fn make_connection(from: &str, to: &str, amount: i64) -> WalletConnection {
+        WalletConnection {
+            from_address: from.to_string(),
+            to_address: to.to_string(),
+            total_transfers: 1,
+            total_amount: BigDecimal::from(amount),
+            last_transfer_time: time("2023-01-01 00:00:00"),
+        }
+    }
+
+    #[test]
+    fn test_expand_graph() {
+        let connections = [
+            make_connection("alice", "bob", 100),
+            make_connection("carol", "alice", 50),
+            make_connection("bob", "dave", 20),
+            make_connection("dave", "erin", 10),
+        ];
+        let find = |accounts: &[String]| -> Result<Vec<WalletConnection>, DbError> {
+            Ok(connections
+                .iter()
+                .filter(|c| accounts.contains(&c.from_address) || accounts.contains(&c.to_address))
+                .cloned()
+                .collect())
+        };
+
+        let graph = expand_graph("alice", 2, 10, find).unwrap();
+        let nodes = graph
+            .nodes
+            .iter()
+            .map(|node| (node.account.as_str(), node.depth.unwrap()))
+            .collect::<Vec<(&str, u32)>>();
+        assert_eq!(
+            nodes,
+            vec![("alice", 0), ("bob", 1), ("carol", 1), ("dave", 2)]
+        );
+        assert_eq!(graph.edges.len(), 3);
+
+        // The largest connections are kept when the graph is full
+        let graph = expand_graph("alice", 2, 2, find).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges, vec![make_connection("alice", "bob", 100)]);
+    }
+}
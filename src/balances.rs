@@ -0,0 +1,182 @@
+use crate::db::DbError;
+use crate::repository::BalancesRepository;
+
+/// Recomputes the balances table from all indexed transfers.
+pub fn rebuild_balances(repository: &BalancesRepository) -> Result<usize, DbError> {
+    let before = std::time::Instant::now();
+    let inserted = repository.rebuild()?;
+    log::info!(
+        "Rebuilt {} balances in {} ms",
+        inserted,
+        before.elapsed().as_millis()
+    );
+    Ok(inserted)
+}
+
+/// Compares the stored balances with the ones computed from the transfers table and
+/// logs every difference. Returns the number of mismatches.
+pub fn verify_balances(repository: &BalancesRepository) -> Result<usize, DbError> {
+    let mismatches = repository.find_mismatches()?;
+    for mismatch in mismatches.iter() {
+        log::warn!(
+            "Balance mismatch for {} ({}) on chain {}: stored {:?}, computed {:?}",
+            mismatch.account,
+            mismatch.module,
+            mismatch.chain_id,
+            mismatch.stored,
+            mismatch.computed
+        );
+    }
+    log::info!("Found {} balance mismatches", mismatches.len());
+    Ok(mismatches.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::Transfer;
+    use crate::repository::{BlocksRepository, TransfersRepository};
+    use crate::test_utils::{make_block, make_transfer, time};
+    use bigdecimal::BigDecimal;
+    use serial_test::serial;
+    use std::str::FromStr;
+
+    #[test]
+    #[serial]
+    fn test_apply_and_revert_blocks() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let balances_repository = BalancesRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 0, "block-0"), make_block(0, 1, "block-1")])
+            .unwrap();
+        transfers_repository
+            .insert_batch(&[
+                make_transfer("block-0", 0, 0, "", "alice", "100.5"),
+                make_transfer("block-1", 1, 0, "alice", "bob", "10.25"),
+            ])
+            .unwrap();
+        let mut conn = pool.get().unwrap();
+        balances_repository
+            .apply_blocks_with_conn(
+                &["block-0".to_string(), "block-1".to_string()],
+                1,
+                &mut conn,
+            )
+            .unwrap();
+        assert_eq!(
+            balances_repository
+                .find_by_account_module("alice", "coin")
+                .unwrap()[&0],
+            BigDecimal::from_str("90.25").unwrap()
+        );
+        assert_eq!(
+            balances_repository
+                .find_by_account_module("bob", "coin")
+                .unwrap()[&0],
+            BigDecimal::from_str("10.25").unwrap()
+        );
+        assert!(balances_repository.find_mismatches().unwrap().is_empty());
+
+        balances_repository
+            .revert_block_with_conn("block-1", &mut conn)
+            .unwrap();
+        assert_eq!(
+            balances_repository
+                .find_by_account_module("alice", "coin")
+                .unwrap()[&0],
+            BigDecimal::from_str("100.5").unwrap()
+        );
+        assert_eq!(
+            balances_repository
+                .find_by_account_module("bob", "coin")
+                .unwrap()[&0],
+            BigDecimal::from(0)
+        );
+
+        balances_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_find_historical_and_history() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let balances_repository = BalancesRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[
+                make_block(0, 0, "block-0"),
+                make_block(0, 1, "block-1"),
+                make_block(1, 0, "block-2"),
+            ])
+            .unwrap();
+        transfers_repository
+            .insert_batch(&[
+                Transfer {
+                    creation_time: time("2023-01-01 00:00:00"),
+                    ..make_transfer("block-0", 0, 0, "", "alice", "100")
+                },
+                Transfer {
+                    creation_time: time("2023-01-02 00:00:00"),
+                    ..make_transfer("block-1", 1, 0, "alice", "bob", "30")
+                },
+                Transfer {
+                    creation_time: time("2023-01-02 00:00:00"),
+                    ..make_transfer("block-1", 1, 1, "bob", "alice", "5")
+                },
+                Transfer {
+                    chain_id: 1,
+                    creation_time: time("2023-01-03 00:00:00"),
+                    ..make_transfer("block-2", 0, 0, "", "alice", "1.5")
+                },
+            ])
+            .unwrap();
+
+        let balances = balances_repository
+            .find_historical("alice", "coin", None, None)
+            .unwrap();
+        assert_eq!(balances[&0], BigDecimal::from(75));
+        assert_eq!(balances[&1], BigDecimal::from_str("1.5").unwrap());
+        let balances = balances_repository
+            .find_historical("alice", "coin", Some(0), None)
+            .unwrap();
+        assert_eq!(balances[&0], BigDecimal::from(100));
+        assert_eq!(balances[&1], BigDecimal::from_str("1.5").unwrap());
+        let balances = balances_repository
+            .find_historical("alice", "coin", None, Some(time("2023-01-02 12:00:00")))
+            .unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[&0], BigDecimal::from(75));
+
+        let history = balances_repository
+            .find_history("alice", None, Some(0))
+            .unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|change| (change.height, change.idx, change.balance.clone()))
+                .collect::<Vec<(i64, i64, BigDecimal)>>(),
+            vec![
+                (0, 0, BigDecimal::from(100)),
+                (1, 0, BigDecimal::from(70)),
+                (1, 1, BigDecimal::from(75)),
+            ]
+        );
+        let history = balances_repository
+            .find_history("alice", Some("coin".to_string()), None)
+            .unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[3].chain_id, 1);
+        assert_eq!(history[3].balance, BigDecimal::from_str("1.5").unwrap());
+
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+use chrono::{NaiveDateTime, Timelike};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::db::DbError;
+use crate::repository::AnalyticsRepository;
+
+/// Number of latest transactions and transfers the features are computed from
+const MAX_TRANSACTIONS: i64 = 1000;
+/// Accounts with fewer transactions are labelled `unknown`
+const MIN_TRANSACTIONS: usize = 20;
+const BOT_SCORE: f64 = 0.65;
+const HUMAN_SCORE: f64 = 0.35;
+/// Transactions per day at which the frequency signal is maxed out
+const BOT_TX_PER_DAY: f64 = 50.0;
+
+/// Weight of each feature in the score
+const WEIGHTS: [(&str, f64); 6] = [
+    ("tx_per_day", 0.25),
+    ("interarrival_cv", 0.2),
+    ("hour_entropy", 0.2),
+    ("code_repetition", 0.15),
+    ("gas_price_uniformity", 0.1),
+    ("counterparty_diversity", 0.1),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BehaviourFeatures {
+    pub transactions: usize,
+    /// Transactions per day between the first and the last analysed transaction
+    pub tx_per_day: Option<f64>,
+    /// Coefficient of variation of the time between transactions, close to 0 when they are
+    /// sent at regular intervals
+    pub interarrival_cv: Option<f64>,
+    /// Entropy of the hour of day of transactions, from 0 (always the same hour) to 1
+    /// (evenly spread over the day)
+    pub hour_entropy: Option<f64>,
+    /// Share of transactions using the most common gas price
+    pub gas_price_uniformity: Option<f64>,
+    /// Share of transactions running the most common code, once literals are removed
+    pub code_repetition: Option<f64>,
+    /// Distinct receivers per transfer sent
+    pub counterparty_diversity: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountBehaviour {
+    pub account: String,
+    /// `likely_bot`, `likely_human` or `unknown`
+    pub label: String,
+    /// Weighted average of the signals, from 0 (human-like) to 1 (bot-like)
+    pub score: Option<f64>,
+    pub features: BehaviourFeatures,
+    /// Bot-likeness of every available feature, from 0 to 1, with its weight
+    pub signals: BTreeMap<String, (f64, f64)>,
+}
+
+/// Computes the behaviour features of the account from its latest transactions and
+/// transfers, and scores them.
+pub fn account_behaviour(
+    repository: &AnalyticsRepository,
+    account: &str,
+) -> Result<AccountBehaviour, DbError> {
+    let transactions = repository.find_sender_transactions(account, MAX_TRANSACTIONS)?;
+    let receivers = repository.find_sent_transfer_receivers(account, MAX_TRANSACTIONS)?;
+    let features = extract_features(&transactions, &receivers);
+    Ok(score(account, features))
+}
+
+pub fn extract_features(
+    transactions: &[(NaiveDateTime, f64, Option<String>)],
+    receivers: &[String],
+) -> BehaviourFeatures {
+    let mut times = transactions
+        .iter()
+        .map(|(time, _, _)| *time)
+        .collect::<Vec<NaiveDateTime>>();
+    times.sort();
+    let gaps = times
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_milliseconds() as f64 / 1000.0)
+        .collect::<Vec<f64>>();
+    let tx_per_day = match (times.first(), times.last()) {
+        (Some(first), Some(last)) if times.len() > 1 => {
+            let days = ((*last - *first).num_seconds() as f64 / 86400.0).max(1.0 / 24.0);
+            Some(times.len() as f64 / days)
+        }
+        _ => None,
+    };
+    let interarrival_cv = match gaps.len() {
+        0 | 1 => None,
+        _ => {
+            let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+            let variance =
+                gaps.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+            Some(match mean > 0.0 {
+                true => variance.sqrt() / mean,
+                false => 0.0,
+            })
+        }
+    };
+    let hour_entropy = match times.is_empty() {
+        true => None,
+        false => {
+            let mut hours = [0usize; 24];
+            for time in times.iter() {
+                hours[time.hour() as usize] += 1;
+            }
+            let total = times.len() as f64;
+            let entropy = hours
+                .iter()
+                .filter(|count| **count > 0)
+                .map(|count| {
+                    let p = *count as f64 / total;
+                    -p * p.log2()
+                })
+                .sum::<f64>();
+            Some(entropy / 24f64.log2())
+        }
+    };
+    let gas_price_uniformity = most_common_share(
+        transactions
+            .iter()
+            .map(|(_, gas_price, _)| gas_price.to_bits()),
+    );
+    let code_repetition = most_common_share(
+        transactions
+            .iter()
+            .filter_map(|(_, _, code)| code.as_deref().map(code_template)),
+    );
+    let counterparty_diversity = match receivers.is_empty() {
+        true => None,
+        false => {
+            let distinct = receivers.iter().collect::<HashSet<&String>>().len();
+            Some(distinct as f64 / receivers.len() as f64)
+        }
+    };
+    BehaviourFeatures {
+        transactions: transactions.len(),
+        tx_per_day,
+        interarrival_cv,
+        hour_entropy,
+        gas_price_uniformity,
+        code_repetition,
+        counterparty_diversity,
+    }
+}
+
+fn most_common_share<T, I>(values: I) -> Option<f64>
+where
+    T: std::hash::Hash + Eq,
+    I: Iterator<Item = T>,
+{
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    let mut total = 0;
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+        total += 1;
+    }
+    counts.values().max().map(|max| *max as f64 / total as f64)
+}
+
+/// Replaces string and number literals of Pact code with `?` and collapses whitespace, so
+/// transactions calling the same functions with different arguments share a template.
+pub fn code_template(code: &str) -> String {
+    let mut template = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => break,
+                    _ => {}
+                }
+            }
+            template.push('?');
+        } else if c.is_ascii_digit()
+            && !template.ends_with(|p: char| p.is_alphanumeric() || p == '_' || p == '-')
+        {
+            while chars
+                .peek()
+                .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+            {
+                chars.next();
+            }
+            template.push('?');
+        } else if c.is_whitespace() {
+            if !template.ends_with(' ') && !template.is_empty() {
+                template.push(' ');
+            }
+        } else {
+            template.push(c);
+        }
+    }
+    template.trim_end().to_string()
+}
+
+/// Maps every available feature to a bot-likeness signal and averages them with their
+/// weights. The label is `unknown` below `MIN_TRANSACTIONS` or between the thresholds.
+pub fn score(account: &str, features: BehaviourFeatures) -> AccountBehaviour {
+    let signals = [
+        features
+            .tx_per_day
+            .map(|value| (value / BOT_TX_PER_DAY).min(1.0)),
+        features.interarrival_cv.map(|value| 1.0 - value.min(1.0)),
+        // Humans sleep, so their transactions rarely spread evenly over the day
+        features
+            .hour_entropy
+            .map(|value| ((value - 0.8) / 0.2).clamp(0.0, 1.0)),
+        features.code_repetition,
+        features.gas_price_uniformity,
+        features.counterparty_diversity.map(|value| 1.0 - value),
+    ];
+    let signals = WEIGHTS
+        .iter()
+        .zip(signals.iter())
+        .filter_map(|((name, weight), signal)| {
+            signal.map(|signal| (name.to_string(), (signal, *weight)))
+        })
+        .collect::<BTreeMap<String, (f64, f64)>>();
+    let total_weight = signals.values().map(|(_, weight)| weight).sum::<f64>();
+    let score = match total_weight > 0.0 {
+        true => Some(
+            signals
+                .values()
+                .map(|(signal, weight)| signal * weight)
+                .sum::<f64>()
+                / total_weight,
+        ),
+        false => None,
+    };
+    let label = match score {
+        _ if features.transactions < MIN_TRANSACTIONS => "unknown",
+        Some(score) if score >= BOT_SCORE => "likely_bot",
+        Some(score) if score <= HUMAN_SCORE => "likely_human",
+        _ => "unknown",
+    };
+    AccountBehaviour {
+        account: account.to_string(),
+        label: label.to_string(),
+        score,
+        features,
+        signals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::time;
+    use chrono::Duration;
+
+    #[test]
+    fn test_code_template() {
+        assert_eq!(
+            code_template("(coin.transfer \"k:abc\" \"k:def\" 1.5)"),
+            "(coin.transfer ? ? ?)"
+        );
+        assert_eq!(
+            code_template("(free.dex-v2.swap  \"a \\\" b\"\n  20)"),
+            "(free.dex-v2.swap ? ?)"
+        );
+    }
+
+    #[test]
+    fn test_bot_and_human_scores() {
+        // Same call every 10 minutes, around the clock
+        let start = time("2023-10-01 00:00:00");
+        let bot = (0..500)
+            .map(|i| {
+                (
+                    start + Duration::minutes(10 * i),
+                    0.00000001,
+                    Some(format!("(free.arb.run {})", i)),
+                )
+            })
+            .collect::<Vec<(NaiveDateTime, f64, Option<String>)>>();
+        let receivers = vec!["free.arb-pool".to_string(); 100];
+        let behaviour = score("bot", extract_features(&bot, &receivers));
+        assert_eq!(behaviour.label, "likely_bot");
+        assert_eq!(behaviour.features.code_repetition, Some(1.0));
+
+        // Bursts of transfers on some days, during the day
+        let human = (0..30)
+            .map(|i| {
+                (
+                    start
+                        + Duration::days((i * i) % 45)
+                        + Duration::hours(8 + (i * 5) % 14)
+                        + Duration::minutes((i * 17) % 60),
+                    0.00000001 * (1 + i % 3) as f64,
+                    Some(format!(
+                        "(coin.transfer \"me\" \"friend-{}\" {})",
+                        i % 10,
+                        i
+                    )),
+                )
+            })
+            .collect::<Vec<(NaiveDateTime, f64, Option<String>)>>();
+        let receivers = (0..30)
+            .map(|i| format!("friend-{}", i % 10))
+            .collect::<Vec<String>>();
+        let behaviour = score("human", extract_features(&human, &receivers));
+        assert_eq!(behaviour.label, "likely_human");
+
+        let behaviour = score("new", extract_features(&human[..5], &receivers));
+        assert_eq!(behaviour.label, "unknown");
+    }
+}
@@ -1,8 +1,19 @@
 use actix_web::{error, get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use bento::activity::{self, ActivityInterval, ActivityScope};
+use bento::analytics;
+use bento::behaviour;
+use bento::cohorts::{self, CohortInterval, CohortMatrix};
 use bento::db;
+use bento::dex;
+use bento::flows;
+use bento::graph_export;
+use bento::labels::{self, Labelled, LabelledAccount, LabelledTransaction, LabelledTransfer};
 use bento::models::*;
+use bento::network;
 use bento::repository::*;
+use bento::supply;
 use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
 use dotenvy::dotenv;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -18,13 +29,16 @@ struct RequestKeys {
 async fn tx(
     path: web::Path<String>,
     transactions: web::Data<TransactionsRepository>,
+    labels_repository: web::Data<LabelsRepository>,
 ) -> actix_web::Result<impl Responder> {
     let request_key = path.into_inner();
     let req_key = request_key.clone();
-    let tx: HashMap<String, Vec<Transaction>> =
-        web::block(move || transactions.find_all_related(&vec![request_key]))
-            .await?
-            .map_err(error::ErrorInternalServerError)?;
+    let tx: HashMap<String, Vec<LabelledTransaction>> = web::block(move || {
+        let related = transactions.find_all_related(&vec![request_key])?;
+        labels::label_transactions(&labels_repository, related)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
     Ok(match tx.contains_key(&req_key) {
         false => HttpResponse::NotFound().body("Tx not found"),
         true => HttpResponse::Ok().json(tx.get(&req_key).unwrap()),
@@ -35,37 +49,90 @@ async fn tx(
 async fn txs(
     body: web::Json<RequestKeys>,
     transactions: web::Data<TransactionsRepository>,
+    labels_repository: web::Data<LabelsRepository>,
 ) -> actix_web::Result<impl Responder> {
-    let result: HashMap<String, Vec<Transaction>> =
-        web::block(move || transactions.find_all_related(&body.request_keys))
-            .await?
-            .map_err(error::ErrorInternalServerError)?;
+    let result: HashMap<String, Vec<LabelledTransaction>> = web::block(move || {
+        let related = transactions.find_all_related(&body.request_keys)?;
+        labels::label_transactions(&labels_repository, related)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
     Ok(HttpResponse::Ok().json(result))
 }
 
 #[get("/balance/{account}")]
 async fn all_balances(
     path: web::Path<String>,
-    transfers: web::Data<TransfersRepository>,
+    balances: web::Data<BalancesRepository>,
 ) -> actix_web::Result<impl Responder> {
     let account = path.into_inner();
     let all: HashMap<String, HashMap<i64, BigDecimal>> =
-        web::block(move || transfers.calculate_all_balances(&account))
+        web::block(move || balances.find_by_account(&account))
             .await?
             .map_err(error::ErrorInternalServerError)?;
     Ok(HttpResponse::Ok().json(all))
 }
 
+#[get("/balance/{account}/history")]
+async fn balance_history(
+    path: web::Path<String>,
+    request: HttpRequest,
+    balances: web::Data<BalancesRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let module = params.get("module").cloned();
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let history = web::block(move || -> Result<Vec<LabelledAccount<_>>, db::DbError> {
+        let history = balances.find_history(&account, module, chain)?;
+        let account_labels = labels::find_labels(&labels_repository, [&account])?
+            .remove(&account)
+            .unwrap_or_default();
+        Ok(history
+            .into_iter()
+            .map(|change| LabelledAccount {
+                value: change,
+                account_labels: account_labels.clone(),
+            })
+            .collect())
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(history))
+}
+
 #[get("/balance/{account}/{module}")]
 async fn balance(
     path: web::Path<(String, String)>,
-    transfers: web::Data<TransfersRepository>,
+    request: HttpRequest,
+    balances: web::Data<BalancesRepository>,
 ) -> actix_web::Result<impl Responder> {
     let (account, module) = path.into_inner();
-    let balance: HashMap<i64, BigDecimal> =
-        web::block(move || transfers.calculate_balance(&account, &module))
-            .await?
-            .map_err(error::ErrorInternalServerError)?;
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let at_height = match params.get("at_height").map(|e| e.parse::<i64>()) {
+        Some(Ok(height)) => Some(height),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid at_height")),
+        None => None,
+    };
+    let at_time = match params.get("at_time").map(|e| parse_time(e)) {
+        Some(Some(time)) => Some(time),
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid at_time")),
+        None => None,
+    };
+    let balance: HashMap<i64, BigDecimal> = web::block(move || {
+        if at_height.is_some() || at_time.is_some() {
+            balances.find_historical(&account, &module, at_height, at_time)
+        } else {
+            balances.find_by_account_module(&account, &module)
+        }
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
     Ok(HttpResponse::Ok().json(balance))
 }
 
@@ -75,15 +142,32 @@ async fn received_transfers(
     path: web::Path<String>,
     request: HttpRequest,
     transfers: web::Data<TransfersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
 ) -> actix_web::Result<impl Responder> {
     let account = path.into_inner();
     let params = web::Query::<HashMap<String, i64>>::from_query(request.query_string()).unwrap();
     let min_height = params.get("min_height").copied();
     let before = Instant::now();
-    let transfers: HashMap<String, Vec<Transfer>> =
-        web::block(move || transfers.find_received(&account, min_height))
-            .await?
-            .map_err(error::ErrorInternalServerError)?;
+    let transfers = web::block(
+        move || -> Result<HashMap<String, Vec<LabelledTransfer>>, db::DbError> {
+            let received: HashMap<String, Vec<Transfer>> =
+                transfers.find_received(&account, min_height)?;
+            let (keys, grouped): (Vec<String>, Vec<Vec<Transfer>>) = received.into_iter().unzip();
+            let sizes = grouped.iter().map(Vec::len).collect::<Vec<usize>>();
+            let mut labelled = labels::label_transfers(
+                &labels_repository,
+                grouped.into_iter().flatten().collect(),
+            )?
+            .into_iter();
+            Ok(keys
+                .into_iter()
+                .zip(sizes)
+                .map(|(key, size)| (key, labelled.by_ref().take(size).collect()))
+                .collect())
+        },
+    )
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
     log::info!("Received transfers took {:?}", before.elapsed().as_millis());
     Ok(HttpResponse::Ok().json(transfers))
 }
@@ -92,6 +176,7 @@ async fn received_transfers(
 async fn get_transfers(
     request: HttpRequest,
     transfers: web::Data<TransfersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
 ) -> actix_web::Result<impl Responder> {
     let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
     let from = params.get("from").map(|e| e.to_string());
@@ -101,12 +186,766 @@ async fn get_transfers(
         Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid min_height")),
         None => None,
     };
-    let transfers = web::block(move || transfers.find(from, to, min_height))
+    let transfers = web::block(move || {
+        let found = transfers.find(from, to, min_height)?;
+        labels::label_transfers(&labels_repository, found)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(transfers))
+}
+
+#[get("/labels")]
+async fn get_labels(
+    request: HttpRequest,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let label = params.get("label").cloned();
+    let category = params.get("category").cloned();
+    let source = params.get("source").cloned();
+    let limit = match params.get("limit").map(|l| l.parse::<i64>()) {
+        Some(Ok(limit)) if (1..=1000).contains(&limit) => limit,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid limit")),
+        None => 100,
+    };
+    let offset = match params.get("offset").map(|o| o.parse::<i64>()) {
+        Some(Ok(offset)) if offset >= 0 => offset,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid offset")),
+        None => 0,
+    };
+    let found = web::block(move || labels_repository.find(label, category, source, limit, offset))
         .await?
         .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(found))
+}
+
+#[get("/labels/{account}")]
+async fn get_account_labels(
+    path: web::Path<String>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let found = web::block(move || -> Result<Vec<Label>, db::DbError> {
+        let mut found = labels_repository.find_by_accounts(&[account.clone()])?;
+        Ok(found.remove(&account).unwrap_or_default())
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(found))
+}
+
+#[get("/crosschain/pending/{account}")]
+async fn pending_crosschain_transfers(
+    path: web::Path<String>,
+    crosschain: web::Data<CrossChainTransfersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let pending = web::block(move || {
+        labels::label_crosschain_transfers(
+            &labels_repository,
+            crosschain.find_pending(&account)?,
+            |transfer| (&transfer.sender, transfer.receiver.as_ref()),
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(pending))
+}
+
+#[get("/crosschain/stuck")]
+async fn stuck_crosschain_transfers(
+    request: HttpRequest,
+    stuck: web::Data<StuckCrossChainTransfersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let account = params.get("account").map(|e| e.to_string());
+    let min_age = match params.get("min_age").map(|e| parse_duration(e)) {
+        Some(Some(min_age)) => min_age,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid min_age")),
+        None => Duration::zero(),
+    };
+    let initiated_before = match Utc::now().naive_utc().checked_sub_signed(min_age) {
+        Some(initiated_before) => initiated_before,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid min_age")),
+    };
+    let transfers = web::block(move || {
+        labels::label_crosschain_transfers(
+            &labels_repository,
+            stuck.find(account, initiated_before)?,
+            |transfer| (&transfer.sender, transfer.receiver.as_ref()),
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
     Ok(HttpResponse::Ok().json(transfers))
 }
 
+#[get("/crosschain/{pact_id}")]
+async fn crosschain_transfer(
+    path: web::Path<String>,
+    crosschain: web::Data<CrossChainTransfersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let pact_id = path.into_inner();
+    let transfer = web::block(move || {
+        let found = crosschain.find_by_pact_id(&pact_id)?.into_iter().collect();
+        labels::label_crosschain_transfers(&labels_repository, found, |transfer| {
+            (&transfer.sender, transfer.receiver.as_ref())
+        })
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .pop();
+    Ok(match transfer {
+        Some(transfer) => HttpResponse::Ok().json(transfer),
+        None => HttpResponse::NotFound().body("Cross-chain transfer not found"),
+    })
+}
+
+#[get("/tokens")]
+async fn get_tokens(tokens: web::Data<TokensRepository>) -> actix_web::Result<impl Responder> {
+    let all = web::block(move || tokens.find_all())
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(all))
+}
+
+#[get("/tokens/{module}")]
+async fn get_token(
+    path: web::Path<String>,
+    tokens: web::Data<TokensRepository>,
+) -> actix_web::Result<impl Responder> {
+    let module = path.into_inner();
+    let token = web::block(move || tokens.find_by_module(&module))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(match token {
+        Some(token) => HttpResponse::Ok().json(token),
+        None => HttpResponse::NotFound().body("Token not found"),
+    })
+}
+
+#[get("/tokens/{module}/holders")]
+async fn get_token_holders(
+    path: web::Path<String>,
+    request: HttpRequest,
+    tokens: web::Data<TokensRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let module = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let limit = match params.get("limit").map(|l| l.parse::<i64>()) {
+        Some(Ok(limit)) if (1..=1000).contains(&limit) => limit,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid limit")),
+        None => 100,
+    };
+    let offset = match params.get("offset").map(|o| o.parse::<i64>()) {
+        Some(Ok(offset)) if offset >= 0 => offset,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid offset")),
+        None => 0,
+    };
+    let holders = web::block(move || -> Result<Labelled<TokenHolders>, db::DbError> {
+        let holders = TokenHolders {
+            holders: tokens.find_holders(&module, chain, limit, offset)?,
+            total_supply: tokens.find_total_supply(&module, chain)?,
+            module,
+            chain_id: chain,
+        };
+        let labels = labels::find_labels(
+            &labels_repository,
+            holders.holders.iter().map(|holder| &holder.account),
+        )?;
+        Ok(Labelled {
+            value: holders,
+            labels,
+        })
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(holders))
+}
+
+#[get("/tokens/{module}/supply")]
+async fn get_token_supply(
+    path: web::Path<String>,
+    request: HttpRequest,
+    supply_snapshots: web::Data<SupplySnapshotsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let module = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let interval = match parse_duration(params.get("interval").map_or("1d", |e| e.as_str())) {
+        Some(interval) if interval.num_seconds() % 3600 == 0 => interval,
+        _ => return Ok(HttpResponse::BadRequest().body("Invalid interval")),
+    };
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => to,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => Utc::now().naive_utc(),
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => from,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => to - Duration::days(30),
+    };
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let series = web::block(move || {
+        supply::supply_series(&supply_snapshots, &module, interval, from, to, chain)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(series))
+}
+
+#[get("/analytics/holding/{account}/{module}")]
+async fn get_holding_periods(
+    path: web::Path<(String, String)>,
+    analytics_repository: web::Data<AnalyticsRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let (account, module) = path.into_inner();
+    let periods = web::block(move || {
+        labels::label_accounts(
+            &labels_repository,
+            analytics::holding_periods(&analytics_repository, &account, &module)?,
+            |period| &period.address,
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(periods))
+}
+
+#[get("/analytics/graph/{account}")]
+async fn get_wallet_graph(
+    path: web::Path<String>,
+    request: HttpRequest,
+    wallet_connections: web::Data<WalletConnectionsRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let depth = match params.get("depth").map(|e| e.parse::<u32>()) {
+        Some(Ok(depth)) if (1..=3).contains(&depth) => depth,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid depth")),
+        None => 2,
+    };
+    let module = params
+        .get("module")
+        .cloned()
+        .unwrap_or_else(|| "coin".to_string());
+    let min_amount = match params.get("min_amount").map(|e| e.parse::<BigDecimal>()) {
+        Some(Ok(min_amount)) => Some(min_amount),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid min_amount")),
+        None => None,
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => Some(from),
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => None,
+    };
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => Some(to),
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => None,
+    };
+    let format = params.get("format").cloned().unwrap_or_default();
+    if ![
+        "",
+        "json",
+        "graphml",
+        "gexf",
+        "dot",
+        "csv-nodes",
+        "csv-edges",
+    ]
+    .contains(&format.as_str())
+    {
+        return Ok(HttpResponse::BadRequest().body("Invalid format"));
+    }
+    let filter = analytics::GraphFilter {
+        module,
+        from,
+        to,
+        min_amount,
+    };
+    // Labels are only part of the JSON format
+    let json = format.is_empty() || format == "json";
+    let (graph, labels) = web::block(move || -> Result<_, db::DbError> {
+        let graph = analytics::wallet_graph(&wallet_connections, &account, depth, &filter)?;
+        let labels = match json {
+            true => labels::find_labels(
+                &labels_repository,
+                graph.nodes.iter().map(|node| &node.account),
+            )?,
+            false => HashMap::new(),
+        };
+        Ok((graph, labels))
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(match format.as_str() {
+        "graphml" => HttpResponse::Ok()
+            .content_type("application/xml")
+            .body(graph_export::to_graphml(&graph)),
+        "gexf" => HttpResponse::Ok()
+            .content_type("application/xml")
+            .body(graph_export::to_gexf(&graph)),
+        "dot" => HttpResponse::Ok()
+            .content_type("text/vnd.graphviz")
+            .body(graph_export::to_dot(&graph)),
+        "csv-nodes" => HttpResponse::Ok()
+            .content_type("text/csv")
+            .body(graph_export::to_csv_nodes(&graph)),
+        "csv-edges" => HttpResponse::Ok()
+            .content_type("text/csv")
+            .body(graph_export::to_csv_edges(&graph)),
+        _ => HttpResponse::Ok().json(Labelled {
+            value: graph,
+            labels,
+        }),
+    })
+}
+
+#[get("/analytics/accounts/{account}/behaviour")]
+async fn get_account_behaviour(
+    path: web::Path<String>,
+    analytics_repository: web::Data<AnalyticsRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let behaviour = web::block(move || -> Result<LabelledAccount<_>, db::DbError> {
+        let behaviour = behaviour::account_behaviour(&analytics_repository, &account)?;
+        let account_labels = labels::find_labels(&labels_repository, [&account])?
+            .remove(&account)
+            .unwrap_or_default();
+        Ok(LabelledAccount {
+            value: behaviour,
+            account_labels,
+        })
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(behaviour))
+}
+
+#[get("/analytics/activity")]
+async fn get_activity(
+    request: HttpRequest,
+    activity_rollups: web::Data<ActivityRollupsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let scope = match ActivityScope::new(
+        params.get("scope").map_or("network", |e| e.as_str()),
+        params.get("id").map(|e| e.as_str()),
+    ) {
+        Some(scope) => scope,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid scope")),
+    };
+    let interval = match params
+        .get("interval")
+        .map_or("hour", |e| e.as_str())
+        .parse::<ActivityInterval>()
+    {
+        Ok(interval) => interval,
+        _ => return Ok(HttpResponse::BadRequest().body("Invalid interval")),
+    };
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => to,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => Utc::now().naive_utc(),
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => from,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None if interval == ActivityInterval::Minute => to - Duration::days(1),
+        None => to - Duration::days(30),
+    };
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let series = web::block(move || {
+        activity::activity_series(&activity_rollups, &scope, interval, from, to, chain)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(series))
+}
+
+#[get("/analytics/flows/{module}")]
+async fn get_token_flows(
+    path: web::Path<String>,
+    request: HttpRequest,
+    analytics_repository: web::Data<AnalyticsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let module = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => to,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => Utc::now().naive_utc(),
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => from,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => to - Duration::days(30),
+    };
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let groups = match params.get("groups").map(|e| e.parse::<usize>()) {
+        Some(Ok(groups)) if (1..=flows::MAX_FLOW_GROUPS).contains(&groups) => groups,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid groups")),
+        None => flows::DEFAULT_FLOW_GROUPS,
+    };
+    let sankey = web::block(move || {
+        flows::token_flows(&analytics_repository, &module, from, to, chain, groups)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(sankey))
+}
+
+#[get("/analytics/cohorts")]
+async fn get_cohorts(
+    request: HttpRequest,
+    cohorts_repository: web::Data<CohortsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let module = params
+        .get("module")
+        .map_or("coin", |e| e.as_str())
+        .to_string();
+    let interval = match params
+        .get("interval")
+        .map_or("week", |e| e.as_str())
+        .parse::<CohortInterval>()
+    {
+        Ok(interval) => interval,
+        Err(_) => return Ok(HttpResponse::BadRequest().body("Invalid interval")),
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => Some(from),
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => None,
+    };
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => Some(to),
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => None,
+    };
+    let matrix = web::block(move || -> Result<Option<CohortMatrix>, db::DbError> {
+        let rows = cohorts_repository.find(&module, interval.name(), from, to)?;
+        Ok(match rows.is_empty() {
+            true => None,
+            false => Some(cohorts::cohort_matrix(
+                &module,
+                interval,
+                &rows,
+                Utc::now().naive_utc(),
+            )),
+        })
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(match matrix {
+        Some(matrix) => HttpResponse::Ok().json(matrix),
+        None => HttpResponse::NotFound().body("Cohorts not found"),
+    })
+}
+
+#[get("/analytics/entity/{account}")]
+async fn get_entity(
+    path: web::Path<String>,
+    clusters: web::Data<ClustersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let entity = web::block(move || -> Result<Labelled<Entity>, db::DbError> {
+        // Accounts without links are entities on their own
+        let entity = clusters.find_entity(&account)?.unwrap_or_else(|| Entity {
+            cluster_id: account.clone(),
+            accounts: vec![account.clone()],
+            links: vec![],
+        });
+        let labels = labels::find_labels(&labels_repository, entity.accounts.iter())?;
+        Ok(Labelled {
+            value: entity,
+            labels,
+        })
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(entity))
+}
+
+#[get("/analytics/tokens/{module}/holding-stats")]
+async fn get_holding_stats(
+    path: web::Path<String>,
+    holding_stats: web::Data<HoldingStatsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let module = path.into_inner();
+    let summary = web::block(move || holding_stats.find_by_module(&module))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(match summary {
+        Some(summary) => HttpResponse::Ok().json(summary),
+        None => HttpResponse::NotFound().body("Holding stats not found"),
+    })
+}
+
+#[get("/dex/pairs/{pair}/candles")]
+async fn get_pair_candles(
+    path: web::Path<String>,
+    request: HttpRequest,
+    dex_repository: web::Data<DexRepository>,
+) -> actix_web::Result<impl Responder> {
+    let pair = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let interval = match parse_duration(params.get("interval").map_or("1h", |e| e.as_str())) {
+        Some(interval) => interval,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid interval")),
+    };
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => to,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => Utc::now().naive_utc(),
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => from,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => to - Duration::days(30),
+    };
+    let module = params.get("module").cloned();
+    let candles = web::block(move || {
+        dex::candles(
+            &dex_repository,
+            &pair,
+            interval,
+            from,
+            to,
+            module.as_deref(),
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(candles))
+}
+
+#[get("/nft/tokens/{token_id}")]
+async fn get_nft_token(
+    path: web::Path<String>,
+    nft: web::Data<NftRepository>,
+) -> actix_web::Result<impl Responder> {
+    let token_id = path.into_inner();
+    let tokens = web::block(move || nft.find_tokens(&token_id))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(match tokens.is_empty() {
+        true => HttpResponse::NotFound().body("Token not found"),
+        false => HttpResponse::Ok().json(tokens),
+    })
+}
+
+#[get("/nft/tokens/{token_id}/owners")]
+async fn get_nft_owners(
+    path: web::Path<String>,
+    request: HttpRequest,
+    nft: web::Data<NftRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let token_id = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let owners = web::block(move || {
+        labels::label_accounts(
+            &labels_repository,
+            nft.find_owners(&token_id, chain)?,
+            |owner| &owner.account,
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(owners))
+}
+
+#[get("/nft/tokens/{token_id}/sales")]
+async fn get_nft_sales(
+    path: web::Path<String>,
+    request: HttpRequest,
+    nft: web::Data<NftRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let token_id = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let sales = web::block(move || {
+        labels::label_nft_sales(&labels_repository, nft.find_sales(&token_id, chain)?)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(sales))
+}
+
+#[get("/miners")]
+async fn get_miners(
+    request: HttpRequest,
+    miners: web::Data<MinersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let window = match parse_duration(params.get("window").map_or("24h", |e| e.as_str())) {
+        Some(window) => window,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid window")),
+    };
+    let limit = match params.get("limit").map(|l| l.parse::<i64>()) {
+        Some(Ok(limit)) if (1..=1000).contains(&limit) => limit,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Invalid limit")),
+        None => 100,
+    };
+    let since = match Utc::now().naive_utc().checked_sub_signed(window) {
+        Some(since) => since,
+        None => return Ok(HttpResponse::BadRequest().body("Invalid window")),
+    };
+    let miners = web::block(move || {
+        labels::label_accounts(
+            &labels_repository,
+            miners.find_all(since, limit)?,
+            |miner| &miner.miner,
+        )
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(miners))
+}
+
+#[get("/miners/{account}")]
+async fn get_miner(
+    path: web::Path<String>,
+    request: HttpRequest,
+    miners: web::Data<MinersRepository>,
+    labels_repository: web::Data<LabelsRepository>,
+) -> actix_web::Result<impl Responder> {
+    let account = path.into_inner();
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let now = Utc::now().naive_utc();
+    let mut windows = vec![];
+    for window in params
+        .get("windows")
+        .map_or("1h,24h,7d,30d", |e| e.as_str())
+        .split(',')
+    {
+        match parse_duration(window).and_then(|duration| now.checked_sub_signed(duration)) {
+            Some(since) => windows.push((window.to_string(), since)),
+            None => return Ok(HttpResponse::BadRequest().body("Invalid windows")),
+        }
+    }
+    let details = web::block(move || {
+        let found = miners
+            .find_by_account(&account, &windows)?
+            .into_iter()
+            .collect();
+        labels::label_accounts(&labels_repository, found, |details| &details.miner)
+    })
+    .await?
+    .map_err(error::ErrorInternalServerError)?
+    .pop();
+    Ok(match details {
+        Some(details) => HttpResponse::Ok().json(details),
+        None => HttpResponse::NotFound().body("Miner not found"),
+    })
+}
+
+#[get("/network/hashrate")]
+async fn get_hashrate(
+    request: HttpRequest,
+    hashrate: web::Data<HashrateRepository>,
+) -> actix_web::Result<impl Responder> {
+    let params = web::Query::<HashMap<String, String>>::from_query(request.query_string()).unwrap();
+    let interval = match parse_duration(params.get("interval").map_or("1h", |e| e.as_str())) {
+        Some(interval) if interval.num_seconds() % 3600 == 0 => interval,
+        _ => return Ok(HttpResponse::BadRequest().body("Invalid interval")),
+    };
+    let now = Utc::now().naive_utc();
+    let to = match params.get("to").map(|e| parse_time(e)) {
+        Some(Some(to)) => to,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid to")),
+        None => now,
+    };
+    let from = match params.get("from").map(|e| parse_time(e)) {
+        Some(Some(from)) => from,
+        Some(None) => return Ok(HttpResponse::BadRequest().body("Invalid from")),
+        None => to - Duration::days(7),
+    };
+    let chain = match params.get("chain").map(|e| e.parse::<i64>()) {
+        Some(Ok(chain)) => Some(chain),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().body("Invalid chain")),
+        None => None,
+    };
+    let stats =
+        web::block(move || network::hashrate_series(&hashrate, interval, from, to, chain, now))
+            .await?
+            .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+const MAX_DURATION_DAYS: i64 = 100 * 365;
+
+/// Parses RFC 3339 timestamps or dates like `2023-10-01`.
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => Some(time.naive_utc()),
+        Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0)),
+    }
+}
+
+/// Parses durations like `30m`, `1h`, `7d` or `2w`.
+/// Parses durations like `30m`, `24h`, `7d` or `2w`. Durations are limited to
+/// `MAX_DURATION_DAYS` so intervals can be added to the times of the buckets.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let unit_index = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(unit_index);
+    let amount = amount.parse::<i64>().ok().filter(|amount| *amount > 0)?;
+    let unit_seconds = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => return None,
+    };
+    // chrono panics on out of range durations, milliseconds always fit
+    amount
+        .checked_mul(unit_seconds)?
+        .checked_mul(1000)
+        .map(Duration::milliseconds)
+        .filter(|duration| duration.num_days() <= MAX_DURATION_DAYS)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
@@ -119,17 +958,74 @@ async fn main() -> std::io::Result<()> {
     let pool = db::initialize_db_pool();
     let transactions = TransactionsRepository { pool: pool.clone() };
     let transfers = TransfersRepository { pool: pool.clone() };
+    let miners = MinersRepository { pool: pool.clone() };
+    let hashrate = HashrateRepository { pool: pool.clone() };
+    let crosschain = CrossChainTransfersRepository { pool: pool.clone() };
+    let stuck_crosschain = StuckCrossChainTransfersRepository { pool: pool.clone() };
+    let balances = BalancesRepository { pool: pool.clone() };
+    let tokens = TokensRepository { pool: pool.clone() };
+    let supply_snapshots = SupplySnapshotsRepository { pool: pool.clone() };
+    let nft = NftRepository { pool: pool.clone() };
+    let dex_repository = DexRepository { pool: pool.clone() };
+    let analytics_repository = AnalyticsRepository { pool: pool.clone() };
+    let holding_stats_repository = HoldingStatsRepository { pool: pool.clone() };
+    let wallet_connections = WalletConnectionsRepository { pool: pool.clone() };
+    let clusters = ClustersRepository { pool: pool.clone() };
+    let activity_rollups = ActivityRollupsRepository { pool: pool.clone() };
+    let labels_repository = LabelsRepository { pool: pool.clone() };
+    let cohorts_repository = CohortsRepository { pool: pool.clone() };
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(transactions.clone()))
             .app_data(web::Data::new(transfers.clone()))
+            .app_data(web::Data::new(miners.clone()))
+            .app_data(web::Data::new(hashrate.clone()))
+            .app_data(web::Data::new(crosschain.clone()))
+            .app_data(web::Data::new(stuck_crosschain.clone()))
+            .app_data(web::Data::new(balances.clone()))
+            .app_data(web::Data::new(tokens.clone()))
+            .app_data(web::Data::new(supply_snapshots.clone()))
+            .app_data(web::Data::new(nft.clone()))
+            .app_data(web::Data::new(dex_repository.clone()))
+            .app_data(web::Data::new(analytics_repository.clone()))
+            .app_data(web::Data::new(holding_stats_repository.clone()))
+            .app_data(web::Data::new(wallet_connections.clone()))
+            .app_data(web::Data::new(clusters.clone()))
+            .app_data(web::Data::new(activity_rollups.clone()))
+            .app_data(web::Data::new(labels_repository.clone()))
+            .app_data(web::Data::new(cohorts_repository.clone()))
             .service(tx)
             .service(txs)
+            .service(balance_history)
             .service(balance)
             .service(all_balances)
             .service(received_transfers)
             .service(get_transfers)
+            .service(get_labels)
+            .service(get_account_labels)
+            .service(get_tokens)
+            .service(get_token_holders)
+            .service(get_token_supply)
+            .service(get_token)
+            .service(get_pair_candles)
+            .service(get_holding_periods)
+            .service(get_holding_stats)
+            .service(get_wallet_graph)
+            .service(get_entity)
+            .service(get_account_behaviour)
+            .service(get_activity)
+            .service(get_token_flows)
+            .service(get_cohorts)
+            .service(get_nft_owners)
+            .service(get_nft_sales)
+            .service(get_nft_token)
+            .service(get_miners)
+            .service(get_miner)
+            .service(get_hashrate)
+            .service(pending_crosschain_transfers)
+            .service(stuck_crosschain_transfers)
+            .service(crosschain_transfer)
     })
     .bind(("0.0.0.0", port))?
     .run()
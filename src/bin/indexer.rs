@@ -1,10 +1,26 @@
+use bento::analytics::{self, GraphFilter};
+use bento::balances;
 use bento::chainweb_client::ChainwebClient;
+use bento::clustering;
+use bento::cohorts::{self, CohortInterval};
+use bento::crosschain;
 use bento::db;
 use bento::gaps;
+use bento::graph_export::{self, GraphFormat};
+use bento::handlers;
+use bento::holding_stats;
 use bento::indexer::*;
+use bento::labels;
+use bento::mappings;
+use bento::network;
+use bento::reconcile;
 use bento::repository::*;
+use bento::tokens;
+use bigdecimal::BigDecimal;
+use chrono::{Duration, NaiveDate, Utc};
 use clap::{Parser, Subcommand};
 use dotenvy::dotenv;
+use std::env;
 
 #[derive(Parser)]
 /// By default new blocks are indexed as they are mined. For backfilling and filling gaps use the
@@ -20,6 +36,107 @@ enum Command {
     Backfill,
     /// Index missed blocks
     Gaps,
+    /// Decode the targets of blocks indexed before they were stored and refresh the hashrate
+    Targets,
+    /// Find cross-chain transfers that weren't completed on the target chain
+    StuckCrosschain {
+        /// Minimum age of the step 0 transaction, in hours
+        #[arg(long, default_value_t = 24)]
+        min_age_hours: i64,
+    },
+    /// Rebuild the balances table from transfers
+    Balances {
+        /// Only report balances that differ from the transfers, without rebuilding
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Compare indexed coin balances with the balances reported by the node
+    Reconcile {
+        /// Comma separated accounts to check, a random sample of accounts is used otherwise
+        #[arg(long, value_delimiter = ',')]
+        accounts: Vec<String>,
+        /// Number of accounts to sample
+        #[arg(long, default_value_t = 100)]
+        sample: i64,
+    },
+    /// Fetch the precision of new tokens from the node
+    Tokens {
+        /// Recompute transfer counts and volumes of all tokens from transfers first
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Compute holding period statistics of tokens
+    HoldingStats {
+        /// Comma separated modules, all tokens are used otherwise
+        #[arg(long, value_delimiter = ',')]
+        modules: Vec<String>,
+        /// Compute the statistics again every given number of hours instead of exiting
+        #[arg(long)]
+        every_hours: Option<u64>,
+    },
+    /// Group accounts controlled by the same entity into clusters
+    Clusters,
+    /// Compute the retention of cohorts of new accounts
+    Cohorts {
+        /// Comma separated modules whose transfers count as activity
+        #[arg(long, value_delimiter = ',', default_value = "coin")]
+        modules: Vec<String>,
+        /// Comma separated intervals: day, week or month
+        #[arg(long, value_delimiter = ',', default_value = "week")]
+        intervals: Vec<CohortInterval>,
+        /// Compute the cohorts again every given number of hours instead of exiting
+        #[arg(long)]
+        every_hours: Option<u64>,
+    },
+    /// Import account labels from a CSV or JSON file
+    Labels {
+        /// CSV file with account, label and category columns, and optionally source and
+        /// confidence columns, or JSON file (.json) with an array of objects with these fields
+        file: String,
+        /// Source of the labels that don't have one
+        #[arg(long, default_value = labels::DEFAULT_SOURCE)]
+        source: String,
+        /// Delete the other labels of the imported sources
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Export a wallet graph for Gephi, Graphviz or Neo4j
+    ExportGraph {
+        /// graphml, gexf, dot or csv (Neo4j nodes and relationships files)
+        #[arg(long)]
+        format: GraphFormat,
+        /// Path of the file, for csv the prefix of the -nodes.csv and -edges.csv files
+        #[arg(long)]
+        output: String,
+        #[arg(long, default_value = "coin")]
+        module: String,
+        /// Export the accounts connected to this account only, instead of every connection
+        #[arg(long)]
+        account: Option<String>,
+        /// Maximum number of transfers between the account and the exported accounts
+        #[arg(long, default_value_t = 2)]
+        depth: u32,
+        /// Only count transfers made on or after this date
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Only count transfers made before this date
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Minimum total amount of the exported connections
+        #[arg(long)]
+        min_amount: Option<BigDecimal>,
+        /// Maximum number of connections exported without an account, largest first
+        #[arg(long, default_value_t = analytics::MAX_NETWORK_EDGES)]
+        limit: i64,
+    },
+    /// Rebuild the data of an event handler from the events table
+    Rebuild {
+        /// Name of the handler, eg. marmalade
+        handler: String,
+        /// Number of block heights processed per transaction
+        #[arg(long, default_value_t = 10000)]
+        batch_size: i64,
+    },
 }
 
 #[tokio::main]
@@ -34,6 +151,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let events = EventsRepository { pool: pool.clone() };
     let transactions = TransactionsRepository { pool: pool.clone() };
     let transfers_repo = TransfersRepository { pool: pool.clone() };
+    let hashrate = HashrateRepository { pool: pool.clone() };
+    let balances = BalancesRepository { pool: pool.clone() };
+    let tokens_repo = TokensRepository { pool: pool.clone() };
+    let supply = SupplySnapshotsRepository { pool: pool.clone() };
+    let wallet_connections = WalletConnectionsRepository { pool: pool.clone() };
+    let activity = ActivityRollupsRepository { pool: pool.clone() };
+    let accounts = AccountsRepository { pool: pool.clone() };
+    let mut handlers = handlers::default_handlers(&pool);
+    if let Ok(path) = env::var("EVENT_MAPPINGS") {
+        mappings::register_mappings(&path, &pool, &mut handlers)?;
+    }
     let chainweb_client = ChainwebClient::new();
     let indexer = Indexer {
         chainweb_client: &chainweb_client,
@@ -41,6 +169,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         events: events.clone(),
         transactions: transactions.clone(),
         transfers: transfers_repo.clone(),
+        hashrate: hashrate.clone(),
+        balances: balances.clone(),
+        tokens: tokens_repo.clone(),
+        supply: supply.clone(),
+        wallet_connections: wallet_connections.clone(),
+        activity: activity.clone(),
+        accounts: accounts.clone(),
+        handlers,
     };
 
     let args = IndexerCli::parse();
@@ -53,6 +189,189 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             log::info!("Filling gaps...");
             gaps::fill_gaps(&chainweb_client, &blocks, &indexer).await?;
         }
+        Some(Command::Targets) => {
+            log::info!("Decoding block targets...");
+            let updated = network::backfill_targets(&chainweb_client, &blocks, &hashrate).await?;
+            log::info!("Decoded the targets of {} blocks", updated);
+        }
+        Some(Command::StuckCrosschain { min_age_hours }) => {
+            log::info!("Looking for stuck cross-chain transfers...");
+            let stuck = StuckCrossChainTransfersRepository { pool: pool.clone() };
+            crosschain::detect_stuck_transfers(&stuck, Duration::hours(min_age_hours))
+                .map_err(|e| e as Box<dyn std::error::Error>)?;
+        }
+        Some(Command::Balances { verify }) => {
+            if verify {
+                log::info!("Verifying balances...");
+                balances::verify_balances(&balances)
+                    .map_err(|e| e as Box<dyn std::error::Error>)?;
+            } else {
+                log::info!("Rebuilding balances...");
+                balances::rebuild_balances(&balances)
+                    .map_err(|e| e as Box<dyn std::error::Error>)?;
+            }
+        }
+        Some(Command::Reconcile { accounts, sample }) => {
+            log::info!("Reconciling balances...");
+            let accounts = if accounts.is_empty() {
+                balances
+                    .sample_accounts("coin", sample)
+                    .map_err(|e| e as Box<dyn std::error::Error>)?
+            } else {
+                accounts
+            };
+            let mismatches = BalanceMismatchesRepository { pool: pool.clone() };
+            reconcile::reconcile_accounts(&chainweb_client, &balances, &mismatches, &accounts)
+                .await?;
+        }
+        Some(Command::Tokens { rebuild }) => {
+            if rebuild {
+                log::info!("Rebuilding token stats...");
+                tokens_repo
+                    .rebuild_stats()
+                    .map_err(|e| e as Box<dyn std::error::Error>)?;
+            }
+            log::info!("Updating token precisions...");
+            tokens::update_precisions(&chainweb_client, &tokens_repo).await?;
+        }
+        Some(Command::HoldingStats {
+            modules,
+            every_hours,
+        }) => {
+            let analytics = AnalyticsRepository { pool: pool.clone() };
+            let holding_stats_repo = HoldingStatsRepository { pool: pool.clone() };
+            loop {
+                let modules = if modules.is_empty() {
+                    tokens_repo
+                        .find_all()
+                        .map_err(|e| e as Box<dyn std::error::Error>)?
+                        .into_iter()
+                        .map(|summary| summary.token.module)
+                        .collect()
+                } else {
+                    modules.clone()
+                };
+                for module in modules.iter() {
+                    log::info!("Computing holding stats of {}...", module);
+                    holding_stats::refresh_holding_stats(
+                        &analytics,
+                        &holding_stats_repo,
+                        module,
+                        Utc::now().naive_utc(),
+                    )
+                    .map_err(|e| e as Box<dyn std::error::Error>)?;
+                }
+                match every_hours {
+                    Some(hours) => {
+                        tokio::time::sleep(std::time::Duration::from_secs(hours * 3600)).await
+                    }
+                    None => break,
+                }
+            }
+        }
+        Some(Command::Clusters) => {
+            log::info!("Clustering accounts...");
+            let clusters = ClustersRepository { pool: pool.clone() };
+            clustering::refresh_clusters(&clusters).map_err(|e| e as Box<dyn std::error::Error>)?;
+        }
+        Some(Command::Cohorts {
+            modules,
+            intervals,
+            every_hours,
+        }) => {
+            let cohorts_repo = CohortsRepository { pool: pool.clone() };
+            loop {
+                for module in modules.iter() {
+                    for interval in intervals.iter() {
+                        log::info!("Computing {} cohorts of {}...", interval.name(), module);
+                        let count = cohorts::refresh_cohorts(&cohorts_repo, module, *interval)
+                            .map_err(|e| e as Box<dyn std::error::Error>)?;
+                        log::info!("Stored {} cohorts", count);
+                    }
+                }
+                match every_hours {
+                    Some(hours) => {
+                        tokio::time::sleep(std::time::Duration::from_secs(hours * 3600)).await
+                    }
+                    None => break,
+                }
+            }
+        }
+        Some(Command::Labels {
+            file,
+            source,
+            replace,
+        }) => {
+            log::info!("Importing labels from {}...", file);
+            let labels_repo = LabelsRepository { pool: pool.clone() };
+            let imported = labels::import_labels(&labels_repo, &file, &source, replace)?;
+            log::info!("Imported {} labels", imported);
+        }
+        Some(Command::ExportGraph {
+            format,
+            output,
+            module,
+            account,
+            depth,
+            from,
+            to,
+            min_amount,
+            limit,
+        }) => {
+            let filter = GraphFilter {
+                module,
+                from: from.and_then(|date| date.and_hms_opt(0, 0, 0)),
+                to: to.and_then(|date| date.and_hms_opt(0, 0, 0)),
+                min_amount,
+            };
+            let graph = match account {
+                Some(account) => {
+                    analytics::wallet_graph(&wallet_connections, &account, depth, &filter)
+                }
+                None => analytics::wallet_network(&wallet_connections, &filter, limit),
+            }
+            .map_err(|e| e as Box<dyn std::error::Error>)?;
+            match format {
+                GraphFormat::GraphMl => std::fs::write(&output, graph_export::to_graphml(&graph))?,
+                GraphFormat::Gexf => std::fs::write(&output, graph_export::to_gexf(&graph))?,
+                GraphFormat::Dot => std::fs::write(&output, graph_export::to_dot(&graph))?,
+                GraphFormat::Csv => {
+                    std::fs::write(
+                        format!("{}-nodes.csv", output),
+                        graph_export::to_csv_nodes(&graph),
+                    )?;
+                    std::fs::write(
+                        format!("{}-edges.csv", output),
+                        graph_export::to_csv_edges(&graph),
+                    )?;
+                }
+            }
+            log::info!(
+                "Exported {} accounts and {} connections",
+                graph.nodes.len(),
+                graph.edges.len()
+            );
+        }
+        Some(Command::Rebuild {
+            handler,
+            batch_size,
+        }) => {
+            let handler = match indexer.handlers.find(&handler) {
+                Some(handler) => handler,
+                None => {
+                    return Err(format!(
+                        "Unknown event handler {}, available handlers: {}",
+                        handler,
+                        indexer.handlers.names().join(", ")
+                    )
+                    .into())
+                }
+            };
+            log::info!("Rebuilding {}...", handler.name());
+            let processed = handlers::rebuild(handler, &blocks, &events, batch_size)
+                .map_err(|e| e as Box<dyn std::error::Error>)?;
+            log::info!("Processed {} events", processed);
+        }
         None => {
             log::info!("Indexing blocks...");
             indexer.listen_headers_stream().await?;
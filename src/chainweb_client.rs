@@ -1,10 +1,12 @@
 use self::tx_result::PactTransactionResult;
 use bigdecimal::BigDecimal;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
 use eventsource_client::SSE;
 use futures::Stream;
 use reqwest::Url;
-use serde::Deserializer;
 use serde::{Deserialize, Serialize};
+use serde::{Deserializer, Serializer};
 use serde_json::Value;
 use std::env;
 use std::fmt::Display;
@@ -108,14 +110,14 @@ pub struct BlockPayload {
 pub struct Sig {
     pub sig: String,
 }
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct SignedTransaction {
     pub cmd: String,
     pub hash: String,
     pub sigs: Vec<Sig>,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum Network {
     #[serde(rename = "mainnet01")]
     Mainnet,
@@ -125,24 +127,19 @@ pub enum Network {
     Devnet,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Meta {
-    #[serde(rename(deserialize = "chainId"))]
+    #[serde(rename = "chainId")]
     pub chain_id: String,
     #[serde(
-        rename(deserialize = "creationTime",),
-        deserialize_with = "de_f64_or_string_as_f64"
+        rename = "creationTime",
+        deserialize_with = "de_f64_or_string_as_f64",
+        serialize_with = "ser_f64_as_i64"
     )]
     pub creation_time: f64,
-    #[serde(
-        rename(deserialize = "gasLimit"),
-        deserialize_with = "de_i64_or_string_as_i64"
-    )]
+    #[serde(rename = "gasLimit", deserialize_with = "de_i64_or_string_as_i64")]
     pub gas_limit: i64,
-    #[serde(
-        rename(deserialize = "gasPrice"),
-        deserialize_with = "de_f64_or_string_as_f64"
-    )]
+    #[serde(rename = "gasPrice", deserialize_with = "de_f64_or_string_as_f64")]
     pub gas_price: f64,
     pub sender: String,
     #[serde(deserialize_with = "de_f64_or_u64_or_string_as_u64")]
@@ -173,6 +170,11 @@ fn de_i64_or_string_as_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result
     })
 }
 
+/// Pact expects the creation time of a command to be an integer.
+fn ser_f64_as_i64<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(*value as i64)
+}
+
 fn de_f64_or_u64_or_string_as_u64<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<u64, D::Error> {
@@ -183,15 +185,15 @@ fn de_f64_or_u64_or_string_as_u64<'de, D: Deserializer<'de>>(
     })
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Signer {
-    #[serde(rename(deserialize = "pubKey"))]
+    #[serde(rename = "pubKey")]
     public_key: String,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Command {
-    #[serde(rename(deserialize = "networkId"))]
+    #[serde(rename = "networkId")]
     pub network_id: Option<Network>,
     pub nonce: String,
     pub payload: Payload,
@@ -201,7 +203,9 @@ pub struct Command {
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Payload {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub exec: Option<ExecPayload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub cont: Option<ContPayload>,
 }
 
@@ -214,7 +218,7 @@ pub struct ExecPayload {
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ContPayload {
     pub data: Value,
-    #[serde(rename(deserialize = "pactId"))]
+    #[serde(rename = "pactId")]
     pub pact_id: String,
     pub proof: Option<String>,
     pub rollback: bool,
@@ -241,7 +245,8 @@ pub mod tx_result {
 
     #[derive(Deserialize, Serialize, Debug)]
     pub struct Metadata {
-        #[serde(rename(deserialize = "blockHash"))]
+        // Not returned by the /local endpoint
+        #[serde(rename(deserialize = "blockHash"), default)]
         pub block_hash: String,
         #[serde(rename(deserialize = "blockHeight"))]
         pub block_height: i64,
@@ -288,6 +293,10 @@ pub struct ChainwebClient {
 impl ChainwebClient {
     pub fn new() -> Self {
         let host = env::var("CHAINWEB_NODE_HOST").expect("Missing CHAINWEB_NODE_HOST");
+        Self::with_host(&host)
+    }
+
+    pub fn with_host(host: &str) -> Self {
         ChainwebClient {
             base_url: format!("{host}/chainweb/0.0/mainnet01"),
         }
@@ -398,6 +407,32 @@ impl ChainwebClient {
         Ok(response)
     }
 
+    /// Executes read-only Pact code on the given chain with an unsigned command, without
+    /// submitting a transaction.
+    pub async fn local(
+        &self,
+        chain: &ChainId,
+        code: &str,
+    ) -> Result<PactTransactionResult, Box<dyn Error>> {
+        let endpoint = format!("/chain/{chain}/pact/api/v1/local");
+        let url = Url::parse(&format!("{}{}", self.base_url, endpoint)).unwrap();
+        let command = build_local_command(
+            chain,
+            code,
+            serde_json::json!({}),
+            chrono::Utc::now().timestamp(),
+        );
+        let response: PactTransactionResult = reqwest::Client::new()
+            .post(url)
+            .json(&command)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+
     #[allow(dead_code)]
     pub fn start_headers_stream(
         &self,
@@ -424,6 +459,50 @@ impl ChainwebClient {
     }
 }
 
+/// Gas limit of commands sent to /local, high enough for any read-only query.
+const LOCAL_GAS_LIMIT: i64 = 150000;
+
+/// Builds an unsigned exec command for the mainnet chain. The command is serialized to
+/// JSON and hashed, the hash is what the node uses as request key.
+pub fn build_local_command(
+    chain: &ChainId,
+    code: &str,
+    data: Value,
+    creation_time: i64,
+) -> SignedTransaction {
+    let command = Command {
+        network_id: Some(Network::Mainnet),
+        nonce: creation_time.to_string(),
+        payload: Payload {
+            exec: Some(ExecPayload {
+                code: code.to_string(),
+                data,
+            }),
+            cont: None,
+        },
+        signers: vec![],
+        meta: Meta {
+            chain_id: chain.to_string(),
+            creation_time: creation_time as f64,
+            gas_limit: LOCAL_GAS_LIMIT,
+            gas_price: 1e-8,
+            sender: String::new(),
+            ttl: 600,
+        },
+    };
+    let cmd = serde_json::to_string(&command).unwrap();
+    SignedTransaction {
+        hash: hash_command(&cmd),
+        cmd,
+        sigs: vec![],
+    }
+}
+
+/// Unpadded base64url encoded blake2b-256 hash of a command, as computed by Pact.
+pub fn hash_command(cmd: &str) -> String {
+    base64_url::encode(&Blake2b::<U32>::digest(cmd.as_bytes()))
+}
+
 impl Default for ChainwebClient {
     fn default() -> Self {
         Self::new()
@@ -522,6 +601,33 @@ mod tests {
         assert!(command.payload.exec.is_some());
     }
 
+    #[test]
+    fn test_hash_command() {
+        assert_eq!(
+            hash_command(""),
+            "DldRwCblQ7Loqy6wYJnaodHl30d3j3eH-qtFzfEv46g"
+        );
+    }
+
+    #[test]
+    fn test_build_local_command() {
+        let command = build_local_command(
+            &ChainId(2),
+            "(coin.details \"alice\")",
+            serde_json::json!({}),
+            1700000000,
+        );
+        assert!(command.sigs.is_empty());
+        assert_eq!(command.hash, hash_command(&command.cmd));
+        assert!(command.cmd.contains("\"creationTime\":1700000000,"));
+        assert!(!command.cmd.contains("\"cont\""));
+        let cmd = serde_json::from_str::<Command>(&command.cmd).unwrap();
+        assert_eq!(cmd.network_id, Some(Network::Mainnet));
+        assert_eq!(cmd.meta.chain_id, "2");
+        assert_eq!(cmd.meta.creation_time, 1700000000.0);
+        assert_eq!(cmd.payload.exec.unwrap().code, "(coin.details \"alice\")");
+    }
+
     #[test]
     fn test_parsing_command_with_gas_price_as_string() {
         let json = "{\"meta\":{\"chainId\":\"0\",\"creationTime\":1688039944,\"gasLimit\":8000,\"gasPrice\":\"0.00000001\",\"sender\":\"k:0b259904ba912dcfe7af4c70016e1a93982610c740b27c766ad329772ad44bd3\",\"ttl\":28860},\"networkId\":\"mainnet01\",\"nonce\":\"2023-06-29T19:59:04Z.189Z\",\"payload\":{\"exec\":{\"code\":\"(coin.transfer-create \\\"k:0b259904ba912dcfe7af4c70016e1a93982610c740b27c766ad329772ad44bd3\\\" \\\"k:5c01f1f5d0aa2fe56ad69b50025d56a1e2043cd76f743e792da7adf04d7abd06\\\" (read-keyset \\\"receiver-guard\\\") 230.9)\",\"data\":{\"receiver-guard\":{\"keys\":[\"5c01f1f5d0aa2fe56ad69b50025d56a1e2043cd76f743e792da7adf04d7abd06\"],\"pred\":\"keys-all\"}}}},\"signers\":[{\"clist\":[{\"args\":[\"k:0b259904ba912dcfe7af4c70016e1a93982610c740b27c766ad329772ad44bd3\",\"k:5c01f1f5d0aa2fe56ad69b50025d56a1e2043cd76f743e792da7adf04d7abd06\",230.9],\"name\":\"coin.TRANSFER\"},{\"args\":[],\"name\":\"coin.GAS\"}],\"pubKey\":\"0b259904ba912dcfe7af4c70016e1a93982610c740b27c766ad329772ad44bd3\"}]}";
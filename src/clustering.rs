@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::db::DbError;
+use crate::models::{AccountPair, Cluster, ClusterLink, DataKeyset};
+use crate::repository::ClustersRepository;
+
+/// Accounts paying gas for more accounts than this, or whose transfers are paid for by more
+/// accounts than this, are services (gas stations, pools) rather than one entity's wallets.
+const MAX_GAS_PAYER_ACCOUNTS: usize = 5;
+/// Keysets used by more senders than this are shared on purpose, eg. by a team
+const MAX_KEYSET_ACCOUNTS: usize = 5;
+/// Senders using more keysets than this create accounts for others, eg. wallets or faucets
+const MAX_SENDER_KEYSETS: usize = 5;
+
+/// Disjoint sets of accounts, with path compression and union by size.
+#[derive(Default)]
+pub struct UnionFind {
+    indexes: HashMap<String, usize>,
+    accounts: Vec<String>,
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    fn index(&mut self, account: &str) -> usize {
+        if let Some(index) = self.indexes.get(account) {
+            return *index;
+        }
+        let index = self.accounts.len();
+        self.indexes.insert(account.to_string(), index);
+        self.accounts.push(account.to_string());
+        self.parents.push(index);
+        self.sizes.push(1);
+        index
+    }
+
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut current = index;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    pub fn union(&mut self, a: &str, b: &str) {
+        let (a, b) = (self.index(a), self.index(b));
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+
+    /// Returns every account with its cluster, named after the first account of the cluster.
+    pub fn clusters(&mut self) -> Vec<Cluster> {
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for index in 0..self.accounts.len() {
+            let root = self.find(index);
+            groups.entry(root).or_default().push(index);
+        }
+        let mut clusters = vec![];
+        for indexes in groups.values() {
+            let cluster_id = indexes
+                .iter()
+                .map(|index| &self.accounts[*index])
+                .min()
+                .unwrap();
+            for index in indexes {
+                clusters.push(Cluster {
+                    account: self.accounts[*index].clone(),
+                    cluster_id: cluster_id.clone(),
+                });
+            }
+        }
+        clusters.sort_by(|a, b| a.account.cmp(&b.account));
+        clusters
+    }
+}
+
+fn make_link(
+    account: &str,
+    linked_account: &str,
+    evidence: &str,
+    detail: Option<String>,
+) -> ClusterLink {
+    ClusterLink {
+        account: account.to_string(),
+        linked_account: linked_account.to_string(),
+        evidence: evidence.to_string(),
+        detail,
+    }
+}
+
+/// Links gas payers to the accounts whose transfers they paid for, unless either side is
+/// involved with too many accounts.
+pub fn gas_payer_links(pairs: &[AccountPair]) -> Vec<ClusterLink> {
+    let mut paid_accounts: HashMap<&str, usize> = HashMap::new();
+    let mut payers: HashMap<&str, usize> = HashMap::new();
+    for pair in pairs.iter() {
+        *paid_accounts.entry(pair.account.as_str()).or_default() += 1;
+        *payers.entry(pair.linked_account.as_str()).or_default() += 1;
+    }
+    pairs
+        .iter()
+        .filter(|pair| {
+            paid_accounts[pair.account.as_str()] <= MAX_GAS_PAYER_ACCOUNTS
+                && payers[pair.linked_account.as_str()] <= MAX_GAS_PAYER_ACCOUNTS
+        })
+        .map(|pair| {
+            make_link(
+                &pair.account,
+                &pair.linked_account,
+                "gas_payer",
+                pair.detail.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the accounts paying gas for too many accounts to own them, their transactions
+/// carry the keysets of their users.
+pub fn gas_stations(pairs: &[AccountPair]) -> HashSet<String> {
+    let mut paid_accounts: HashMap<&str, usize> = HashMap::new();
+    for pair in pairs.iter() {
+        *paid_accounts.entry(pair.account.as_str()).or_default() += 1;
+    }
+    paid_accounts
+        .into_iter()
+        .filter(|(_, count)| *count > MAX_GAS_PAYER_ACCOUNTS)
+        .map(|(account, _)| account.to_string())
+        .collect()
+}
+
+/// Returns the keyset as `pred:key1,key2` with sorted keys, `None` if it isn't a keyset.
+fn keyset_name(keyset: &DataKeyset) -> Option<(String, Vec<String>)> {
+    let mut keys = keyset
+        .keys
+        .as_array()?
+        .iter()
+        .map(|key| key.as_str().map(|key| key.to_string()))
+        .collect::<Option<Vec<String>>>()?;
+    if keys.is_empty() {
+        return None;
+    }
+    keys.sort();
+    keys.dedup();
+    let pred = keyset.pred.as_deref().unwrap_or("keys-all");
+    Some((format!("{}:{}", pred, keys.join(",")), keys))
+}
+
+/// Returns the `k:` accounts of the single key keysets, `keyset_links` links senders to those
+/// of them that exist.
+pub fn key_accounts(keysets: &[DataKeyset]) -> Vec<String> {
+    keysets
+        .iter()
+        .filter_map(keyset_name)
+        .filter(|(_, keys)| keys.len() == 1)
+        .map(|(_, keys)| format!("k:{}", keys[0]))
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+/// Links the senders using the same keyset together, and senders to the `k:` account of the
+/// key of their single key keysets. Gas stations and senders using too many keysets are
+/// left out.
+pub fn keyset_links(
+    keysets: &[DataKeyset],
+    existing: &HashSet<String>,
+    gas_stations: &HashSet<String>,
+) -> Vec<ClusterLink> {
+    let named = keysets
+        .iter()
+        .filter(|keyset| !gas_stations.contains(&keyset.sender))
+        .filter_map(|keyset| keyset_name(keyset).map(|(name, keys)| (keyset, name, keys)))
+        .collect::<Vec<(&DataKeyset, String, Vec<String>)>>();
+    let mut sender_keysets: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (keyset, name, _) in named.iter() {
+        sender_keysets
+            .entry(keyset.sender.as_str())
+            .or_default()
+            .insert(name.as_str());
+    }
+    let mut senders: BTreeMap<String, BTreeMap<&str, &str>> = BTreeMap::new();
+    let mut keys_by_name = HashMap::new();
+    for (keyset, name, keys) in named.iter() {
+        if sender_keysets[keyset.sender.as_str()].len() > MAX_SENDER_KEYSETS {
+            continue;
+        }
+        senders
+            .entry(name.clone())
+            .or_default()
+            .insert(&keyset.sender, &keyset.request_key);
+        keys_by_name.insert(name.clone(), keys.clone());
+    }
+    let mut links = vec![];
+    for (name, senders) in senders.iter() {
+        if senders.len() > MAX_KEYSET_ACCOUNTS {
+            continue;
+        }
+        let first = senders.keys().next().unwrap();
+        for sender in senders.keys().skip(1) {
+            links.push(make_link(
+                sender,
+                first,
+                "shared_keyset",
+                Some(name.clone()),
+            ));
+        }
+        let keys = &keys_by_name[name];
+        if keys.len() == 1 {
+            let key_account = format!("k:{}", keys[0]);
+            if !existing.contains(&key_account) {
+                continue;
+            }
+            for (sender, request_key) in senders.iter() {
+                if **sender != key_account {
+                    links.push(make_link(
+                        sender,
+                        &key_account,
+                        "public_key",
+                        Some(request_key.to_string()),
+                    ));
+                }
+            }
+        }
+    }
+    links
+}
+
+/// Collects the evidence, merges linked accounts and replaces the stored clusters. Returns
+/// the number of clustered accounts.
+pub fn refresh_clusters(repository: &ClustersRepository) -> Result<usize, DbError> {
+    let gas_payers = repository.find_gas_payers()?;
+    let mut links = gas_payer_links(&gas_payers);
+    log::info!("Found {} gas payer links", links.len());
+
+    let keysets = repository.find_data_keysets()?;
+    let existing = repository.find_existing_accounts(&key_accounts(&keysets))?;
+    let stations = gas_stations(&gas_payers);
+    let count = links.len();
+    links.extend(keyset_links(&keysets, &existing, &stations));
+    log::info!("Found {} keyset links", links.len() - count);
+
+    let count = links.len();
+    links.extend(
+        repository
+            .find_funding_sources()?
+            .iter()
+            .map(|pair| make_link(&pair.account, &pair.linked_account, "funding_source", None)),
+    );
+    log::info!("Found {} funding source links", links.len() - count);
+
+    let mut union_find = UnionFind::default();
+    for link in links.iter() {
+        union_find.union(&link.account, &link.linked_account);
+    }
+    let clusters = union_find.clusters();
+    repository.replace(&clusters, &links)?;
+    log::info!("Stored {} clustered accounts", clusters.len());
+    Ok(clusters.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_pair(account: &str, linked_account: &str) -> AccountPair {
+        AccountPair {
+            account: account.to_string(),
+            linked_account: linked_account.to_string(),
+            detail: None,
+        }
+    }
+
+    fn make_keyset(sender: &str, keys: &[&str]) -> DataKeyset {
+        DataKeyset {
+            sender: sender.to_string(),
+            keys: serde_json::json!(keys),
+            pred: Some("keys-all".to_string()),
+            request_key: format!("request-key-{}", sender),
+        }
+    }
+
+    #[test]
+    fn test_union_find() {
+        let mut union_find = UnionFind::default();
+        union_find.union("bob", "alice");
+        union_find.union("carol", "dave");
+        union_find.union("dave", "bob");
+        union_find.union("erin", "frank");
+        let clusters = union_find
+            .clusters()
+            .into_iter()
+            .map(|cluster| (cluster.account, cluster.cluster_id))
+            .collect::<Vec<(String, String)>>();
+        let expected = [
+            ("alice", "alice"),
+            ("bob", "alice"),
+            ("carol", "alice"),
+            ("dave", "alice"),
+            ("erin", "erin"),
+            ("frank", "erin"),
+        ];
+        assert_eq!(
+            clusters,
+            expected
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<(String, String)>>()
+        );
+    }
+
+    #[test]
+    fn test_gas_payer_links() {
+        let mut pairs = vec![make_pair("alice", "alice-savings")];
+        // A gas station paying for many accounts doesn't own them
+        for i in 0..=MAX_GAS_PAYER_ACCOUNTS {
+            pairs.push(make_pair("gas-station", &format!("user-{}", i)));
+        }
+        let links = gas_payer_links(&pairs);
+        assert_eq!(
+            links,
+            vec![make_link("alice", "alice-savings", "gas_payer", None)]
+        );
+    }
+
+    #[test]
+    fn test_keyset_links() {
+        let keysets = vec![
+            make_keyset("alice", &["abc"]),
+            make_keyset("alice-savings", &["abc"]),
+            make_keyset("bob", &["def", "ghi"]),
+            make_keyset("k:missing", &["jkl"]),
+        ];
+        assert_eq!(key_accounts(&keysets).len(), 2);
+        let existing = HashSet::from(["k:abc".to_string()]);
+        let links = keyset_links(&keysets, &existing, &HashSet::new());
+        assert_eq!(
+            links,
+            vec![
+                make_link(
+                    "alice-savings",
+                    "alice",
+                    "shared_keyset",
+                    Some("keys-all:abc".to_string())
+                ),
+                make_link(
+                    "alice",
+                    "k:abc",
+                    "public_key",
+                    Some("request-key-alice".to_string())
+                ),
+                make_link(
+                    "alice-savings",
+                    "k:abc",
+                    "public_key",
+                    Some("request-key-alice-savings".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyset_links_skip_account_creators() {
+        let mut pairs = vec![];
+        let mut keysets = vec![];
+        // A gas station sends the transactions creating the accounts of its users
+        for i in 0..=MAX_GAS_PAYER_ACCOUNTS {
+            pairs.push(make_pair("gas-station", &format!("user-{}", i)));
+            keysets.push(make_keyset("gas-station", &[format!("key-{}", i).as_str()]));
+        }
+        // So does a faucet paying its own gas
+        for i in 0..=MAX_SENDER_KEYSETS {
+            keysets.push(make_keyset("faucet", &[format!("key-{}", i).as_str()]));
+        }
+        let existing = (0..=MAX_SENDER_KEYSETS)
+            .map(|i| format!("k:key-{}", i))
+            .collect::<HashSet<String>>();
+        let stations = gas_stations(&pairs);
+        assert_eq!(stations, HashSet::from(["gas-station".to_string()]));
+        assert!(keyset_links(&keysets, &existing, &stations).is_empty());
+    }
+}
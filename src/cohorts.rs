@@ -0,0 +1,229 @@
+use chrono::{Datelike, NaiveDateTime};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use crate::db::DbError;
+use crate::models::{CohortActivity, CohortRetention, CohortSize};
+use crate::repository::CohortsRepository;
+
+/// Length of cohorts and of retention periods, weeks start on Monday as in Postgres
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CohortInterval {
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for CohortInterval {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "day" => Ok(CohortInterval::Day),
+            "week" => Ok(CohortInterval::Week),
+            "month" => Ok(CohortInterval::Month),
+            _ => Err(format!("Unknown cohort interval: {}", value)),
+        }
+    }
+}
+
+impl CohortInterval {
+    /// Name of the interval, as stored and as given to `date_trunc`
+    pub fn name(&self) -> &'static str {
+        match self {
+            CohortInterval::Day => "day",
+            CohortInterval::Week => "week",
+            CohortInterval::Month => "month",
+        }
+    }
+
+    /// Number of intervals between the start of a cohort and `time`
+    fn periods_between(&self, cohort_start: NaiveDateTime, time: NaiveDateTime) -> i64 {
+        match self {
+            CohortInterval::Day => (time - cohort_start).num_days(),
+            CohortInterval::Week => (time - cohort_start).num_weeks(),
+            CohortInterval::Month => {
+                (time.year() - cohort_start.year()) as i64 * 12 + time.month() as i64
+                    - cohort_start.month() as i64
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cohort {
+    pub start: NaiveDateTime,
+    pub size: i64,
+    /// Accounts of the cohort active in every period since the cohort started, the first
+    /// period being the cohort's own
+    pub active_accounts: Vec<i64>,
+    /// Share of the cohort active in every period
+    pub retention: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CohortMatrix {
+    pub module: String,
+    pub interval: String,
+    pub cohorts: Vec<Cohort>,
+}
+
+/// Computes the retention of every cohort of new accounts for the module and replaces the
+/// stored rows. Returns the number of cohorts.
+pub fn refresh_cohorts(
+    repository: &CohortsRepository,
+    module: &str,
+    interval: CohortInterval,
+) -> Result<usize, DbError> {
+    let sizes = repository.find_cohort_sizes(module, interval.name())?;
+    let activity = repository.find_cohort_activity(module, interval.name())?;
+    let rows = retention_rows(module, interval, &sizes, &activity);
+    repository.replace(module, interval.name(), &rows)?;
+    Ok(sizes.len())
+}
+
+/// Turns the activity of the cohorts into retention rows. Every cohort gets a row for its
+/// first period, even without activity, so its size is known.
+pub fn retention_rows(
+    module: &str,
+    interval: CohortInterval,
+    sizes: &[CohortSize],
+    activity: &[CohortActivity],
+) -> Vec<CohortRetention> {
+    let mut active: HashMap<(NaiveDateTime, i64), i64> = HashMap::new();
+    for row in activity.iter() {
+        let period = interval.periods_between(row.cohort_start, row.period_start);
+        *active.entry((row.cohort_start, period)).or_default() += row.accounts;
+    }
+    let sizes = sizes
+        .iter()
+        .map(|size| (size.cohort_start, size.accounts))
+        .collect::<BTreeMap<NaiveDateTime, i64>>();
+    for cohort_start in sizes.keys() {
+        active.entry((*cohort_start, 0)).or_default();
+    }
+    let mut rows = active
+        .into_iter()
+        .filter_map(|((cohort_start, period), active_accounts)| {
+            sizes.get(&cohort_start).map(|cohort_size| CohortRetention {
+                module: module.to_string(),
+                cohort_interval: interval.name().to_string(),
+                cohort_start,
+                period,
+                cohort_size: *cohort_size,
+                active_accounts,
+            })
+        })
+        .collect::<Vec<CohortRetention>>();
+    rows.sort_by_key(|row| (row.cohort_start, row.period));
+    rows
+}
+
+/// Returns the stored cohorts as a matrix, with a value for every period up to the one
+/// containing `now`.
+pub fn cohort_matrix(
+    module: &str,
+    interval: CohortInterval,
+    rows: &[CohortRetention],
+    now: NaiveDateTime,
+) -> CohortMatrix {
+    let mut cohorts: BTreeMap<NaiveDateTime, Cohort> = BTreeMap::new();
+    for row in rows.iter() {
+        let cohort = cohorts.entry(row.cohort_start).or_insert_with(|| {
+            let periods = interval.periods_between(row.cohort_start, now).max(0) + 1;
+            Cohort {
+                start: row.cohort_start,
+                size: row.cohort_size,
+                active_accounts: vec![0; periods as usize],
+                retention: vec![],
+            }
+        });
+        if let Some(active) = cohort.active_accounts.get_mut(row.period as usize) {
+            *active = row.active_accounts;
+        }
+    }
+    let cohorts = cohorts
+        .into_values()
+        .map(|mut cohort| {
+            cohort.retention = cohort
+                .active_accounts
+                .iter()
+                .map(|active| match cohort.size {
+                    0 => 0.0,
+                    size => *active as f64 / size as f64,
+                })
+                .collect();
+            cohort
+        })
+        .collect();
+    CohortMatrix {
+        module: module.to_string(),
+        interval: interval.name().to_string(),
+        cohorts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::time;
+
+    fn make_activity(cohort_start: &str, period_start: &str, accounts: i64) -> CohortActivity {
+        CohortActivity {
+            cohort_start: time(cohort_start),
+            period_start: time(period_start),
+            accounts,
+        }
+    }
+
+    #[test]
+    fn test_retention_matrix() {
+        let sizes = vec![
+            CohortSize {
+                cohort_start: time("2023-10-02 00:00:00"),
+                accounts: 10,
+            },
+            CohortSize {
+                cohort_start: time("2023-10-09 00:00:00"),
+                accounts: 4,
+            },
+        ];
+        let activity = vec![
+            make_activity("2023-10-02 00:00:00", "2023-10-02 00:00:00", 10),
+            make_activity("2023-10-02 00:00:00", "2023-10-16 00:00:00", 5),
+        ];
+        let rows = retention_rows("coin", CohortInterval::Week, &sizes, &activity);
+        let periods = rows
+            .iter()
+            .map(|row| (row.period, row.cohort_size, row.active_accounts))
+            .collect::<Vec<(i64, i64, i64)>>();
+        assert_eq!(periods, [(0, 10, 10), (2, 10, 5), (0, 4, 0)]);
+
+        let matrix = cohort_matrix(
+            "coin",
+            CohortInterval::Week,
+            &rows,
+            time("2023-10-18 12:00:00"),
+        );
+        assert_eq!(matrix.cohorts.len(), 2);
+        assert_eq!(matrix.cohorts[0].active_accounts, [10, 0, 5]);
+        assert_eq!(matrix.cohorts[0].retention, [1.0, 0.0, 0.5]);
+        assert_eq!(matrix.cohorts[1].active_accounts, [0, 0]);
+    }
+
+    #[test]
+    fn test_periods_between() {
+        let start = time("2023-11-01 00:00:00");
+        assert_eq!(
+            CohortInterval::Month.periods_between(start, time("2024-02-15 00:00:00")),
+            3
+        );
+        assert_eq!(
+            CohortInterval::Day.periods_between(start, time("2023-11-03 23:59:59")),
+            2
+        );
+        assert_eq!("week".parse::<CohortInterval>(), Ok(CohortInterval::Week));
+        assert!("year".parse::<CohortInterval>().is_err());
+    }
+}
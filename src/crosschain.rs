@@ -0,0 +1,202 @@
+use chrono::{Duration, Utc};
+
+use crate::db::DbError;
+use crate::repository::StuckCrossChainTransfersRepository;
+
+/// Looks for cross-chain transfers with a successful step 0 which are older than `min_age`
+/// and weren't completed on the target chain, and stores them for the `/crosschain/stuck`
+/// endpoint. Previous results are replaced.
+pub fn detect_stuck_transfers(
+    repository: &StuckCrossChainTransfersRepository,
+    min_age: Duration,
+) -> Result<usize, DbError> {
+    let before = std::time::Instant::now();
+    let found = repository.refresh(Utc::now().naive_utc() - min_age)?;
+    log::info!(
+        "Found {} stuck cross-chain transfers in {} ms",
+        found,
+        before.elapsed().as_millis()
+    );
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::{Event, Transaction, Transfer};
+    use crate::repository::{
+        BlocksRepository, CrossChainTransfersRepository, EventsRepository, TransactionsRepository,
+        TransfersRepository,
+    };
+    use crate::test_utils::{make_block, make_event, make_transaction, make_transfer, time};
+    use chrono::NaiveDateTime;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_crosschain_transfers_view() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let events_repository = EventsRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let crosschain_repository = CrossChainTransfersRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 10, "block-0"), make_block(1, 12, "block-1")])
+            .unwrap();
+        events_repository
+            .insert_batch(&[Event {
+                block: "block-0".to_string(),
+                height: 10,
+                request_key: "request-key-10-0".to_string(),
+                ..make_event(
+                    "coin",
+                    "TRANSFER_XCHAIN",
+                    serde_json::json!(["alice", "bob", 10.0, "1"]),
+                )
+            }])
+            .unwrap();
+        transfers_repository
+            .insert_batch(&[Transfer {
+                pact_id: Some("pact-id".to_string()),
+                ..make_transfer("block-0", 10, 0, "alice", "", "10.0")
+            }])
+            .unwrap();
+
+        let pending = crosschain_repository
+            .find_by_pact_id("pact-id")
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.status, "pending");
+        assert_eq!(pending.sender, "alice");
+        assert_eq!(pending.receiver, Some("bob".to_string()));
+        assert_eq!(pending.source_chain_id, 0);
+        assert_eq!(pending.target_chain_id, Some(1));
+        assert_eq!(pending.target_request_key, None);
+        assert_eq!(
+            crosschain_repository.find_pending("alice").unwrap().len(),
+            1
+        );
+
+        transfers_repository
+            .insert_batch(&[Transfer {
+                chain_id: 1,
+                pact_id: Some("pact-id".to_string()),
+                ..make_transfer("block-1", 12, 0, "", "bob", "10.0")
+            }])
+            .unwrap();
+        let completed = crosschain_repository
+            .find_by_pact_id("pact-id")
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.status, "completed");
+        assert_eq!(completed.receiver, Some("bob".to_string()));
+        assert_eq!(
+            completed.target_request_key,
+            Some("request-key-12-0".to_string())
+        );
+        assert_eq!(completed.target_height, Some(12));
+        assert!(crosschain_repository
+            .find_pending("alice")
+            .unwrap()
+            .is_empty());
+        // The continuation isn't a cross-chain transfer on its own
+        assert!(crosschain_repository.find_pending("").unwrap().is_empty());
+
+        events_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_detect_stuck_transfers() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let events_repository = EventsRepository { pool: pool.clone() };
+        let transactions_repository = TransactionsRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let stuck_repository = StuckCrossChainTransfersRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 10, "block-0"), make_block(1, 12, "block-1")])
+            .unwrap();
+        // Step 0 of three cross-chain transfers: the first one is never continued, the
+        // second one is completed on chain 1 and the third one is too recent
+        let step_0 = |idx: i64, pact_id: &str, creation_time: NaiveDateTime| Transaction {
+            creation_time,
+            pact_id: Some(pact_id.to_string()),
+            step: Some(0),
+            ..make_transaction("block-0", 10, &format!("request-key-10-{}", idx))
+        };
+        let mut conn = pool.get().unwrap();
+        transactions_repository
+            .insert_batch_with_conn(
+                &[
+                    step_0(0, "pact-1", time("2023-01-01 00:00:00")),
+                    step_0(1, "pact-2", time("2023-01-01 00:00:00")),
+                    step_0(2, "pact-3", Utc::now().naive_utc()),
+                    Transaction {
+                        chain_id: 1,
+                        pact_id: Some("pact-2".to_string()),
+                        step: Some(1),
+                        ..make_transaction("block-1", 12, "request-key-12-0")
+                    },
+                ],
+                &mut conn,
+            )
+            .unwrap();
+        let burn = |idx: i64, pact_id: &str, from: &str| Transfer {
+            pact_id: Some(pact_id.to_string()),
+            ..make_transfer("block-0", 10, idx, from, "", "10.0")
+        };
+        transfers_repository
+            .insert_batch(&[
+                burn(0, "pact-1", "alice"),
+                burn(1, "pact-2", "carol"),
+                burn(2, "pact-3", "dave"),
+            ])
+            .unwrap();
+        events_repository
+            .insert_batch(&[Event {
+                block: "block-0".to_string(),
+                height: 10,
+                request_key: "request-key-10-0".to_string(),
+                ..make_event(
+                    "coin",
+                    "TRANSFER_XCHAIN",
+                    serde_json::json!(["alice", "bob", 10.0, "1"]),
+                )
+            }])
+            .unwrap();
+
+        assert_eq!(
+            detect_stuck_transfers(&stuck_repository, Duration::hours(1)).unwrap(),
+            1
+        );
+        let stuck = stuck_repository.find(None, Utc::now().naive_utc()).unwrap();
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].pact_id, "pact-1");
+        assert_eq!(stuck[0].sender, "alice");
+        assert_eq!(stuck[0].receiver, Some("bob".to_string()));
+        assert_eq!(stuck[0].target_chain_id, Some(1));
+        assert_eq!(
+            stuck_repository
+                .find(Some("bob".to_string()), Utc::now().naive_utc())
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(stuck_repository
+            .find(Some("carol".to_string()), Utc::now().naive_utc())
+            .unwrap()
+            .is_empty());
+
+        stuck_repository.delete_all().unwrap();
+        events_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        transactions_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
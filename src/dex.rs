@@ -0,0 +1,469 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, NaiveDateTime};
+use diesel::PgConnection;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::DbError;
+use crate::handlers::{EventFilter, EventHandler};
+use crate::models::{Block, Candle, Event, LiquidityChange, PoolReserve, Swap};
+use crate::repository::DexRepository;
+use crate::tokens::parse_decimal;
+
+/// Exchange modules of kaddex (ecko) and kdswap, both derived from the kadenaswap contracts.
+pub const EXCHANGE_MODULES: [&str; 2] = ["kaddex.exchange", "kdlaunch.kdswap-exchange"];
+
+const PRICE_SCALE: i64 = 18;
+
+pub struct DexHandler {
+    pub repository: DexRepository,
+}
+
+impl EventHandler for DexHandler {
+    fn name(&self) -> &str {
+        "dex"
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::new(
+            &EXCHANGE_MODULES,
+            &["SWAP", "UPDATE", "ADD_LIQUIDITY", "REMOVE_LIQUIDITY"],
+        )
+    }
+
+    fn process(
+        &self,
+        events: &[Event],
+        blocks: &[Block],
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError> {
+        let creation_times = blocks
+            .iter()
+            .map(|block| (block.hash.as_str(), block.creation_time))
+            .collect::<HashMap<&str, NaiveDateTime>>();
+        let mut swaps = vec![];
+        let mut reserves = vec![];
+        let mut liquidity_changes = vec![];
+        for event in events.iter() {
+            let creation_time = match creation_times.get(event.block.as_str()) {
+                Some(creation_time) => *creation_time,
+                None => continue,
+            };
+            let parsed = match event.name.as_str() {
+                "SWAP" => make_swap(event, creation_time).map(|swap| swaps.push(swap)),
+                "UPDATE" => {
+                    make_reserve(event, creation_time).map(|reserve| reserves.push(reserve))
+                }
+                "ADD_LIQUIDITY" | "REMOVE_LIQUIDITY" => make_liquidity_change(event, creation_time)
+                    .map(|change| liquidity_changes.push(change)),
+                _ => continue,
+            };
+            if parsed.is_none() {
+                log::warn!(
+                    "Unable to parse {} event in {}: {}",
+                    event.qual_name,
+                    event.request_key,
+                    event.params
+                );
+            }
+        }
+        self.repository.insert_swaps_with_conn(&swaps, conn)?;
+        self.repository.insert_reserves_with_conn(&reserves, conn)?;
+        self.repository
+            .insert_liquidity_changes_with_conn(&liquidity_changes, conn)?;
+        Ok(())
+    }
+
+    fn rollback_with_conn(&self, block: &Block, conn: &mut PgConnection) -> Result<(), DbError> {
+        self.repository
+            .delete_all_by_block_with_conn(&block.hash, conn)?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), DbError> {
+        self.repository.delete_all()?;
+        Ok(())
+    }
+}
+
+/// Returns the OHLC candles of the pair over buckets of the given interval. Buckets without
+/// swaps are omitted.
+pub fn candles(
+    repository: &DexRepository,
+    pair: &str,
+    interval: Duration,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    module: Option<&str>,
+) -> Result<Vec<Candle>, DbError> {
+    let swaps = repository.find_swaps(pair, from, to, module)?;
+    Ok(aggregate_candles(&swaps, interval))
+}
+
+/// Expects the swaps ordered by time, swaps without a price are skipped.
+fn aggregate_candles(swaps: &[Swap], interval: Duration) -> Vec<Candle> {
+    let interval_secs = interval.num_seconds();
+    let mut candles: BTreeMap<i64, Candle> = BTreeMap::new();
+    for swap in swaps.iter() {
+        let price = match &swap.price {
+            Some(price) => price,
+            None => continue,
+        };
+        let volume = match swap.pair.starts_with(&format!("{}:", swap.token_in)) {
+            true => &swap.amount_in,
+            false => &swap.amount_out,
+        };
+        let timestamp = swap.creation_time.timestamp();
+        let start = timestamp - timestamp.rem_euclid(interval_secs);
+        let candle = candles.entry(start).or_insert_with(|| {
+            let start_time = NaiveDateTime::from_timestamp_opt(start, 0).unwrap();
+            Candle {
+                start_time,
+                end_time: start_time + interval,
+                open: price.clone(),
+                high: price.clone(),
+                low: price.clone(),
+                close: price.clone(),
+                volume: BigDecimal::zero(),
+                swaps: 0,
+            }
+        });
+        if price > &candle.high {
+            candle.high = price.clone();
+        }
+        if price < &candle.low {
+            candle.low = price.clone();
+        }
+        candle.close = price.clone();
+        candle.volume += volume;
+        candle.swaps += 1;
+    }
+    candles.into_values().collect()
+}
+
+/// Pairs are keyed by their tokens sorted by name, like `get-pair-key` of the exchange.
+/// Returns the key and whether `token` is the first token of the pair.
+fn pair_key(token: &str, other: &str) -> (String, bool) {
+    match token < other {
+        true => (format!("{}:{}", token, other), true),
+        false => (format!("{}:{}", other, token), false),
+    }
+}
+
+/// Tokens are module references, `{"refName": {"namespace": ..., "name": ...}, ...}`.
+fn parse_module_ref(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => Some(name.clone()),
+        Value::Object(object) => {
+            let ref_name = object.get("refName")?;
+            let name = ref_name["name"].as_str()?;
+            match ref_name["namespace"].as_str() {
+                Some(namespace) => Some(format!("{}.{}", namespace, name)),
+                None => Some(name.to_string()),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `SWAP (sender receiver in token-in out token-out)`, the price is the price of the first
+/// token of the pair in the second one.
+fn make_swap(event: &Event, creation_time: NaiveDateTime) -> Option<Swap> {
+    let params = event.params.as_array()?;
+    let trader = params.first()?.as_str()?.to_string();
+    let receiver = params.get(1)?.as_str()?.to_string();
+    let amount_in = parse_decimal(params.get(2)?)?;
+    let token_in = parse_module_ref(params.get(3)?)?;
+    let amount_out = parse_decimal(params.get(4)?)?;
+    let token_out = parse_module_ref(params.get(5)?)?;
+    let (pair, selling_token0) = pair_key(&token_in, &token_out);
+    let (amount0, amount1) = match selling_token0 {
+        true => (&amount_in, &amount_out),
+        false => (&amount_out, &amount_in),
+    };
+    let price = match amount0.is_zero() {
+        true => None,
+        false => Some((amount1 / amount0).with_scale(PRICE_SCALE)),
+    };
+    Some(Swap {
+        block: event.block.clone(),
+        chain_id: event.chain_id,
+        height: event.height,
+        idx: event.idx,
+        request_key: event.request_key.clone(),
+        creation_time,
+        module: event.module.clone(),
+        pair,
+        trader,
+        receiver,
+        token_in,
+        amount_in,
+        token_out,
+        amount_out,
+        price,
+    })
+}
+
+/// `UPDATE (pair reserve0 reserve1)`
+fn make_reserve(event: &Event, creation_time: NaiveDateTime) -> Option<PoolReserve> {
+    let params = event.params.as_array()?;
+    Some(PoolReserve {
+        block: event.block.clone(),
+        chain_id: event.chain_id,
+        height: event.height,
+        idx: event.idx,
+        request_key: event.request_key.clone(),
+        creation_time,
+        module: event.module.clone(),
+        pair: params.first()?.as_str()?.to_string(),
+        reserve0: parse_decimal(params.get(1)?)?,
+        reserve1: parse_decimal(params.get(2)?)?,
+    })
+}
+
+/// `ADD_LIQUIDITY (sender token0 token1 amount0 amount1 ...)` and the same for
+/// `REMOVE_LIQUIDITY`, amounts are swapped if the tokens aren't in pair order.
+fn make_liquidity_change(event: &Event, creation_time: NaiveDateTime) -> Option<LiquidityChange> {
+    let params = event.params.as_array()?;
+    let account = params.first()?.as_str()?.to_string();
+    let token0 = parse_module_ref(params.get(1)?)?;
+    let token1 = parse_module_ref(params.get(2)?)?;
+    let amount0 = parse_decimal(params.get(3)?)?;
+    let amount1 = parse_decimal(params.get(4)?)?;
+    let (pair, in_order) = pair_key(&token0, &token1);
+    let (amount0, amount1) = match in_order {
+        true => (amount0, amount1),
+        false => (amount1, amount0),
+    };
+    Some(LiquidityChange {
+        block: event.block.clone(),
+        chain_id: event.chain_id,
+        height: event.height,
+        idx: event.idx,
+        request_key: event.request_key.clone(),
+        creation_time,
+        module: event.module.clone(),
+        pair,
+        kind: match event.name.as_str() {
+            "ADD_LIQUIDITY" => "add".to_string(),
+            _ => "remove".to_string(),
+        },
+        account,
+        amount0,
+        amount1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repository::BlocksRepository;
+    use crate::test_utils::{self, time};
+    use serial_test::serial;
+    use std::str::FromStr;
+
+    fn make_event(name: &str, params: Value) -> Event {
+        Event {
+            chain_id: 2,
+            height: 100,
+            idx: 1,
+            ..test_utils::make_event("kaddex.exchange", name, params)
+        }
+    }
+
+    fn module_ref(namespace: Option<&str>, name: &str) -> Value {
+        serde_json::json!({
+            "refName": { "namespace": namespace, "name": name },
+            "refSpec": [{ "namespace": null, "name": "fungible-v2" }]
+        })
+    }
+
+    #[test]
+    fn test_make_swap() {
+        let event = make_event(
+            "SWAP",
+            serde_json::json!([
+                "alice",
+                "alice",
+                { "decimal": "50.0" },
+                module_ref(Some("kaddex"), "kdx"),
+                10,
+                module_ref(None, "coin")
+            ]),
+        );
+        let swap = make_swap(&event, time("2023-10-01 00:00:00")).unwrap();
+        assert_eq!(swap.pair, "coin:kaddex.kdx");
+        assert_eq!(swap.token_in, "kaddex.kdx");
+        assert_eq!(swap.token_out, "coin");
+        // 10 KDA for 50 KDX, so one KDA costs 5 KDX
+        assert_eq!(swap.price.unwrap(), BigDecimal::from(5));
+    }
+
+    #[test]
+    fn test_make_reserve_and_liquidity_change() {
+        let event = make_event(
+            "UPDATE",
+            serde_json::json!(["coin:kaddex.kdx", { "decimal": "1000.5" }, 2000]),
+        );
+        let reserve = make_reserve(&event, time("2023-10-01 00:00:00")).unwrap();
+        assert_eq!(reserve.pair, "coin:kaddex.kdx");
+        assert_eq!(reserve.reserve0, BigDecimal::from_str("1000.5").unwrap());
+
+        let event = make_event(
+            "REMOVE_LIQUIDITY",
+            serde_json::json!([
+                "bob",
+                module_ref(Some("kaddex"), "kdx"),
+                module_ref(None, "coin"),
+                20,
+                4
+            ]),
+        );
+        let change = make_liquidity_change(&event, time("2023-10-01 00:00:00")).unwrap();
+        assert_eq!(change.kind, "remove");
+        assert_eq!(change.pair, "coin:kaddex.kdx");
+        assert_eq!(change.amount0, BigDecimal::from(4));
+        assert_eq!(change.amount1, BigDecimal::from(20));
+    }
+
+    #[test]
+    fn test_aggregate_candles() {
+        let make = |creation_time: &str, token_in: &str, amount_in: i64, price: i64| Swap {
+            block: "block".to_string(),
+            chain_id: 2,
+            height: 100,
+            idx: 1,
+            request_key: "request-key".to_string(),
+            creation_time: time(creation_time),
+            module: "kaddex.exchange".to_string(),
+            pair: "coin:kaddex.kdx".to_string(),
+            trader: "alice".to_string(),
+            receiver: "alice".to_string(),
+            token_in: token_in.to_string(),
+            amount_in: BigDecimal::from(amount_in),
+            token_out: "other".to_string(),
+            amount_out: BigDecimal::from(amount_in * price),
+            price: Some(BigDecimal::from(price)),
+        };
+        let swaps = vec![
+            make("2023-10-01 00:10:00", "coin", 1, 5),
+            make("2023-10-01 00:20:00", "coin", 2, 7),
+            make("2023-10-01 00:50:00", "coin", 1, 4),
+            make("2023-10-01 02:00:00", "kaddex.kdx", 3, 6),
+        ];
+        let candles = aggregate_candles(&swaps, Duration::hours(1));
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].start_time, time("2023-10-01 00:00:00"));
+        assert_eq!(candles[0].open, BigDecimal::from(5));
+        assert_eq!(candles[0].high, BigDecimal::from(7));
+        assert_eq!(candles[0].low, BigDecimal::from(4));
+        assert_eq!(candles[0].close, BigDecimal::from(4));
+        assert_eq!(candles[0].volume, BigDecimal::from(4));
+        assert_eq!(candles[0].swaps, 3);
+        assert_eq!(candles[1].start_time, time("2023-10-01 02:00:00"));
+        // KDX was sold, the volume is the amount of KDA received
+        assert_eq!(candles[1].volume, BigDecimal::from(18));
+    }
+
+    #[test]
+    #[serial]
+    fn test_process_and_rollback() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let handler = DexHandler {
+            repository: DexRepository { pool: pool.clone() },
+        };
+        let blocks = vec![
+            Block {
+                creation_time: time("2023-10-01 00:10:00"),
+                ..test_utils::make_block(2, 100, "block-100")
+            },
+            Block {
+                creation_time: time("2023-10-01 00:40:00"),
+                ..test_utils::make_block(2, 101, "block-101")
+            },
+        ];
+        blocks_repository.insert_batch(&blocks).unwrap();
+        let event = |block: &Block, idx: i64, name: &str, params: Value| Event {
+            block: block.hash.clone(),
+            height: block.height,
+            idx,
+            ..make_event(name, params)
+        };
+        let swap = |amount_out: i64| {
+            serde_json::json!([
+                "alice",
+                "alice",
+                10,
+                module_ref(None, "coin"),
+                amount_out,
+                module_ref(Some("kaddex"), "kdx")
+            ])
+        };
+        let events = vec![
+            event(&blocks[0], 0, "SWAP", swap(50)),
+            event(
+                &blocks[0],
+                1,
+                "UPDATE",
+                serde_json::json!(["coin:kaddex.kdx", 1000, 5000]),
+            ),
+            event(
+                &blocks[0],
+                2,
+                "ADD_LIQUIDITY",
+                serde_json::json!([
+                    "bob",
+                    module_ref(None, "coin"),
+                    module_ref(Some("kaddex"), "kdx"),
+                    10,
+                    50
+                ]),
+            ),
+            event(&blocks[1], 0, "SWAP", swap(40)),
+        ];
+        let mut conn = pool.get().unwrap();
+        handler.process(&events, &blocks, &mut conn).unwrap();
+
+        let (from, to) = (time("2023-10-01 00:00:00"), time("2023-10-02 00:00:00"));
+        let swaps = handler
+            .repository
+            .find_swaps("coin:kaddex.kdx", from, to, None)
+            .unwrap();
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].price.clone().unwrap(), BigDecimal::from(5));
+        let hourly = candles(
+            &handler.repository,
+            "coin:kaddex.kdx",
+            Duration::hours(1),
+            from,
+            to,
+            None,
+        )
+        .unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].open, BigDecimal::from(5));
+        assert_eq!(hourly[0].close, BigDecimal::from(4));
+
+        handler.rollback_with_conn(&blocks[1], &mut conn).unwrap();
+        let swaps = handler
+            .repository
+            .find_swaps("coin:kaddex.kdx", from, to, None)
+            .unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].block, "block-100");
+        // The swap, the reserve snapshot and the liquidity change of the first block
+        assert_eq!(
+            handler
+                .repository
+                .delete_all_by_block_with_conn("block-100", &mut conn)
+                .unwrap(),
+            3
+        );
+
+        handler.repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
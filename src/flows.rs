@@ -0,0 +1,211 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::DbError;
+use crate::models::GroupFlow;
+use crate::repository::AnalyticsRepository;
+
+pub const DEFAULT_FLOW_GROUPS: usize = 20;
+pub const MAX_FLOW_GROUPS: usize = 100;
+
+/// Group of accounts on one side of the diagram. Every group appears once as a `source` and
+/// once as a `target`, so flows in both directions don't make cycles.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SankeyNode {
+    pub name: String,
+    /// `label`, `cluster` or `other`
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// `source` or `target`
+    pub side: String,
+}
+
+/// Transfers from the `source` node to the `target` node, given as indexes in `nodes`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SankeyLink {
+    pub source: usize,
+    pub target: usize,
+    pub value: BigDecimal,
+    pub transfers: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SankeyFlows {
+    pub nodes: Vec<SankeyNode>,
+    pub links: Vec<SankeyLink>,
+}
+
+type GroupKey = (String, String);
+
+fn other() -> GroupKey {
+    ("other".to_string(), "other".to_string())
+}
+
+/// Returns the transfers of the module between account groups in the window, as a Sankey
+/// diagram of the `max_groups` groups moving the largest amounts.
+pub fn token_flows(
+    repository: &AnalyticsRepository,
+    module: &str,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    chain_id: Option<i64>,
+    max_groups: usize,
+) -> Result<SankeyFlows, DbError> {
+    let flows = repository.find_group_flows(module, from, to, chain_id)?;
+    Ok(sankey(&flows, max_groups))
+}
+
+/// Keeps the `max_groups` groups with the largest volume sent and received, merges the
+/// others into `other` and leaves out transfers within a group, except within `other`.
+pub fn sankey(flows: &[GroupFlow], max_groups: usize) -> SankeyFlows {
+    let mut volumes: HashMap<GroupKey, BigDecimal> = HashMap::new();
+    let mut categories: HashMap<GroupKey, Option<String>> = HashMap::new();
+    for flow in flows.iter() {
+        if flow.from_kind == flow.to_kind && flow.from_group == flow.to_group {
+            continue;
+        }
+        let groups = [
+            (&flow.from_kind, &flow.from_group, &flow.from_category),
+            (&flow.to_kind, &flow.to_group, &flow.to_category),
+        ];
+        for (kind, name, category) in groups {
+            if kind == "other" {
+                continue;
+            }
+            let key = (kind.clone(), name.clone());
+            *volumes.entry(key.clone()).or_insert_with(BigDecimal::zero) += &flow.amount;
+            categories.insert(key, category.clone());
+        }
+    }
+    let mut ranked = volumes.into_iter().collect::<Vec<(GroupKey, BigDecimal)>>();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(max_groups);
+    let kept = ranked
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect::<Vec<GroupKey>>();
+    let group = |kind: &str, name: &str| {
+        let key = (kind.to_string(), name.to_string());
+        match kept.contains(&key) {
+            true => key,
+            false => other(),
+        }
+    };
+
+    let mut totals: BTreeMap<(GroupKey, GroupKey), (BigDecimal, i64)> = BTreeMap::new();
+    for flow in flows.iter() {
+        let from = group(&flow.from_kind, &flow.from_group);
+        let to = group(&flow.to_kind, &flow.to_group);
+        if from == to && from != other() {
+            continue;
+        }
+        let (amount, transfers) = totals
+            .entry((from, to))
+            .or_insert_with(|| (BigDecimal::zero(), 0));
+        *amount += &flow.amount;
+        *transfers += flow.transfers;
+    }
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|a, b| (b.1).0.cmp(&(a.1).0));
+
+    let mut nodes = vec![];
+    let mut indexes: HashMap<(GroupKey, &str), usize> = HashMap::new();
+    let mut node_index = |key: &GroupKey, side: &'static str, nodes: &mut Vec<SankeyNode>| {
+        *indexes.entry((key.clone(), side)).or_insert_with(|| {
+            nodes.push(SankeyNode {
+                name: key.1.clone(),
+                kind: key.0.clone(),
+                category: categories.get(key).cloned().flatten(),
+                side: side.to_string(),
+            });
+            nodes.len() - 1
+        })
+    };
+    let mut links = vec![];
+    for ((from, to), (value, transfers)) in totals {
+        let source = node_index(&from, "source", &mut nodes);
+        let target = node_index(&to, "target", &mut nodes);
+        links.push(SankeyLink {
+            source,
+            target,
+            value,
+            transfers,
+        });
+    }
+    SankeyFlows { nodes, links }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_flow(from: (&str, &str), to: (&str, &str), amount: i64) -> GroupFlow {
+        let category = |kind: &str| match kind {
+            "label" => Some("exchange".to_string()),
+            _ => None,
+        };
+        GroupFlow {
+            from_group: from.1.to_string(),
+            from_kind: from.0.to_string(),
+            from_category: category(from.0),
+            to_group: to.1.to_string(),
+            to_kind: to.0.to_string(),
+            to_category: category(to.0),
+            transfers: 1,
+            amount: BigDecimal::from(amount),
+        }
+    }
+
+    #[test]
+    fn test_sankey() {
+        let exchange = ("label", "Exchange");
+        let whale = ("cluster", "k:whale");
+        let minnow = ("cluster", "k:minnow");
+        let other = ("other", "other");
+        let flows = vec![
+            make_flow(whale, exchange, 100),
+            make_flow(exchange, whale, 40),
+            // Hot wallet to cold wallet
+            make_flow(exchange, exchange, 500),
+            make_flow(minnow, exchange, 5),
+            make_flow(other, exchange, 10),
+            make_flow(other, other, 3),
+        ];
+        let sankey = sankey(&flows, 2);
+        let names = sankey
+            .nodes
+            .iter()
+            .map(|node| format!("{} {}", node.side, node.name))
+            .collect::<Vec<String>>();
+        assert_eq!(
+            names,
+            [
+                "source k:whale",
+                "target Exchange",
+                "source Exchange",
+                "target k:whale",
+                "source other",
+                "target other"
+            ]
+        );
+        assert_eq!(sankey.nodes[1].category, Some("exchange".to_string()));
+        let links = sankey
+            .links
+            .iter()
+            .map(|link| (link.source, link.target, link.value.clone(), link.transfers))
+            .collect::<Vec<(usize, usize, BigDecimal, i64)>>();
+        assert_eq!(
+            links,
+            [
+                (0, 1, BigDecimal::from(100), 1),
+                (2, 3, BigDecimal::from(40), 1),
+                // The minnow is merged into other
+                (4, 1, BigDecimal::from(15), 2),
+                (4, 5, BigDecimal::from(3), 1),
+            ]
+        );
+    }
+}
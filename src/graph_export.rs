@@ -0,0 +1,276 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::analytics::{GraphNode, WalletConnection, WalletGraph};
+
+/// File formats wallet graphs can be exported to. `Csv` is a pair of files, nodes and edges,
+/// that can be loaded with `neo4j-admin database import`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    GraphMl,
+    Gexf,
+    Dot,
+    Csv,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "graphml" => Ok(GraphFormat::GraphMl),
+            "gexf" => Ok(GraphFormat::Gexf),
+            "dot" => Ok(GraphFormat::Dot),
+            "csv" => Ok(GraphFormat::Csv),
+            _ => Err(format!("Unknown graph format: {}", value)),
+        }
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_time(edge: &WalletConnection) -> String {
+    edge.last_transfer_time
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+fn depth(node: &GraphNode) -> String {
+    node.depth
+        .map(|depth| depth.to_string())
+        .unwrap_or_default()
+}
+
+pub fn to_graphml(graph: &WalletGraph) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    out.push_str("  <key id=\"depth\" for=\"node\" attr.name=\"depth\" attr.type=\"int\"/>\n");
+    out.push_str("  <key id=\"total_transfers\" for=\"edge\" attr.name=\"total_transfers\" attr.type=\"long\"/>\n");
+    out.push_str("  <key id=\"total_amount\" for=\"edge\" attr.name=\"total_amount\" attr.type=\"double\"/>\n");
+    out.push_str("  <key id=\"last_transfer_time\" for=\"edge\" attr.name=\"last_transfer_time\" attr.type=\"string\"/>\n");
+    out.push_str("  <graph id=\"wallets\" edgedefault=\"directed\">\n");
+    for node in graph.nodes.iter() {
+        let account = escape_xml(&node.account);
+        match node.depth {
+            Some(depth) => writeln!(
+                out,
+                "    <node id=\"{}\"><data key=\"depth\">{}</data></node>",
+                account, depth
+            ),
+            None => writeln!(out, "    <node id=\"{}\"/>", account),
+        }
+        .unwrap();
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        writeln!(
+            out,
+            "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"><data key=\"total_transfers\">{}</data><data key=\"total_amount\">{}</data><data key=\"last_transfer_time\">{}</data></edge>",
+            i,
+            escape_xml(&edge.from_address),
+            escape_xml(&edge.to_address),
+            edge.total_transfers,
+            edge.total_amount,
+            format_time(edge)
+        )
+        .unwrap();
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+/// Edges are weighted by total amount, as Gephi uses the weight for layouts and filters.
+pub fn to_gexf(graph: &WalletGraph) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+    out.push_str("  <graph defaultedgetype=\"directed\">\n");
+    out.push_str("    <attributes class=\"node\">\n");
+    out.push_str("      <attribute id=\"depth\" title=\"depth\" type=\"integer\"/>\n");
+    out.push_str("    </attributes>\n");
+    out.push_str("    <attributes class=\"edge\">\n");
+    out.push_str(
+        "      <attribute id=\"total_transfers\" title=\"total_transfers\" type=\"long\"/>\n",
+    );
+    out.push_str("      <attribute id=\"last_transfer_time\" title=\"last_transfer_time\" type=\"string\"/>\n");
+    out.push_str("    </attributes>\n");
+    out.push_str("    <nodes>\n");
+    for node in graph.nodes.iter() {
+        let account = escape_xml(&node.account);
+        match node.depth {
+            Some(depth) => writeln!(
+                out,
+                "      <node id=\"{}\" label=\"{}\"><attvalues><attvalue for=\"depth\" value=\"{}\"/></attvalues></node>",
+                account, account, depth
+            ),
+            None => writeln!(out, "      <node id=\"{}\" label=\"{}\"/>", account, account),
+        }
+        .unwrap();
+    }
+    out.push_str("    </nodes>\n");
+    out.push_str("    <edges>\n");
+    for (i, edge) in graph.edges.iter().enumerate() {
+        writeln!(
+            out,
+            "      <edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"><attvalues><attvalue for=\"total_transfers\" value=\"{}\"/><attvalue for=\"last_transfer_time\" value=\"{}\"/></attvalues></edge>",
+            i,
+            escape_xml(&edge.from_address),
+            escape_xml(&edge.to_address),
+            edge.total_amount,
+            edge.total_transfers,
+            format_time(edge)
+        )
+        .unwrap();
+    }
+    out.push_str("    </edges>\n");
+    out.push_str("  </graph>\n</gexf>\n");
+    out
+}
+
+/// Graphviz only accepts integer edge weights, so the total amount is rounded for the weight
+/// and kept as is in the label.
+pub fn to_dot(graph: &WalletGraph) -> String {
+    let mut out = String::from("digraph wallets {\n");
+    for node in graph.nodes.iter() {
+        match node.depth {
+            Some(depth) => writeln!(
+                out,
+                "  \"{}\" [depth={}];",
+                escape_dot(&node.account),
+                depth
+            ),
+            None => writeln!(out, "  \"{}\";", escape_dot(&node.account)),
+        }
+        .unwrap();
+    }
+    for edge in graph.edges.iter() {
+        writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [weight={}, transfers={}, label=\"{}\", last_transfer_time=\"{}\"];",
+            escape_dot(&edge.from_address),
+            escape_dot(&edge.to_address),
+            edge.total_amount.round(0),
+            edge.total_transfers,
+            edge.total_amount,
+            format_time(edge)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Nodes file of the Neo4j CSV export, accounts get the `Account` label.
+pub fn to_csv_nodes(graph: &WalletGraph) -> String {
+    let mut out = String::from("account:ID,depth:int,:LABEL\n");
+    for node in graph.nodes.iter() {
+        writeln!(out, "{},{},Account", escape_csv(&node.account), depth(node)).unwrap();
+    }
+    out
+}
+
+/// Relationships file of the Neo4j CSV export, connections get the `TRANSFERRED` type.
+pub fn to_csv_edges(graph: &WalletGraph) -> String {
+    let mut out = String::from(
+        ":START_ID,:END_ID,:TYPE,total_transfers:long,total_amount:double,last_transfer_time:datetime\n",
+    );
+    for edge in graph.edges.iter() {
+        writeln!(
+            out,
+            "{},{},TRANSFERRED,{},{},{}",
+            escape_csv(&edge.from_address),
+            escape_csv(&edge.to_address),
+            edge.total_transfers,
+            edge.total_amount,
+            format_time(edge)
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDateTime;
+
+    fn make_graph() -> WalletGraph {
+        WalletGraph {
+            nodes: vec![
+                GraphNode {
+                    account: "k:alice".to_string(),
+                    depth: Some(0),
+                },
+                GraphNode {
+                    account: "bob \"the builder\", <b>".to_string(),
+                    depth: Some(1),
+                },
+            ],
+            edges: vec![WalletConnection {
+                from_address: "k:alice".to_string(),
+                to_address: "bob \"the builder\", <b>".to_string(),
+                total_transfers: 3,
+                total_amount: BigDecimal::from(12),
+                last_transfer_time: NaiveDateTime::parse_from_str(
+                    "2023-10-01 12:00:00",
+                    "%Y-%m-%d %H:%M:%S",
+                )
+                .unwrap(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_xml_formats() {
+        let graph = make_graph();
+        let graphml = to_graphml(&graph);
+        assert!(graphml.contains("<node id=\"bob &quot;the builder&quot;, &lt;b&gt;\"><data key=\"depth\">1</data></node>"));
+        assert!(graphml.contains("<data key=\"last_transfer_time\">2023-10-01T12:00:00Z</data>"));
+        let gexf = to_gexf(&graph);
+        assert!(gexf.contains(
+            "source=\"k:alice\" target=\"bob &quot;the builder&quot;, &lt;b&gt;\" weight=\"12\""
+        ));
+    }
+
+    #[test]
+    fn test_dot_and_csv_formats() {
+        let graph = make_graph();
+        let dot = to_dot(&graph);
+        assert!(
+            dot.contains("\"k:alice\" -> \"bob \\\"the builder\\\", <b>\" [weight=12, transfers=3")
+        );
+        let mut fractional = make_graph();
+        fractional.edges[0].total_amount = BigDecimal::from_str("12.6").unwrap();
+        assert!(to_dot(&fractional).contains("[weight=13, transfers=3, label=\"12.6\""));
+        assert_eq!(
+            to_csv_nodes(&graph),
+            "account:ID,depth:int,:LABEL\nk:alice,0,Account\n\"bob \"\"the builder\"\", <b>\",1,Account\n"
+        );
+        let edges = to_csv_edges(&graph);
+        assert!(edges.ends_with(
+            "k:alice,\"bob \"\"the builder\"\", <b>\",TRANSFERRED,3,12,2023-10-01T12:00:00Z\n"
+        ));
+        assert_eq!("csv".parse::<GraphFormat>(), Ok(GraphFormat::Csv));
+        assert!("png".parse::<GraphFormat>().is_err());
+    }
+}
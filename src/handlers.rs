@@ -0,0 +1,340 @@
+use diesel::{Connection, PgConnection};
+use std::collections::HashSet;
+
+use crate::db::{DbError, DbPool};
+use crate::dex::DexHandler;
+use crate::marmalade::MarmaladeHandler;
+use crate::models::{Block, Event};
+use crate::repository::{BlocksRepository, DexRepository, EventsRepository, NftRepository};
+
+/// Selects the events passed to a handler. Empty lists don't restrict the selection, so an
+/// empty `names` list matches every event of the given modules. `qual_names` are patterns
+/// where `*` matches any sequence of characters, eg. `free.*.SWAP`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventFilter {
+    pub modules: Vec<String>,
+    pub names: Vec<String>,
+    pub qual_names: Vec<String>,
+}
+
+impl EventFilter {
+    pub fn new(modules: &[&str], names: &[&str]) -> Self {
+        EventFilter {
+            modules: modules.iter().map(|e| e.to_string()).collect(),
+            names: names.iter().map(|e| e.to_string()).collect(),
+            qual_names: vec![],
+        }
+    }
+
+    pub fn with_qual_names(patterns: &[String]) -> Self {
+        EventFilter {
+            modules: vec![],
+            names: vec![],
+            qual_names: patterns.to_vec(),
+        }
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.modules.is_empty() || self.modules.contains(&event.module))
+            && (self.names.is_empty() || self.names.contains(&event.name))
+            && (self.qual_names.is_empty()
+                || self
+                    .qual_names
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, &event.qual_name)))
+    }
+}
+
+/// Matches `value` against a pattern where `*` stands for any sequence of characters.
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let parts = pattern.split('*').collect::<Vec<&str>>();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if value.len() < first.len() + last.len() || !value.starts_with(first) || !value.ends_with(last)
+    {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Converts a `qual_names` pattern to a SQL LIKE pattern.
+pub fn like_pattern(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
+
+/// Derives data from the events of specific modules. Handlers are called with the events of
+/// newly indexed blocks, in the same database transaction that inserts the events, and with
+/// every block removed by a reorg.
+pub trait EventHandler: Send + Sync {
+    /// Unique name, used to select the handler when rebuilding
+    fn name(&self) -> &str;
+
+    fn filter(&self) -> EventFilter;
+
+    /// Called only with events matching the filter, `blocks` are the blocks those events
+    /// belong to. Blocks are passed once, when they are first indexed, and events of other
+    /// blocks must be ignored.
+    fn process(
+        &self,
+        events: &[Event],
+        blocks: &[Block],
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError>;
+
+    /// Undoes the changes made by the events of the block, before they are deleted. Called
+    /// in the same database transaction that deletes the block.
+    fn rollback_with_conn(&self, block: &Block, conn: &mut PgConnection) -> Result<(), DbError>;
+
+    /// Deletes all the data derived by the handler, before a rebuild.
+    fn reset(&self) -> Result<(), DbError>;
+}
+
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<Box<dyn EventHandler>>,
+}
+
+impl HandlerRegistry {
+    pub fn register(&mut self, handler: Box<dyn EventHandler>) {
+        if self.find(handler.name()).is_some() {
+            panic!("Event handler {} is already registered", handler.name());
+        }
+        self.handlers.push(handler);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn EventHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.name() == name)
+            .map(|handler| handler.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.handlers.iter().map(|handler| handler.name()).collect()
+    }
+
+    /// Passes the matching events of the given blocks to every handler. Handlers without
+    /// matching events are skipped.
+    pub fn process_with_conn(
+        &self,
+        events: &[Event],
+        blocks: &[Block],
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError> {
+        let hashes = blocks
+            .iter()
+            .map(|block| block.hash.as_str())
+            .collect::<HashSet<&str>>();
+        for handler in self.handlers.iter() {
+            let filter = handler.filter();
+            let matching = events
+                .iter()
+                .filter(|event| hashes.contains(event.block.as_str()) && filter.matches(event))
+                .cloned()
+                .collect::<Vec<Event>>();
+            if !matching.is_empty() {
+                handler.process(&matching, blocks, conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rollback_with_conn(
+        &self,
+        block: &Block,
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError> {
+        for handler in self.handlers.iter() {
+            handler.rollback_with_conn(block, conn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Registry with the handlers that are part of Bento.
+pub fn default_handlers(pool: &DbPool) -> HandlerRegistry {
+    let mut registry = HandlerRegistry::default();
+    registry.register(Box::new(MarmaladeHandler {
+        repository: NftRepository { pool: pool.clone() },
+    }));
+    registry.register(Box::new(DexHandler {
+        repository: DexRepository { pool: pool.clone() },
+    }));
+    registry
+}
+
+/// Deletes the data of the handler and processes again all the matching events stored in the
+/// `events` table, `batch_size` block heights at a time, chain by chain. Every batch is
+/// processed in its own transaction. Returns the number of processed events.
+pub fn rebuild(
+    handler: &dyn EventHandler,
+    blocks: &BlocksRepository,
+    events: &EventsRepository,
+    batch_size: i64,
+) -> Result<usize, DbError> {
+    handler.reset()?;
+    let filter = handler.filter();
+    let mut processed = 0;
+    for chain_id in events.find_chain_ids()? {
+        let max_height = events.find_max_height(chain_id)?;
+        let mut min_height = 0;
+        while min_height <= max_height {
+            let batch = events
+                .find_by_filter(&filter, min_height, min_height + batch_size - 1, chain_id)?
+                .into_iter()
+                .filter(|event| filter.matches(event))
+                .collect::<Vec<Event>>();
+            if !batch.is_empty() {
+                let hashes = batch
+                    .iter()
+                    .map(|event| event.block.clone())
+                    .collect::<HashSet<String>>()
+                    .into_iter()
+                    .collect::<Vec<String>>();
+                let batch_blocks = blocks.find_by_hashes(&hashes)?;
+                let mut conn = events.pool.get().unwrap();
+                conn.transaction(|conn| handler.process(&batch, &batch_blocks, conn))?;
+                processed += batch.len();
+            }
+            min_height += batch_size;
+        }
+        log::info!(
+            "Rebuilt {} for chain {}, {} events processed so far",
+            handler.name(),
+            chain_id,
+            processed
+        );
+    }
+    Ok(processed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::Transaction;
+    use crate::repository::TransactionsRepository;
+    use crate::test_utils;
+    use serial_test::serial;
+
+    fn make_event(module: &str, name: &str) -> Event {
+        test_utils::make_event(module, name, serde_json::json!([]))
+    }
+
+    #[test]
+    fn test_event_filter_matches() {
+        let filter = EventFilter::new(&["kaddex.exchange"], &["SWAP"]);
+        assert!(filter.matches(&make_event("kaddex.exchange", "SWAP")));
+        assert!(!filter.matches(&make_event("kaddex.exchange", "UPDATE")));
+        assert!(!filter.matches(&make_event("coin", "SWAP")));
+
+        let filter = EventFilter::new(&["marmalade.ledger"], &[]);
+        assert!(filter.matches(&make_event("marmalade.ledger", "MINT")));
+        assert!(!filter.matches(&make_event("marmalade-v2.ledger", "MINT")));
+
+        let filter = EventFilter::with_qual_names(&["free.*.SWAP".to_string()]);
+        assert!(filter.matches(&make_event("free.dex", "SWAP")));
+        assert!(!filter.matches(&make_event("kaddex.exchange", "SWAP")));
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("coin.TRANSFER", "coin.TRANSFER"));
+        assert!(!matches_pattern("coin.TRANSFER", "coin.TRANSFERS"));
+        assert!(matches_pattern("*.SWAP", "kaddex.exchange.SWAP"));
+        assert!(matches_pattern(
+            "free.*.*_LIQUIDITY",
+            "free.dex.ADD_LIQUIDITY"
+        ));
+        assert!(!matches_pattern("free.*.SWAP", "free.SWAP"));
+        assert!(matches_pattern("*", "anything"));
+    }
+
+    #[test]
+    fn test_like_pattern() {
+        assert_eq!(
+            like_pattern("free.*.ADD_LIQUIDITY"),
+            "free.%.ADD\\_LIQUIDITY"
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_find_by_filter_orders_by_tx_position() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let events_repository = EventsRepository { pool: pool.clone() };
+        let transactions_repository = TransactionsRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[
+                test_utils::make_block(0, 10, "block-10"),
+                test_utils::make_block(0, 11, "block-11"),
+            ])
+            .unwrap();
+        // The second transaction of the block sorts first by request key
+        let transaction = |request_key: &str, position: i64| Transaction {
+            position: Some(position),
+            ..test_utils::make_transaction("block-10", 10, request_key)
+        };
+        let mut conn = pool.get().unwrap();
+        transactions_repository
+            .insert_batch_with_conn(
+                &[
+                    transaction("request-key-b", 0),
+                    transaction("request-key-a", 1),
+                ],
+                &mut conn,
+            )
+            .unwrap();
+        let event = |block: &str, height: i64, request_key: &str, idx: i64| Event {
+            block: block.to_string(),
+            height,
+            idx,
+            request_key: request_key.to_string(),
+            ..make_event("kaddex.exchange", "SWAP")
+        };
+        events_repository
+            .insert_batch(&[
+                event("block-11", 11, "request-key-c", 0),
+                event("block-10", 10, "request-key-a", 0),
+                event("block-10", 10, "request-key-b", 1),
+                event("block-10", 10, "request-key-b", 0),
+            ])
+            .unwrap();
+
+        let filter = EventFilter::new(&["kaddex.exchange"], &["SWAP"]);
+        let found = events_repository
+            .find_by_filter(&filter, 10, 11, 0)
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.request_key, event.idx))
+            .collect::<Vec<(String, i64)>>();
+        assert_eq!(
+            found,
+            vec![
+                ("request-key-b".to_string(), 0),
+                ("request-key-b".to_string(), 1),
+                ("request-key-a".to_string(), 0),
+                ("request-key-c".to_string(), 0),
+            ]
+        );
+
+        events_repository.delete_all().unwrap();
+        transactions_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::analytics::replay_lots;
+use crate::db::DbError;
+use crate::models::{TokenHoldingStats, TokenHoldingStatsPeriod, Transfer};
+use crate::repository::{AnalyticsRepository, HoldingStatsRepository};
+
+const ACCOUNTS_PER_BATCH: usize = 1000;
+const THRESHOLD_DAYS: [i64; 3] = [30, 90, 365];
+const PERCENTILES: [f64; 4] = [0.25, 0.5, 0.75, 0.9];
+const PERIOD_SECS: i64 = 7 * 24 * 3600;
+/// Periods are weeks starting on Monday, 1970-01-05 is the first Monday after the epoch
+const PERIOD_OFFSET_SECS: i64 = 4 * 24 * 3600;
+/// Durations shorter than this are counted per hour, longer ones per day
+const HOURLY_BUCKETS: i64 = 48;
+
+/// Replays the transfers of every account that ever held the module, like
+/// `analytics::holding_periods` does for a single account, and replaces the holding stats
+/// of the module with the results.
+pub fn refresh_holding_stats(
+    analytics: &AnalyticsRepository,
+    repository: &HoldingStatsRepository,
+    module: &str,
+    now: NaiveDateTime,
+) -> Result<TokenHoldingStats, DbError> {
+    let accounts = analytics.find_accounts(module)?;
+    let mut accumulator = HoldingStatsAccumulator::new(now);
+    for chunk in accounts.chunks(ACCOUNTS_PER_BATCH) {
+        let chunk_accounts = chunk.iter().map(|e| e.as_str()).collect::<HashSet<&str>>();
+        let transfers = analytics.find_accounts_transfers(chunk, module)?;
+        let mut transfers_by_account: HashMap<&str, Vec<Transfer>> = HashMap::new();
+        for transfer in transfers.iter() {
+            let mut parties = vec![transfer.from_account.as_str()];
+            if transfer.to_account != transfer.from_account {
+                parties.push(transfer.to_account.as_str());
+            }
+            for account in parties {
+                if chunk_accounts.contains(account) {
+                    transfers_by_account
+                        .entry(account)
+                        .or_default()
+                        .push(transfer.clone());
+                }
+            }
+        }
+        for (account, transfers) in transfers_by_account.iter() {
+            let lots = replay_lots(account, module, transfers, |acquired, disposed, amount| {
+                accumulator.add_disposal(acquired, disposed, amount)
+            });
+            accumulator.add_holder(
+                lots.iter()
+                    .map(|lot| (lot.acquisition_time, &lot.current_amount)),
+            );
+        }
+    }
+    let (stats, history) = accumulator.finish(module);
+    repository.replace(&stats, &history)?;
+    log::info!(
+        "Computed holding stats of {} for {} accounts, {} holders",
+        module,
+        accounts.len(),
+        stats.holders
+    );
+    Ok(stats)
+}
+
+/// Collects the amounts taken from lots and the lots still held. Amounts are converted to
+/// floats, the stats are approximate anyway since durations are bucketed.
+struct HoldingStatsAccumulator {
+    now: NaiveDateTime,
+    holders: i64,
+    held: f64,
+    /// Part of the held amount acquired more than each of `THRESHOLD_DAYS` ago
+    held_longer: [f64; 3],
+    /// Amounts taken from lots per holding duration bucket
+    disposed: Vec<f64>,
+    disposed_by_period: BTreeMap<i64, Vec<f64>>,
+    /// Changes of the amount held at the end of each period, of any age and then older
+    /// than each of `THRESHOLD_DAYS`
+    held_changes: BTreeMap<i64, [f64; 4]>,
+}
+
+impl HoldingStatsAccumulator {
+    fn new(now: NaiveDateTime) -> Self {
+        HoldingStatsAccumulator {
+            now,
+            holders: 0,
+            held: 0.0,
+            held_longer: [0.0; 3],
+            disposed: vec![],
+            disposed_by_period: BTreeMap::new(),
+            held_changes: BTreeMap::new(),
+        }
+    }
+
+    fn add_disposal(
+        &mut self,
+        acquired: NaiveDateTime,
+        disposed: NaiveDateTime,
+        amount: &BigDecimal,
+    ) {
+        let amount = amount.to_f64().unwrap_or(0.0);
+        let bucket = duration_bucket(disposed - acquired);
+        add_to_bucket(&mut self.disposed, bucket, amount);
+        add_to_bucket(
+            self.disposed_by_period
+                .entry(period_start(disposed.timestamp()))
+                .or_default(),
+            bucket,
+            amount,
+        );
+        self.add_held_interval(acquired, Some(disposed), amount);
+    }
+
+    /// Adds the lots of an account with what is left of them
+    fn add_holder<'a, I>(&mut self, lots: I)
+    where
+        I: Iterator<Item = (NaiveDateTime, &'a BigDecimal)>,
+    {
+        let mut holder = false;
+        for (acquired, amount) in lots {
+            let amount = amount.to_f64().unwrap_or(0.0);
+            if amount <= 0.0 {
+                continue;
+            }
+            holder = true;
+            self.held += amount;
+            for (i, days) in THRESHOLD_DAYS.iter().enumerate() {
+                if self.now - acquired >= Duration::days(*days) {
+                    self.held_longer[i] += amount;
+                }
+            }
+            self.add_held_interval(acquired, None, amount);
+        }
+        if holder {
+            self.holders += 1;
+        }
+    }
+
+    /// The amount counts towards the periods ending between its acquisition (plus the age
+    /// threshold) and its disposal.
+    fn add_held_interval(
+        &mut self,
+        acquired: NaiveDateTime,
+        disposed: Option<NaiveDateTime>,
+        amount: f64,
+    ) {
+        let end = disposed.map(|disposed| period_ceil(disposed.timestamp() - PERIOD_SECS));
+        for (i, days) in [0].iter().chain(THRESHOLD_DAYS.iter()).enumerate() {
+            let start = period_ceil(acquired.timestamp() + days * 24 * 3600 - PERIOD_SECS);
+            if let Some(end) = end {
+                if end <= start {
+                    continue;
+                }
+                self.held_changes.entry(end).or_insert([0.0; 4])[i] -= amount;
+            }
+            self.held_changes.entry(start).or_insert([0.0; 4])[i] += amount;
+        }
+    }
+
+    fn finish(self, module: &str) -> (TokenHoldingStats, Vec<TokenHoldingStatsPeriod>) {
+        let [p25_days, median_days, p75_days, p90_days] = percentiles(&self.disposed);
+        let share = |amount: f64| match self.held > 0.0 {
+            true => amount / self.held,
+            false => 0.0,
+        };
+        let stats = TokenHoldingStats {
+            module: module.to_string(),
+            computed_at: self.now,
+            holders: self.holders,
+            held_amount: self.held,
+            p25_days,
+            median_days,
+            p75_days,
+            p90_days,
+            held_30d_share: share(self.held_longer[0]),
+            held_90d_share: share(self.held_longer[1]),
+            held_365d_share: share(self.held_longer[2]),
+        };
+
+        let first = self
+            .held_changes
+            .keys()
+            .chain(self.disposed_by_period.keys())
+            .min()
+            .cloned();
+        let mut history = vec![];
+        let mut held = [0.0; 4];
+        let mut start = match first {
+            Some(first) => first,
+            None => return (stats, history),
+        };
+        // Only complete periods are kept
+        while start + PERIOD_SECS <= self.now.timestamp() {
+            if let Some(changes) = self.held_changes.get(&start) {
+                for (amount, change) in held.iter_mut().zip(changes.iter()) {
+                    *amount += change;
+                }
+            }
+            let amounts = held.map(|amount| amount.max(0.0));
+            let share = |amount: f64| match amounts[0] > 0.0 {
+                true => amount / amounts[0],
+                false => 0.0,
+            };
+            let [p25_days, median_days, p75_days, p90_days] = self
+                .disposed_by_period
+                .get(&start)
+                .map_or([None; 4], |disposed| percentiles(disposed));
+            history.push(TokenHoldingStatsPeriod {
+                module: module.to_string(),
+                period_start: NaiveDateTime::from_timestamp_opt(start, 0).unwrap(),
+                held_amount: amounts[0],
+                p25_days,
+                median_days,
+                p75_days,
+                p90_days,
+                held_30d_share: share(amounts[1]),
+                held_90d_share: share(amounts[2]),
+                held_365d_share: share(amounts[3]),
+            });
+            start += PERIOD_SECS;
+        }
+        (stats, history)
+    }
+}
+
+fn period_start(timestamp: i64) -> i64 {
+    timestamp - (timestamp - PERIOD_OFFSET_SECS).rem_euclid(PERIOD_SECS)
+}
+
+/// First period starting at or after the timestamp
+fn period_ceil(timestamp: i64) -> i64 {
+    match period_start(timestamp) {
+        start if start == timestamp => start,
+        start => start + PERIOD_SECS,
+    }
+}
+
+fn duration_bucket(duration: Duration) -> usize {
+    let hours = duration.num_hours().max(0);
+    match hours < HOURLY_BUCKETS {
+        true => hours as usize,
+        false => (HOURLY_BUCKETS + hours / 24 - 2) as usize,
+    }
+}
+
+/// Lower bound of the bucket in days
+fn bucket_days(bucket: usize) -> f64 {
+    let bucket = bucket as i64;
+    match bucket < HOURLY_BUCKETS {
+        true => bucket as f64 / 24.0,
+        false => (bucket - HOURLY_BUCKETS + 2) as f64,
+    }
+}
+
+fn add_to_bucket(buckets: &mut Vec<f64>, bucket: usize, amount: f64) {
+    if buckets.len() <= bucket {
+        buckets.resize(bucket + 1, 0.0);
+    }
+    buckets[bucket] += amount;
+}
+
+/// Amount weighted `PERCENTILES` of the durations, in days
+fn percentiles(buckets: &[f64]) -> [Option<f64>; 4] {
+    let total = buckets.iter().sum::<f64>();
+    if total <= 0.0 {
+        return [None; 4];
+    }
+    PERCENTILES.map(|percentile| {
+        let mut cumulative = 0.0;
+        for (bucket, amount) in buckets.iter().enumerate() {
+            cumulative += amount;
+            if cumulative >= percentile * total {
+                return Some(bucket_days(bucket));
+            }
+        }
+        buckets.len().checked_sub(1).map(bucket_days)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::time;
+
+    #[test]
+    fn test_duration_buckets() {
+        assert_eq!(duration_bucket(Duration::minutes(30)), 0);
+        assert_eq!(duration_bucket(Duration::hours(47)), 47);
+        assert_eq!(duration_bucket(Duration::hours(48)), 48);
+        assert_eq!(bucket_days(duration_bucket(Duration::days(10))), 10.0);
+        assert_eq!(bucket_days(12), 0.5);
+    }
+
+    #[test]
+    fn test_period_start() {
+        // 2023-10-04 is a Wednesday
+        let start = period_start(time("2023-10-04 12:00:00").timestamp());
+        assert_eq!(start, time("2023-10-02 00:00:00").timestamp());
+        assert_eq!(period_ceil(start), start);
+        assert_eq!(period_ceil(start + 1), start + PERIOD_SECS);
+    }
+
+    #[test]
+    fn test_holding_stats() {
+        let now = time("2023-12-31 00:00:00");
+        let mut accumulator = HoldingStatsAccumulator::new(now);
+        // 30 held for 10 days, 10 for 100 days
+        accumulator.add_disposal(
+            time("2023-01-02 10:00:00"),
+            time("2023-01-12 10:00:00"),
+            &BigDecimal::from(30),
+        );
+        accumulator.add_disposal(
+            time("2023-01-02 10:00:00"),
+            time("2023-04-12 10:00:00"),
+            &BigDecimal::from(10),
+        );
+        accumulator.add_holder(
+            vec![
+                (time("2023-12-20 00:00:00"), &BigDecimal::from(60)),
+                (time("2023-06-01 00:00:00"), &BigDecimal::from(40)),
+            ]
+            .into_iter(),
+        );
+        let (stats, history) = accumulator.finish("coin");
+        assert_eq!(stats.holders, 1);
+        assert_eq!(stats.held_amount, 100.0);
+        assert_eq!(stats.median_days, Some(10.0));
+        assert_eq!(stats.p90_days, Some(100.0));
+        assert_eq!(stats.held_30d_share, 0.4);
+        assert_eq!(stats.held_365d_share, 0.0);
+
+        // Week of 2023-01-02: 40 held at the end, 30 sold on 2023-01-12 during the next week
+        assert_eq!(history[0].period_start, time("2023-01-02 00:00:00"));
+        assert_eq!(history[0].held_amount, 40.0);
+        assert_eq!(history[0].median_days, None);
+        assert_eq!(history[1].held_amount, 10.0);
+        assert_eq!(history[1].median_days, Some(10.0));
+        let last = history.last().unwrap();
+        assert_eq!(last.period_start, time("2023-12-18 00:00:00"));
+        assert_eq!(last.held_amount, 100.0);
+        assert_eq!(last.held_30d_share, 0.4);
+    }
+}
@@ -1,6 +1,7 @@
 use diesel::Connection;
 use diesel::pg::PgConnection;
 //EDIT: added the above lines 
+use bigdecimal::num_bigint::{BigInt, Sign};
 use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use core::panic;
@@ -21,6 +22,8 @@ use super::models::*;
 use super::repository::*;
 use crate::chainweb_client::ChainwebClient;
 use crate::db::DbError;
+use crate::handlers::HandlerRegistry;
+use crate::network;
 use crate::transfers;
 
 pub struct Indexer<'a> {
@@ -29,6 +32,14 @@ pub struct Indexer<'a> {
     pub events: EventsRepository,
     pub transactions: TransactionsRepository,
     pub transfers: TransfersRepository,
+    pub hashrate: HashrateRepository,
+    pub balances: BalancesRepository,
+    pub tokens: TokensRepository,
+    pub supply: SupplySnapshotsRepository,
+    pub wallet_connections: WalletConnectionsRepository,
+    pub activity: ActivityRollupsRepository,
+    pub accounts: AccountsRepository,
+    pub handlers: HandlerRegistry,
 }
 
 // EDIT: ADDED helper function for managing database transactions
@@ -217,35 +228,48 @@ impl<'a> Indexer<'a> {
                 });
         }
 
-        match self.blocks.insert_batch(&blocks) {
-            Ok(_) => {}
-            Err(e) => panic!("Error inserting blocks: {:#?}", e),
-        }
-
         let signed_txs_by_hash = get_signed_txs_from_payloads(&payloads);
+        let positions = get_tx_positions_from_payloads(&payloads);
         let request_keys: Vec<String> = signed_txs_by_hash.keys().map(|e| e.to_string()).collect();
         let tx_results = self
             .fetch_transactions_results(&request_keys[..], chain_id)
             .await?;
-        let txs = get_transactions_from_payload(&signed_txs_by_hash, &tx_results, chain_id);
-        if !txs.is_empty() {
-            match self.transactions.insert_batch(&txs) {
-                Ok(inserted) => log::info!("Inserted {} transactions", inserted),
-                Err(e) => panic!("Error inserting transactions: {:#?}", e),
+        let txs =
+            get_transactions_from_payload(&signed_txs_by_hash, &positions, &tx_results, chain_id);
+        let events = get_events_from_txs(&tx_results, &signed_txs_by_hash, &positions);
+
+        // Blocks are inserted with their transactions, events and derived tables, so blocks
+        // that are already indexed were fully processed. Derived tables and handlers are only
+        // given the blocks inserted now, which aren't in height order when backfilling.
+        match with_transaction(&self.blocks.pool, |conn| -> Result<usize, DbError> {
+            let inserted_blocks = self.blocks.insert_batch_with_conn(&blocks, conn)?;
+            let new_blocks = inserted_blocks
+                .iter()
+                .map(|e| e.hash.clone())
+                .collect::<Vec<String>>();
+            network::refresh_hashrate_with_conn(&blocks, &self.hashrate, conn)?;
+            if !txs.is_empty() {
+                let inserted = self.transactions.insert_batch_with_conn(&txs, conn)?;
+                log::info!("Inserted {} transactions", inserted);
             }
-            let events = get_events_from_txs(&tx_results, &signed_txs_by_hash);
-            if !events.is_empty() {
-                match self.events.insert_batch(&events) {
-                    Ok(inserted) => {
-                        log::info!("Inserted {} events", inserted);
-                        match transfers::process_transfers(&events, &blocks, &self.transfers) {
-                            Ok(_) => {}
-                            Err(e) => panic!("Error updating balances: {:#?}", e),
-                        }
-                    }
-                    Err(e) => panic!("Error inserting events: {:#?}", e),
-                }
+            if events.is_empty() {
+                return Ok(0);
             }
+            let inserted = self.events.insert_batch_with_conn(&events, conn)?;
+            transfers::process_transfers_with_conn(&events, &blocks, &self.transfers, conn)?;
+            self.balances.apply_blocks_with_conn(&new_blocks, 1, conn)?;
+            self.tokens.apply_blocks_with_conn(&new_blocks, 1, conn)?;
+            self.supply.apply_blocks_with_conn(&new_blocks, 1, conn)?;
+            self.wallet_connections
+                .apply_blocks_with_conn(&new_blocks, conn)?;
+            self.activity.apply_blocks_with_conn(&new_blocks, 1, conn)?;
+            self.accounts.apply_blocks_with_conn(&new_blocks, conn)?;
+            self.handlers
+                .process_with_conn(&events, &inserted_blocks, conn)?;
+            Ok(inserted)
+        }) {
+            Ok(inserted) => log::info!("Inserted {} events", inserted),
+            Err(e) => panic!("Error inserting blocks, events and transfers: {:#?}", e),
         }
         Ok(())
     }
@@ -284,8 +308,16 @@ impl<'a> Indexer<'a> {
                 }
             };
 
+            if let Err(e) =
+                network::refresh_hashrate_with_conn(&[saved_block.clone()], &self.hashrate, conn)
+            {
+                log::error!("Failed to refresh hashrate: {:?}", e);
+                return Err(e);
+            }
+
             // Process transactions for the block
             let signed_txs_by_hash = get_signed_txs_from_payload(&payloads[0]);
+            let positions = get_tx_positions_from_payloads(&payloads[..1]);
             let request_keys: Vec<String> = signed_txs_by_hash.keys().map(|e| e.to_string()).collect();
 
             // Measure time taken to get transaction results
@@ -300,10 +332,15 @@ impl<'a> Indexer<'a> {
             log::info!("Elapsed time to get results: {:.2?}", before.elapsed());
 
             // Process and filter transactions
-            let txs = get_transactions_from_payload(&signed_txs_by_hash, &tx_results, chain_id)
-                .into_iter()
-                .filter(|tx| tx.block == saved_block.hash)
-                .collect::<Vec<Transaction>>();
+            let txs = get_transactions_from_payload(
+                &signed_txs_by_hash,
+                &positions,
+                &tx_results,
+                chain_id,
+            )
+            .into_iter()
+            .filter(|tx| tx.block == saved_block.hash)
+            .collect::<Vec<Transaction>>();
 
             // Insert transactions within the same transaction
             if !txs.is_empty() {
@@ -317,7 +354,7 @@ impl<'a> Indexer<'a> {
             }
 
             // Process and insert events
-            let events = get_events_from_txs(&tx_results, &signed_txs_by_hash)
+            let events = get_events_from_txs(&tx_results, &signed_txs_by_hash, &positions)
                 .into_iter()
                 .filter(|e| e.block == saved_block.hash)
                 .collect::<Vec<Event>>();
@@ -327,10 +364,50 @@ impl<'a> Indexer<'a> {
                     Ok(count) => {
                         log::info!("Inserted {} events", count);
                         // Process transfers within the same transaction
-                        if let Err(e) = transfers::process_transfers_with_conn(&events, &[saved_block], &self.transfers, conn) {
+                        if let Err(e) = transfers::process_transfers_with_conn(
+                            &events,
+                            &[saved_block.clone()],
+                            &self.transfers,
+                            conn,
+                        ) {
                             log::error!("Failed to process transfers: {:?}", e);
                             return Err(e);
                         }
+                        let hashes = [saved_block.hash.clone()];
+                        if let Err(e) = self.balances.apply_blocks_with_conn(&hashes, 1, conn) {
+                            log::error!("Failed to update balances: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) = self.tokens.apply_blocks_with_conn(&hashes, 1, conn) {
+                            log::error!("Failed to update tokens: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) = self.supply.apply_blocks_with_conn(&hashes, 1, conn) {
+                            log::error!("Failed to update supply snapshots: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) = self
+                            .wallet_connections
+                            .apply_blocks_with_conn(&hashes, conn)
+                        {
+                            log::error!("Failed to update wallet connections: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) = self.activity.apply_blocks_with_conn(&hashes, 1, conn) {
+                            log::error!("Failed to update activity rollups: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) = self.accounts.apply_blocks_with_conn(&hashes, conn) {
+                            log::error!("Failed to update accounts: {:?}", e);
+                            return Err(e);
+                        }
+                        if let Err(e) =
+                            self.handlers
+                                .process_with_conn(&events, &[saved_block.clone()], conn)
+                        {
+                            log::error!("Failed to process event handlers: {:?}", e);
+                            return Err(e);
+                        }
                     }
                     Err(e) => {
                         log::error!("Failed to insert events: {:?}", e);
@@ -444,12 +521,25 @@ impl<'a> Indexer<'a> {
     }
 
     fn delete_block_data(&self, block: &Block) -> Result<(), DbError> {
-        self.transfers
-            .delete_all_by_block(&block.hash, block.chain_id)?;
-        self.events.delete_all_by_block(&block.hash)?;
-        self.transactions.delete_all_by_block(&block.hash)?;
-        self.blocks.delete_by_hash(&block.hash, block.chain_id)?;
-        Ok(())
+        with_transaction(&self.blocks.pool, |conn| -> Result<(), DbError> {
+            self.balances.revert_block_with_conn(&block.hash, conn)?;
+            self.tokens.revert_block_with_conn(&block.hash, conn)?;
+            self.supply.revert_block_with_conn(&block.hash, conn)?;
+            self.wallet_connections
+                .revert_block_with_conn(&block.hash, conn)?;
+            self.activity.revert_block_with_conn(&block.hash, conn)?;
+            self.accounts.revert_block_with_conn(&block.hash, conn)?;
+            self.handlers.rollback_with_conn(block, conn)?;
+            self.transfers
+                .delete_all_by_block_with_conn(&block.hash, block.chain_id, conn)?;
+            self.events
+                .delete_all_by_block_with_conn(&block.hash, conn)?;
+            self.transactions
+                .delete_all_by_block_with_conn(&block.hash, conn)?;
+            self.blocks
+                .delete_by_hash_with_conn(&block.hash, block.chain_id, conn)?;
+            Ok(())
+        })
     }
 
     async fn fetch_transactions_results(
@@ -499,6 +589,26 @@ fn get_signed_txs_from_payloads(payloads: &[BlockPayload]) -> HashMap<String, Si
         .collect::<HashMap<String, SignedTransaction>>()
 }
 
+/// Returns the position of every transaction in the payload of its block, by request key.
+fn get_tx_positions_from_payloads(payloads: &[BlockPayload]) -> HashMap<String, i64> {
+    payloads
+        .iter()
+        .flat_map(|payload| {
+            payload
+                .transactions
+                .iter()
+                .enumerate()
+                .map(|(position, tx)| {
+                    let tx = serde_json::from_slice::<SignedTransaction>(
+                        &base64_url::decode(&tx).unwrap(),
+                    )
+                    .unwrap();
+                    (tx.hash, position as i64)
+                })
+        })
+        .collect()
+}
+
 fn build_block(header: &BlockHeader, block_payload: &BlockPayload) -> Block {
     let miner_data =
         serde_json::from_slice::<Value>(&base64_url::decode(&block_payload.miner_data).unwrap())
@@ -508,21 +618,34 @@ fn build_block(header: &BlockHeader, block_payload: &BlockPayload) -> Block {
         hash: header.hash.clone(),
         height: header.height as i64,
         parent: header.parent.clone(),
-        weight: BigDecimal::from_str(&header.weight).unwrap_or(BigDecimal::from(0)),
+        weight: decode_pow_value(&header.weight),
         creation_time: NaiveDateTime::from_timestamp_micros(header.creation_time).unwrap(),
         epoch: NaiveDateTime::from_timestamp_micros(header.epoch_start).unwrap(),
         flags: header.feature_flags.clone(),
-        miner: miner_data["account"].to_string(),
+        miner: miner_data["account"].as_str().unwrap_or_default().to_string(),
         nonce: BigDecimal::from_str(&header.nonce).unwrap(),
         payload: block_payload.payload_hash.clone(),
         pow_hash: "".to_string(),
-        predicate: miner_data["predicate"].to_string(),
-        target: bigdecimal::BigDecimal::from(1),
+        predicate: miner_data["predicate"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        target: decode_pow_value(&header.target),
+    }
+}
+
+/// Decodes the base64url encoded, little endian 256 bit integers
+/// used for the target and the weight of block headers.
+pub fn decode_pow_value(value: &str) -> BigDecimal {
+    match base64_url::decode(value) {
+        Ok(bytes) => BigDecimal::new(BigInt::from_bytes_le(Sign::Plus, &bytes), 0),
+        Err(_) => BigDecimal::from(0),
     }
 }
 
 fn get_transactions_from_payload(
     signed_txs: &HashMap<String, SignedTransaction>,
+    positions: &HashMap<String, i64>,
     tx_results: &[PactTransactionResult],
     chain_id: &ChainId,
 ) -> Vec<Transaction> {
@@ -530,7 +653,8 @@ fn get_transactions_from_payload(
         .iter()
         .map(|pact_result| {
             let signed_tx = signed_txs.get(&pact_result.request_key).unwrap();
-            build_transaction(signed_tx, pact_result, chain_id)
+            let position = positions.get(&pact_result.request_key).copied();
+            build_transaction(signed_tx, pact_result, chain_id, position)
         })
         .collect()
 }
@@ -539,6 +663,7 @@ fn build_transaction(
     signed_tx: &SignedTransaction,
     pact_result: &PactTransactionResult,
     chain: &ChainId,
+    position: Option<i64>,
 ) -> Transaction {
     let continuation = pact_result.continuation.clone();
     let command = serde_json::from_str::<Command>(&signed_tx.cmd);
@@ -596,20 +721,32 @@ fn build_transaction(
         step: continuation.map(|e| e["step"].as_i64().unwrap()),
         ttl: command.meta.ttl as i64,
         tx_id: pact_result.tx_id,
+        position,
     };
 }
 
+/// Returns the events of the transactions in block order, results are fetched concurrently
+/// so they come in any order.
 fn get_events_from_txs(
     tx_results: &[PactTransactionResult],
     signed_txs_by_hash: &HashMap<String, SignedTransaction>,
+    positions: &HashMap<String, i64>,
 ) -> Vec<Event> {
-    tx_results
+    let mut events = tx_results
         .iter()
         .flat_map(|pact_result| {
             let signed_tx = signed_txs_by_hash.get(&pact_result.request_key).unwrap();
             build_events(signed_tx, pact_result)
         })
-        .collect()
+        .collect::<Vec<Event>>();
+    events.sort_by_key(|event| {
+        (
+            event.height,
+            positions.get(&event.request_key).copied(),
+            event.idx,
+        )
+    });
+    events
 }
 
 fn build_events(
@@ -666,6 +803,13 @@ mod tests {
         let events = EventsRepository { pool: pool.clone() };
         let transactions = TransactionsRepository { pool: pool.clone() };
         let transfers = TransfersRepository { pool: pool.clone() };
+        let hashrate = HashrateRepository { pool: pool.clone() };
+        let balances = BalancesRepository { pool: pool.clone() };
+        let tokens = TokensRepository { pool: pool.clone() };
+        let supply = SupplySnapshotsRepository { pool: pool.clone() };
+        let wallet_connections = WalletConnectionsRepository { pool: pool.clone() };
+        let activity = ActivityRollupsRepository { pool: pool.clone() };
+        let accounts = AccountsRepository { pool: pool.clone() };
 
         let indexer = Indexer {
             chainweb_client: &client,
@@ -673,6 +817,14 @@ mod tests {
             events: events.clone(),
             transactions: transactions.clone(),
             transfers: transfers.clone(),
+            hashrate: hashrate.clone(),
+            balances: balances.clone(),
+            tokens: tokens.clone(),
+            supply: supply.clone(),
+            wallet_connections: wallet_connections.clone(),
+            activity: activity.clone(),
+            accounts: accounts.clone(),
+            handlers: crate::handlers::default_handlers(&pool),
         };
 
         let orphan_header = BlockHeader {
@@ -724,6 +876,47 @@ mod tests {
         blocks.delete_all().unwrap();
     }
 
+    #[test]
+    fn test_build_block_miner() {
+        let header = BlockHeader {
+            creation_time: 1688902875826238,
+            parent: "mZ3SiegRI9qBY43T3B7VQ82jY40tSgU2E9A7ZGPvXhI".to_string(),
+            height: 3882292,
+            hash: "_6S6n6dhjGw-vVHwIyq8Ulk8VNSlADLchRJCJg4vclM".to_string(),
+            chain_id: ChainId(14),
+            payload_hash: "yRHdjMjoqIeqm8K7WW1c4A77jxi8qP__4x_BjgZoFgE".to_string(),
+            weight: "2CiW41EoGzYIeAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_string(),
+            epoch_start: 1688901280684376,
+            feature_flags: BigDecimal::from(0),
+            adjacents: HashMap::new(),
+            chainweb_version: "mainnet01".to_string(),
+            target: "hvD3dR8UooHyvbpvuIKyu0eALPNztocLHAAAAAAAAAA".to_string(),
+            nonce: "11077503293030185962".to_string(),
+        };
+        let payload = BlockPayload {
+            miner_data: "eyJhY2NvdW50IjoiazplN2Y3MTMwZjM1OWZiMWY4Yzg3ODczYmY4NThhMGU5Y2JjM2MxMDU5ZjYyYWU3MTVlYzcyZTc2MGIwNTVlOWYzIiwicHJlZGljYXRlIjoia2V5cy1hbGwiLCJwdWJsaWMta2V5cyI6WyJlN2Y3MTMwZjM1OWZiMWY4Yzg3ODczYmY4NThhMGU5Y2JjM2MxMDU5ZjYyYWU3MTVlYzcyZTc2MGIwNTVlOWYzIl19".to_string(),
+            outputs_hash: "WrjWEw4Gj-60kcBPY3HZKTT9Gyoh0ZnAjFrL65Fc3GU".to_string(),
+            payload_hash: "yRHdjMjoqIeqm8K7WW1c4A77jxi8qP__4x_BjgZoFgE".to_string(),
+            transactions: vec![],
+            transactions_hash: "9yNSeh7rTW_j1ziKYyubdYUCefnO5K63d5RfPkHQXiM".to_string()
+        };
+        let block = build_block(&header, &payload);
+        assert_eq!(
+            block.miner,
+            "k:e7f7130f359fb1f8c87873bf858a0e9cbc3c1059f62ae715ec72e760b055e9f3"
+        );
+        assert_eq!(block.predicate, "keys-all");
+        assert_eq!(
+            block.target,
+            BigDecimal::from_str("176041566500890765743079240203025667126204291545320436265094")
+                .unwrap()
+        );
+        assert_eq!(
+            block.weight,
+            BigDecimal::from_str("566835450651181697083608").unwrap()
+        );
+    }
+
     #[test]
     fn test_get_signed_txs_from_payloads() {
         let payload = BlockPayload {
@@ -748,6 +941,20 @@ mod tests {
                 sigs: vec![Sig { sig: String::from("43f1212465bdbc41bf0216c26ba332805fa2ad618a20fe65bd4efb559902af69b0c8bed440287c343ffe38ee66b3bf6a1bd376b5781055b92a71fc610304740a")}]
             }),
         ]);
-        assert_eq!(get_signed_txs_from_payloads(&vec![payload]), signed_txs);
+        let payloads = vec![payload];
+        assert_eq!(
+            get_tx_positions_from_payloads(&payloads),
+            HashMap::from([
+                (
+                    String::from("gaD_OZdL3cJKGelC73laoBDJjWJTkstkkjIAIKOOq1U"),
+                    0
+                ),
+                (
+                    String::from("tdZsPK1KjFEwn3Fmm3tTb6DK5XulN1p_ZNzq24pvxfw"),
+                    1
+                ),
+            ])
+        );
+        assert_eq!(get_signed_txs_from_payloads(&payloads), signed_txs);
     }
 }
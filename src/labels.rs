@@ -0,0 +1,421 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::db::DbError;
+use crate::models::{Label, NftSale, Transaction, Transfer};
+use crate::repository::LabelsRepository;
+
+/// Source of the labels imported without one
+pub const DEFAULT_SOURCE: &str = "manual";
+
+/// Label as found in an import file, `source` and `confidence` are optional
+#[derive(Deserialize, Debug)]
+struct LabelRecord {
+    account: String,
+    label: String,
+    category: String,
+    source: Option<String>,
+    confidence: Option<f64>,
+}
+
+impl LabelRecord {
+    fn into_label(self, default_source: &str) -> Result<Label, String> {
+        if self.account.is_empty() || self.label.is_empty() || self.category.is_empty() {
+            return Err(format!("Missing account, label or category: {:?}", self));
+        }
+        let confidence = self.confidence.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(format!(
+                "Invalid confidence {} for {}",
+                confidence, self.account
+            ));
+        }
+        Ok(Label {
+            account: self.account,
+            label: self.label,
+            category: self.category,
+            source: self
+                .source
+                .filter(|source| !source.is_empty())
+                .unwrap_or_else(|| default_source.to_string()),
+            confidence,
+        })
+    }
+}
+
+/// Parses an array of objects with `account`, `label`, `category` and optionally `source`
+/// and `confidence` fields.
+pub fn parse_json(content: &str, default_source: &str) -> Result<Vec<Label>, Box<dyn Error>> {
+    let records: Vec<LabelRecord> = serde_json::from_str(content)?;
+    records
+        .into_iter()
+        .map(|record| record.into_label(default_source).map_err(|e| e.into()))
+        .collect()
+}
+
+/// Splits a CSV line on commas outside of double quotes, `""` is a quote within quotes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Parses a CSV file whose header has `account`, `label` and `category` columns, and
+/// optionally `source` and `confidence` columns, in any order.
+pub fn parse_csv(content: &str, default_source: &str) -> Result<Vec<Label>, Box<dyn Error>> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = match lines.next() {
+        Some((_, line)) => split_csv_line(line),
+        None => return Ok(vec![]),
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let (account, label, category) = match (column("account"), column("label"), column("category"))
+    {
+        (Some(account), Some(label), Some(category)) => (account, label, category),
+        _ => return Err("The header must have account, label and category columns".into()),
+    };
+    let (source, confidence) = (column("source"), column("confidence"));
+    let mut labels = vec![];
+    for (number, line) in lines {
+        let fields = split_csv_line(line);
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .map(|field| field.trim().to_string())
+                .unwrap_or_default()
+        };
+        let confidence = match confidence.map(field).filter(|value| !value.is_empty()) {
+            Some(value) => Some(
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("Line {}: invalid confidence {}", number + 1, value))?,
+            ),
+            None => None,
+        };
+        let record = LabelRecord {
+            account: field(account),
+            label: field(label),
+            category: field(category),
+            source: source.map(field),
+            confidence,
+        };
+        labels.push(
+            record
+                .into_label(default_source)
+                .map_err(|e| format!("Line {}: {}", number + 1, e))?,
+        );
+    }
+    Ok(labels)
+}
+
+/// Imports the labels of a JSON file (ending with `.json`) or a CSV file. Labels without a
+/// source get `default_source`, and a later label of an account replaces an earlier one from
+/// the same source. Returns the number of imported labels.
+pub fn import_labels(
+    repository: &LabelsRepository,
+    path: &str,
+    default_source: &str,
+    replace: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let labels = match path.ends_with(".json") {
+        true => parse_json(&content, default_source)?,
+        false => parse_csv(&content, default_source)?,
+    };
+    // A row can only be updated once per insert
+    let mut indexes = HashMap::new();
+    let mut unique: Vec<Label> = vec![];
+    for label in labels {
+        match indexes.get(&(label.account.clone(), label.source.clone())) {
+            Some(index) => unique[*index] = label,
+            None => {
+                indexes.insert((label.account.clone(), label.source.clone()), unique.len());
+                unique.push(label);
+            }
+        }
+    }
+    repository
+        .import(&unique, replace)
+        .map_err(|e| e as Box<dyn Error>)
+}
+
+/// Returns the labels of the accounts, most confident first. Empty accounts (mints and
+/// burns) are skipped.
+pub fn find_labels<'a, I>(
+    repository: &LabelsRepository,
+    accounts: I,
+) -> Result<HashMap<String, Vec<Label>>, DbError>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let accounts = accounts
+        .into_iter()
+        .filter(|account| !account.is_empty())
+        .cloned()
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
+    if accounts.is_empty() {
+        return Ok(HashMap::new());
+    }
+    repository.find_by_accounts(&accounts)
+}
+
+fn labels_of(labels: &HashMap<String, Vec<Label>>, account: &str) -> Vec<Label> {
+    labels.get(account).cloned().unwrap_or_default()
+}
+
+/// Transfer with the labels of its accounts, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledTransfer {
+    #[serde(flatten)]
+    pub transfer: Transfer,
+    pub from_account_labels: Vec<Label>,
+    pub to_account_labels: Vec<Label>,
+}
+
+impl LabelledTransfer {
+    pub fn new(transfer: Transfer, labels: &HashMap<String, Vec<Label>>) -> Self {
+        LabelledTransfer {
+            from_account_labels: labels_of(labels, &transfer.from_account),
+            to_account_labels: labels_of(labels, &transfer.to_account),
+            transfer,
+        }
+    }
+}
+
+/// Transaction with the labels of its sender, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledTransaction {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub sender_labels: Vec<Label>,
+}
+
+impl LabelledTransaction {
+    pub fn new(transaction: Transaction, labels: &HashMap<String, Vec<Label>>) -> Self {
+        LabelledTransaction {
+            sender_labels: labels_of(labels, &transaction.sender),
+            transaction,
+        }
+    }
+}
+
+/// Value with the labels of its `account`, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledAccount<T> {
+    #[serde(flatten)]
+    pub value: T,
+    pub account_labels: Vec<Label>,
+}
+
+/// Cross-chain transfer with the labels of its sender and receiver, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledCrossChainTransfer<T> {
+    #[serde(flatten)]
+    pub transfer: T,
+    pub sender_labels: Vec<Label>,
+    pub receiver_labels: Vec<Label>,
+}
+
+/// NFT sale with the labels of its seller and buyer, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelledNftSale {
+    #[serde(flatten)]
+    pub sale: NftSale,
+    pub seller_labels: Vec<Label>,
+    pub buyer_labels: Vec<Label>,
+}
+
+/// Value with the labels of the accounts it refers to, keyed by account, as returned by the
+/// API
+#[derive(Debug, Clone, Serialize)]
+pub struct Labelled<T> {
+    #[serde(flatten)]
+    pub value: T,
+    pub labels: HashMap<String, Vec<Label>>,
+}
+
+/// Attaches the labels of their account, returned by `account`, to the values.
+pub fn label_accounts<T, F>(
+    repository: &LabelsRepository,
+    values: Vec<T>,
+    account: F,
+) -> Result<Vec<LabelledAccount<T>>, DbError>
+where
+    F: Fn(&T) -> &String,
+{
+    let labels = find_labels(repository, values.iter().map(&account))?;
+    Ok(values
+        .into_iter()
+        .map(|value| LabelledAccount {
+            account_labels: labels_of(&labels, account(&value)),
+            value,
+        })
+        .collect())
+}
+
+/// Attaches the labels of their sender and receiver, returned by `accounts`, to the
+/// cross-chain transfers.
+pub fn label_crosschain_transfers<T, F>(
+    repository: &LabelsRepository,
+    transfers: Vec<T>,
+    accounts: F,
+) -> Result<Vec<LabelledCrossChainTransfer<T>>, DbError>
+where
+    F: Fn(&T) -> (&String, Option<&String>),
+{
+    let labels = find_labels(
+        repository,
+        transfers.iter().flat_map(|transfer| {
+            let (sender, receiver) = accounts(transfer);
+            std::iter::once(sender).chain(receiver)
+        }),
+    )?;
+    Ok(transfers
+        .into_iter()
+        .map(|transfer| {
+            let (sender, receiver) = accounts(&transfer);
+            LabelledCrossChainTransfer {
+                sender_labels: labels_of(&labels, sender),
+                receiver_labels: receiver
+                    .map(|receiver| labels_of(&labels, receiver))
+                    .unwrap_or_default(),
+                transfer,
+            }
+        })
+        .collect())
+}
+
+/// Attaches the labels of their accounts to the transfers.
+pub fn label_transfers(
+    repository: &LabelsRepository,
+    transfers: Vec<Transfer>,
+) -> Result<Vec<LabelledTransfer>, DbError> {
+    let labels = find_labels(
+        repository,
+        transfers
+            .iter()
+            .flat_map(|transfer| [&transfer.from_account, &transfer.to_account]),
+    )?;
+    Ok(transfers
+        .into_iter()
+        .map(|transfer| LabelledTransfer::new(transfer, &labels))
+        .collect())
+}
+
+/// Attaches the labels of their sellers and buyers to the NFT sales.
+pub fn label_nft_sales(
+    repository: &LabelsRepository,
+    sales: Vec<NftSale>,
+) -> Result<Vec<LabelledNftSale>, DbError> {
+    let labels = find_labels(
+        repository,
+        sales
+            .iter()
+            .flat_map(|sale| std::iter::once(&sale.seller).chain(sale.buyer.as_ref())),
+    )?;
+    Ok(sales
+        .into_iter()
+        .map(|sale| LabelledNftSale {
+            seller_labels: labels_of(&labels, &sale.seller),
+            buyer_labels: sale
+                .buyer
+                .as_ref()
+                .map(|buyer| labels_of(&labels, buyer))
+                .unwrap_or_default(),
+            sale,
+        })
+        .collect())
+}
+
+/// Attaches the labels of their senders to transactions grouped by request key.
+pub fn label_transactions(
+    repository: &LabelsRepository,
+    transactions: HashMap<String, Vec<Transaction>>,
+) -> Result<HashMap<String, Vec<LabelledTransaction>>, DbError> {
+    let labels = find_labels(
+        repository,
+        transactions
+            .values()
+            .flatten()
+            .map(|transaction| &transaction.sender),
+    )?;
+    Ok(transactions
+        .into_iter()
+        .map(|(request_key, transactions)| {
+            let transactions = transactions
+                .into_iter()
+                .map(|transaction| LabelledTransaction::new(transaction, &labels))
+                .collect();
+            (request_key, transactions)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let content = "account,category,label,confidence\n\
+                       k:abc,exchange,\"Binance, hot wallet\",0.9\n\
+                       \n\
+                       k:def,bridge,\"The \"\"Bridge\"\"\",\n";
+        let labels = parse_csv(content, "import").unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                Label {
+                    account: "k:abc".to_string(),
+                    label: "Binance, hot wallet".to_string(),
+                    category: "exchange".to_string(),
+                    source: "import".to_string(),
+                    confidence: 0.9,
+                },
+                Label {
+                    account: "k:def".to_string(),
+                    label: "The \"Bridge\"".to_string(),
+                    category: "bridge".to_string(),
+                    source: "import".to_string(),
+                    confidence: 1.0,
+                },
+            ]
+        );
+        assert!(parse_csv("account,label\nk:abc,Binance\n", "import").is_err());
+        assert!(parse_csv("account,label,category,confidence\nk:abc,a,b,2\n", "import").is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let content = r#"[
+            {"account": "k:abc", "label": "Binance", "category": "exchange", "source": "explorer"},
+            {"account": "k:def", "label": "Team", "category": "team", "confidence": 0.5}
+        ]"#;
+        let labels = parse_json(content, DEFAULT_SOURCE).unwrap();
+        assert_eq!(labels[0].source, "explorer");
+        assert_eq!(labels[0].confidence, 1.0);
+        assert_eq!(labels[1].source, DEFAULT_SOURCE);
+        assert_eq!(labels[1].confidence, 0.5);
+        assert!(parse_json(r#"[{"account": "k:abc", "label": "Binance"}]"#, "a").is_err());
+    }
+}
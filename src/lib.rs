@@ -1,8 +1,29 @@
+pub mod activity;
+pub mod analytics;
+pub mod balances;
+pub mod behaviour;
 pub mod chainweb_client;
+pub mod clustering;
+pub mod cohorts;
+pub mod crosschain;
 pub mod db;
+pub mod dex;
+pub mod flows;
 pub mod gaps;
+pub mod graph_export;
+pub mod handlers;
+pub mod holding_stats;
 pub mod indexer;
+pub mod labels;
+pub mod mappings;
+pub mod marmalade;
 pub mod models;
+pub mod network;
+pub mod reconcile;
 pub mod repository;
 mod schema;
+pub mod supply;
+#[cfg(test)]
+mod test_utils;
+pub mod tokens;
 pub mod transfers;
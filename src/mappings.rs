@@ -0,0 +1,345 @@
+use chrono::{DateTime, NaiveDateTime};
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::db::{DbError, DbPool};
+use crate::handlers::{EventFilter, EventHandler, HandlerRegistry};
+use crate::models::{Block, Event};
+use crate::repository::MappedEventsRepository;
+use crate::tokens::{parse_decimal, parse_integer};
+
+/// Columns every mapped table starts with, they locate the event.
+const EVENT_COLUMNS: [&str; 6] = [
+    "block",
+    "chain_id",
+    "height",
+    "idx",
+    "request_key",
+    "creation_time",
+];
+
+/// Mapped tables must start with the prefix, so a mapping can't write to the tables of the
+/// indexer.
+const TABLE_PREFIX: &str = "mapped_";
+
+#[derive(Debug, Deserialize)]
+pub struct MappingsConfig {
+    #[serde(rename = "mapping", default)]
+    pub mappings: Vec<Mapping>,
+}
+
+/// Stores the events matching any of the `qual_names` patterns in `table`, one row per event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mapping {
+    pub table: String,
+    pub qual_names: Vec<String>,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Column {
+    pub name: String,
+    /// Position of the value in the event params
+    pub param: usize,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    String,
+    Decimal,
+    Integer,
+    Time,
+}
+
+impl ColumnType {
+    fn sql_type(&self) -> &'static str {
+        match self {
+            ColumnType::String => "TEXT",
+            ColumnType::Decimal => "NUMERIC",
+            ColumnType::Integer => "BIGINT",
+            ColumnType::Time => "TIMESTAMPTZ",
+        }
+    }
+
+    /// Converts a param to a JSON value Postgres can cast to the column type, `None` if the
+    /// param doesn't have the expected form.
+    fn convert(&self, param: &Value) -> Option<Value> {
+        match self {
+            ColumnType::String => match param {
+                Value::String(value) => Some(Value::String(value.clone())),
+                Value::Null => None,
+                value => Some(Value::String(value.to_string())),
+            },
+            ColumnType::Decimal => parse_decimal(param)
+                .or_else(|| parse_integer(param).map(bigdecimal::BigDecimal::from))
+                .map(|value| Value::String(value.to_string())),
+            ColumnType::Integer => parse_integer(param).map(Value::from),
+            ColumnType::Time => {
+                let value = match param {
+                    Value::Object(object) => object
+                        .get("time")
+                        .or_else(|| object.get("timep"))
+                        .and_then(|e| e.as_str()),
+                    value => value.as_str(),
+                }?;
+                DateTime::parse_from_rfc3339(value)
+                    .ok()
+                    .map(|time| Value::String(time.to_rfc3339()))
+            }
+        }
+    }
+}
+
+impl Mapping {
+    fn validate(&self) -> Result<(), String> {
+        if !is_identifier(&self.table) {
+            return Err(format!("Invalid table name: {}", self.table));
+        }
+        if !self.table.starts_with(TABLE_PREFIX) || self.table.len() == TABLE_PREFIX.len() {
+            return Err(format!(
+                "Table name {} doesn't start with {}",
+                self.table, TABLE_PREFIX
+            ));
+        }
+        if self.qual_names.is_empty() {
+            return Err(format!("No qual_names for table {}", self.table));
+        }
+        let mut names = HashSet::new();
+        for column in self.columns.iter() {
+            if !is_identifier(&column.name) || EVENT_COLUMNS.contains(&column.name.as_str()) {
+                return Err(format!(
+                    "Invalid column name {} for table {}",
+                    column.name, self.table
+                ));
+            }
+            if !names.insert(column.name.as_str()) {
+                return Err(format!(
+                    "Duplicate column {} for table {}",
+                    column.name, self.table
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the row of the event as a JSON object keyed by column name. Params that are
+    /// missing or can't be converted are stored as NULL.
+    fn make_row(&self, event: &Event, creation_time: NaiveDateTime) -> Value {
+        let mut row = Map::new();
+        row.insert("block".to_string(), Value::from(event.block.clone()));
+        row.insert("chain_id".to_string(), Value::from(event.chain_id));
+        row.insert("height".to_string(), Value::from(event.height));
+        row.insert("idx".to_string(), Value::from(event.idx));
+        row.insert(
+            "request_key".to_string(),
+            Value::from(event.request_key.clone()),
+        );
+        row.insert(
+            "creation_time".to_string(),
+            Value::from(creation_time.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string()),
+        );
+        for column in self.columns.iter() {
+            let value = event
+                .params
+                .get(column.param)
+                .and_then(|param| column.column_type.convert(param));
+            if value.is_none() {
+                log::warn!(
+                    "Unable to read {} of {} from param {} in {}: {}",
+                    column.name,
+                    self.table,
+                    column.param,
+                    event.request_key,
+                    event.params
+                );
+            }
+            row.insert(column.name.clone(), value.unwrap_or(Value::Null));
+        }
+        Value::Object(row)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_lowercase() || first == '_' => {
+            chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+pub fn parse_config(config: &str) -> Result<Vec<Mapping>, Box<dyn Error>> {
+    let config: MappingsConfig = toml::from_str(config)?;
+    let mut tables = HashSet::new();
+    for mapping in config.mappings.iter() {
+        mapping.validate()?;
+        if !tables.insert(mapping.table.clone()) {
+            return Err(format!("Duplicate table {}", mapping.table).into());
+        }
+    }
+    Ok(config.mappings)
+}
+
+/// Fills the table of a mapping, the handler is named after the table.
+pub struct MappingHandler {
+    pub mapping: Mapping,
+    pub repository: MappedEventsRepository,
+}
+
+impl EventHandler for MappingHandler {
+    fn name(&self) -> &str {
+        &self.mapping.table
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::with_qual_names(&self.mapping.qual_names)
+    }
+
+    fn process(
+        &self,
+        events: &[Event],
+        blocks: &[Block],
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError> {
+        let creation_times = blocks
+            .iter()
+            .map(|block| (block.hash.as_str(), block.creation_time))
+            .collect::<HashMap<&str, NaiveDateTime>>();
+        let rows = events
+            .iter()
+            .filter_map(|event| {
+                creation_times
+                    .get(event.block.as_str())
+                    .map(|creation_time| self.mapping.make_row(event, *creation_time))
+            })
+            .collect::<Vec<Value>>();
+        for chunk in rows.chunks(1000) {
+            self.repository.insert_rows_with_conn(
+                &self.mapping.table,
+                &Value::from(chunk.to_vec()),
+                conn,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn rollback_with_conn(&self, block: &Block, conn: &mut PgConnection) -> Result<(), DbError> {
+        self.repository
+            .delete_by_block_with_conn(&self.mapping.table, &block.hash, conn)?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), DbError> {
+        self.repository.delete_all(&self.mapping.table)?;
+        Ok(())
+    }
+}
+
+/// Reads the mappings from the TOML file, creates their tables and registers a handler for
+/// each of them. Returns the number of registered mappings.
+pub fn register_mappings(
+    path: &str,
+    pool: &DbPool,
+    registry: &mut HandlerRegistry,
+) -> Result<usize, Box<dyn Error>> {
+    let mappings = parse_config(&std::fs::read_to_string(path)?)?;
+    let repository = MappedEventsRepository { pool: pool.clone() };
+    for mapping in mappings.iter() {
+        let columns = mapping
+            .columns
+            .iter()
+            .map(|column| (column.name.clone(), column.column_type.sql_type()))
+            .collect::<Vec<(String, &str)>>();
+        if registry.find(&mapping.table).is_some() {
+            return Err(format!("Event handler {} is already registered", mapping.table).into());
+        }
+        repository
+            .create_table(&mapping.table, &columns)
+            .map_err(|e| e as Box<dyn Error>)?;
+        registry.register(Box::new(MappingHandler {
+            mapping: mapping.clone(),
+            repository: repository.clone(),
+        }));
+    }
+    log::info!("Registered {} event mappings from {}", mappings.len(), path);
+    Ok(mappings.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[[mapping]]
+table = "mapped_dex_swaps"
+qual_names = ["kaddex.exchange.SWAP", "free.*.SWAP"]
+columns = [
+    { name = "sender", param = 0, type = "string" },
+    { name = "amount_in", param = 2, type = "decimal" },
+    { name = "nonce", param = 3, type = "integer" },
+    { name = "deadline", param = 4, type = "time" },
+]
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let mappings = parse_config(CONFIG).unwrap();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].table, "mapped_dex_swaps");
+        assert_eq!(mappings[0].columns[1].column_type, ColumnType::Decimal);
+
+        let invalid = CONFIG.replace("mapped_dex_swaps", "dex swaps; DROP TABLE events");
+        assert!(parse_config(&invalid).is_err());
+        let reserved = CONFIG.replace("\"sender\"", "\"height\"");
+        assert!(parse_config(&reserved).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_rejects_indexer_tables() {
+        for table in ["transfers", "blocks", "events", "balances", "mapped_"] {
+            let config = CONFIG.replace("mapped_dex_swaps", table);
+            assert!(parse_config(&config).is_err(), "{} was accepted", table);
+        }
+    }
+
+    #[test]
+    fn test_make_row() {
+        let mapping = parse_config(CONFIG).unwrap().remove(0);
+        let event = Event {
+            block: "block".to_string(),
+            chain_id: 2,
+            height: 100,
+            idx: 1,
+            module: "kaddex.exchange".to_string(),
+            module_hash: "module-hash".to_string(),
+            name: "SWAP".to_string(),
+            params: serde_json::json!([
+                "alice",
+                "bob",
+                { "decimal": "1.5" },
+                { "int": 7 },
+                { "time": "2023-10-01T00:00:00Z" }
+            ]),
+            param_text: "param-text".to_string(),
+            qual_name: "kaddex.exchange.SWAP".to_string(),
+            request_key: "request-key".to_string(),
+            pact_id: None,
+        };
+        let creation_time =
+            NaiveDateTime::parse_from_str("2023-10-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let row = mapping.make_row(&event, creation_time);
+        assert_eq!(row["chain_id"], 2);
+        assert_eq!(row["creation_time"], "2023-10-01T12:00:00Z");
+        assert_eq!(row["sender"], "alice");
+        assert_eq!(row["amount_in"], "1.5");
+        assert_eq!(row["nonce"], 7);
+        assert_eq!(row["deadline"], "2023-10-01T00:00:00+00:00");
+    }
+}
@@ -0,0 +1,433 @@
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::PgConnection;
+use std::collections::{HashMap, HashSet};
+
+use crate::db::DbError;
+use crate::handlers::{EventFilter, EventHandler};
+use crate::models::{Block, Event, NftBalance, NftSale, NftToken};
+use crate::repository::NftRepository;
+use crate::tokens::{parse_decimal, parse_integer};
+
+/// Ledger modules of marmalade v1 and v2.
+pub const LEDGER_MODULES: [&str; 2] = ["marmalade.ledger", "marmalade-v2.ledger"];
+
+/// Updates NFT tokens, balances and sales from the marmalade ledger events of the given
+/// blocks.
+pub fn process_marmalade_events_with_conn(
+    events: &[Event],
+    blocks: &[Block],
+    repository: &NftRepository,
+    conn: &mut PgConnection,
+) -> Result<(), DbError> {
+    let creation_times = blocks
+        .iter()
+        .map(|block| (block.hash.as_str(), block.creation_time))
+        .collect::<HashMap<&str, NaiveDateTime>>();
+    for event in events
+        .iter()
+        .filter(|event| LEDGER_MODULES.contains(&event.module.as_str()))
+    {
+        let creation_time = match creation_times.get(event.block.as_str()) {
+            Some(creation_time) => *creation_time,
+            None => continue,
+        };
+        let processed = match event.name.as_str() {
+            "TOKEN" => make_token(event, creation_time)
+                .map(|token| repository.upsert_token_with_conn(&token, conn)),
+            "MINT" | "BURN" => supply_change(event).map(|(token_id, delta)| {
+                repository.add_supply_with_conn(event.chain_id, &token_id, &delta, conn)
+            }),
+            "RECONCILE" => {
+                parse_reconcile(event).map(|(token_id, changes)| -> Result<usize, DbError> {
+                    let mut updated = 0;
+                    for (account, _, current) in changes {
+                        updated += repository.update_balance_with_conn(
+                            &NftBalance {
+                                chain_id: event.chain_id,
+                                token_id: token_id.clone(),
+                                account,
+                                balance: current,
+                                block: event.block.clone(),
+                                request_key: event.request_key.clone(),
+                                height: event.height,
+                                idx: event.idx,
+                            },
+                            conn,
+                        )?;
+                    }
+                    Ok(updated)
+                })
+            }
+            "SALE" | "OFFER" => make_offer(event, creation_time)
+                .map(|sale| repository.upsert_offer_with_conn(&sale, conn)),
+            "BUY" => make_buy(event, creation_time)
+                .map(|sale| repository.record_buy_with_conn(&sale, conn)),
+            _ => continue,
+        };
+        match processed {
+            Some(result) => {
+                result?;
+            }
+            None => log::warn!(
+                "Unable to parse {} event in {}: {}",
+                event.qual_name,
+                event.request_key,
+                event.params
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Undoes the changes made by the marmalade events of a block that is about to be deleted.
+/// Balances are restored from the `previous` balance of the first RECONCILE event of each
+/// account in the block, as of the end of the parent block.
+pub fn revert_block_with_conn(
+    hash: &str,
+    repository: &NftRepository,
+    conn: &mut PgConnection,
+) -> Result<(), DbError> {
+    let events = repository.find_ledger_events_with_conn(hash, &LEDGER_MODULES, conn)?;
+    let mut restored = HashSet::new();
+    for event in events.iter() {
+        match event.name.as_str() {
+            "MINT" | "BURN" => {
+                if let Some((token_id, delta)) = supply_change(event) {
+                    repository.add_supply_with_conn(event.chain_id, &token_id, &-delta, conn)?;
+                }
+            }
+            "RECONCILE" => {
+                let (token_id, changes) = match parse_reconcile(event) {
+                    Some(reconcile) => reconcile,
+                    None => continue,
+                };
+                for (account, previous, _) in changes {
+                    if !restored.insert((token_id.clone(), account.clone())) {
+                        continue;
+                    }
+                    let balance = NftBalance {
+                        chain_id: event.chain_id,
+                        token_id: token_id.clone(),
+                        account,
+                        balance: previous,
+                        block: String::new(),
+                        request_key: String::new(),
+                        height: event.height - 1,
+                        idx: i64::MAX,
+                    };
+                    repository.restore_balance_with_conn(hash, &balance, conn)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    repository.clear_token_metadata_with_conn(hash, conn)?;
+    repository.revert_sales_with_conn(hash, conn)?;
+    Ok(())
+}
+
+pub struct MarmaladeHandler {
+    pub repository: NftRepository,
+}
+
+impl EventHandler for MarmaladeHandler {
+    fn name(&self) -> &str {
+        "marmalade"
+    }
+
+    fn filter(&self) -> EventFilter {
+        EventFilter::new(&LEDGER_MODULES, &[])
+    }
+
+    fn process(
+        &self,
+        events: &[Event],
+        blocks: &[Block],
+        conn: &mut PgConnection,
+    ) -> Result<(), DbError> {
+        process_marmalade_events_with_conn(events, blocks, &self.repository, conn)
+    }
+
+    fn rollback_with_conn(&self, block: &Block, conn: &mut PgConnection) -> Result<(), DbError> {
+        revert_block_with_conn(&block.hash, &self.repository, conn)
+    }
+
+    fn reset(&self) -> Result<(), DbError> {
+        self.repository.delete_all()?;
+        Ok(())
+    }
+}
+
+/// `TOKEN (id precision policies uri)` in v2, `TOKEN (id precision supply policy)` in v1,
+/// which has no uri. The supply of v1 events is ignored, it's updated by MINT and BURN
+/// events.
+fn make_token(event: &Event, creation_time: NaiveDateTime) -> Option<NftToken> {
+    let params = event.params.as_array()?;
+    let (policies_index, uri_index) = match event.module.as_str() {
+        "marmalade.ledger" => (3, None),
+        _ => (2, Some(3)),
+    };
+    Some(NftToken {
+        chain_id: event.chain_id,
+        token_id: params.first()?.as_str()?.to_string(),
+        module: Some(event.module.clone()),
+        precision: params
+            .get(1)
+            .and_then(parse_integer)
+            .and_then(|precision| i32::try_from(precision).ok()),
+        uri: uri_index
+            .and_then(|index| params.get(index))
+            .and_then(|uri| uri.as_str())
+            .map(|uri| uri.to_string()),
+        policies: params.get(policies_index).cloned(),
+        supply: BigDecimal::from(0),
+        block: Some(event.block.clone()),
+        height: Some(event.height),
+        request_key: Some(event.request_key.clone()),
+        creation_time: Some(creation_time),
+    })
+}
+
+/// `MINT (id account amount)` increases and `BURN (id account amount)` decreases supply.
+fn supply_change(event: &Event) -> Option<(String, BigDecimal)> {
+    let params = event.params.as_array()?;
+    let token_id = params.first()?.as_str()?.to_string();
+    let amount = parse_decimal(params.get(2)?)?;
+    match event.name.as_str() {
+        "MINT" => Some((token_id, amount)),
+        "BURN" => Some((token_id, -amount)),
+        _ => None,
+    }
+}
+
+/// `(account, previous, current)` balance of a side of a reconcile
+type BalanceChange = (String, BigDecimal, BigDecimal);
+
+/// `RECONCILE (token-id amount sender receiver)` where sender and receiver are objects
+/// with `account`, `previous` and `current` balances. The empty account of mints and
+/// burns is skipped. Returns the token id and the balance change of every side.
+fn parse_reconcile(event: &Event) -> Option<(String, Vec<BalanceChange>)> {
+    let params = event.params.as_array()?;
+    let token_id = params.first()?.as_str()?.to_string();
+    let mut changes = vec![];
+    for side in params.get(2..4)? {
+        let account = side["account"].as_str()?;
+        if account.is_empty() {
+            continue;
+        }
+        changes.push((
+            account.to_string(),
+            parse_decimal(&side["previous"])?,
+            parse_decimal(&side["current"])?,
+        ));
+    }
+    Some((token_id, changes))
+}
+
+/// The sale id is the pact id of the sale, which v2 passes as `sale-id` in
+/// `SALE (id seller amount timeout sale-id)`. v1 emits `OFFER (id seller amount timeout)`,
+/// the pact id of the event is used instead.
+fn make_offer(event: &Event, creation_time: NaiveDateTime) -> Option<NftSale> {
+    let params = event.params.as_array()?;
+    let sale_id = params
+        .get(4)
+        .and_then(|id| id.as_str())
+        .map(String::from)
+        .or_else(|| event.pact_id.clone())?;
+    Some(NftSale {
+        chain_id: event.chain_id,
+        sale_id,
+        token_id: params.first()?.as_str()?.to_string(),
+        seller: params.get(1)?.as_str()?.to_string(),
+        amount: parse_decimal(params.get(2)?)?,
+        timeout: params.get(3).and_then(parse_integer),
+        buyer: None,
+        offer_block: Some(event.block.clone()),
+        offer_request_key: Some(event.request_key.clone()),
+        offer_height: Some(event.height),
+        offer_time: Some(creation_time),
+        buy_block: None,
+        buy_request_key: None,
+        buy_height: None,
+        buy_time: None,
+    })
+}
+
+/// `BUY (id seller buyer amount sale-id)` in v2, `BUY (id seller buyer amount timeout
+/// sale-id)` in v1. The sale id is taken from the event like for offers, so that both
+/// sides of a sale get the same id.
+fn make_buy(event: &Event, creation_time: NaiveDateTime) -> Option<NftSale> {
+    let params = event.params.as_array()?;
+    let sale_id = params
+        .get(4..)
+        .and_then(|rest| rest.last())
+        .and_then(|id| id.as_str())
+        .map(String::from)
+        .or_else(|| event.pact_id.clone())?;
+    Some(NftSale {
+        chain_id: event.chain_id,
+        sale_id,
+        token_id: params.first()?.as_str()?.to_string(),
+        seller: params.get(1)?.as_str()?.to_string(),
+        amount: parse_decimal(params.get(3)?)?,
+        timeout: None,
+        buyer: Some(params.get(2)?.as_str()?.to_string()),
+        offer_block: None,
+        offer_request_key: None,
+        offer_height: None,
+        offer_time: None,
+        buy_block: Some(event.block.clone()),
+        buy_request_key: Some(event.request_key.clone()),
+        buy_height: Some(event.height),
+        buy_time: Some(creation_time),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use chrono::Utc;
+
+    fn make_event(name: &str, params: serde_json::Value, pact_id: Option<&str>) -> Event {
+        Event {
+            block: "block-hash".to_string(),
+            chain_id: 8,
+            height: 4000000,
+            idx: 2,
+            pact_id: pact_id.map(String::from),
+            ..test_utils::make_event("marmalade-v2.ledger", name, params)
+        }
+    }
+
+    #[test]
+    fn test_make_token() {
+        let policies = serde_json::json!([
+            { "refSpec": [{ "name": "kip.token-policy-v2" }], "refName": { "name": "non-fungible-policy-v1", "namespace": "marmalade-v2" } }
+        ]);
+        let event = make_event(
+            "TOKEN",
+            serde_json::json!(["t:abc", { "int": 0 }, policies, "ipfs://token"]),
+            None,
+        );
+        let creation_time = Utc::now().naive_utc();
+        let token = make_token(&event, creation_time).unwrap();
+        assert_eq!(token.token_id, "t:abc");
+        assert_eq!(token.chain_id, 8);
+        assert_eq!(token.precision, Some(0));
+        assert_eq!(token.uri, Some("ipfs://token".to_string()));
+        assert_eq!(token.policies, Some(policies));
+        assert_eq!(token.supply, BigDecimal::from(0));
+        assert_eq!(token.creation_time, Some(creation_time));
+
+        let policy = serde_json::json!({ "refSpec": [{ "name": "kip.token-policy-v1" }], "refName": { "name": "fixed-quote-policy", "namespace": "marmalade" } });
+        let event = Event {
+            module: "marmalade.ledger".to_string(),
+            ..make_event(
+                "TOKEN",
+                serde_json::json!(["t:def", { "int": 1 }, 1.0, policy]),
+                None,
+            )
+        };
+        let token = make_token(&event, creation_time).unwrap();
+        assert_eq!(token.token_id, "t:def");
+        assert_eq!(token.precision, Some(1));
+        assert_eq!(token.uri, None);
+        assert_eq!(token.policies, Some(policy));
+        assert_eq!(token.supply, BigDecimal::from(0));
+    }
+
+    #[test]
+    fn test_supply_change() {
+        let mint = make_event("MINT", serde_json::json!(["t:abc", "k:alice", 1.0]), None);
+        assert_eq!(
+            supply_change(&mint),
+            Some(("t:abc".to_string(), BigDecimal::from(1)))
+        );
+        let burn = make_event(
+            "BURN",
+            serde_json::json!(["t:abc", "k:alice", { "decimal": "1.0" }]),
+            None,
+        );
+        assert_eq!(
+            supply_change(&burn),
+            Some(("t:abc".to_string(), BigDecimal::from(-1)))
+        );
+    }
+
+    #[test]
+    fn test_parse_reconcile() {
+        let event = make_event(
+            "RECONCILE",
+            serde_json::json!([
+                "t:abc",
+                1.0,
+                { "account": "", "previous": 0.0, "current": 0.0 },
+                { "account": "k:alice", "previous": 0.0, "current": 1.0 }
+            ]),
+            None,
+        );
+        assert_eq!(
+            parse_reconcile(&event),
+            Some((
+                "t:abc".to_string(),
+                vec![(
+                    "k:alice".to_string(),
+                    BigDecimal::from(0),
+                    BigDecimal::from(1)
+                )]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_make_offer_and_buy() {
+        let creation_time = Utc::now().naive_utc();
+        let sale = make_event(
+            "SALE",
+            serde_json::json!(["t:abc", "k:alice", 1.0, { "int": 0 }, "sale-id"]),
+            Some("sale-id"),
+        );
+        let offer = make_offer(&sale, creation_time).unwrap();
+        assert_eq!(offer.sale_id, "sale-id");
+        assert_eq!(offer.seller, "k:alice");
+        assert_eq!(offer.timeout, Some(0));
+        assert_eq!(offer.buyer, None);
+        assert_eq!(offer.offer_time, Some(creation_time));
+
+        let buy = make_event(
+            "BUY",
+            serde_json::json!(["t:abc", "k:alice", "k:bob", 1.0, "sale-id"]),
+            None,
+        );
+        let bought = make_buy(&buy, creation_time).unwrap();
+        assert_eq!(bought.sale_id, offer.sale_id);
+        assert_eq!(bought.buyer, Some("k:bob".to_string()));
+        assert_eq!(bought.amount, BigDecimal::from(1));
+        assert_eq!(bought.buy_height, Some(4000000));
+
+        // v1 offers don't pass the sale id, v1 purchases pass it after the timeout
+        let offer = Event {
+            module: "marmalade.ledger".to_string(),
+            ..make_event(
+                "OFFER",
+                serde_json::json!(["t:abc", "k:alice", 1.0, { "int": 0 }]),
+                Some("pact-id"),
+            )
+        };
+        let buy = Event {
+            module: "marmalade.ledger".to_string(),
+            ..make_event(
+                "BUY",
+                serde_json::json!(["t:abc", "k:alice", "k:bob", 1.0, { "int": 0 }, "pact-id"]),
+                Some("pact-id"),
+            )
+        };
+        assert_eq!(
+            make_offer(&offer, creation_time).unwrap().sale_id,
+            "pact-id"
+        );
+        assert_eq!(make_buy(&buy, creation_time).unwrap().sale_id, "pact-id");
+    }
+}
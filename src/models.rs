@@ -2,6 +2,7 @@ use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::Serialize;
+use std::collections::HashMap;
 
 #[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
 #[diesel(table_name = crate::schema::blocks)]
@@ -70,6 +71,7 @@ pub struct Transaction {
     pub step: Option<i64>,
     pub ttl: i64,
     pub tx_id: Option<i64>,
+    pub position: Option<i64>,
 }
 
 #[derive(Queryable, Selectable, Insertable, Associations, Debug, Clone, PartialEq, Eq)]
@@ -91,3 +93,529 @@ pub struct Transfer {
     pub request_key: String,
     pub to_account: String,
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MinerSummary {
+    pub miner: String,
+    pub blocks_mined: i64,
+    pub share: f64,
+    pub first_seen: Option<NaiveDateTime>,
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MinerShare {
+    pub blocks_mined: i64,
+    pub total_blocks: i64,
+    pub share: f64,
+    pub chains: HashMap<i64, f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MinerDetails {
+    pub miner: String,
+    pub first_seen: Option<NaiveDateTime>,
+    pub last_seen: Option<NaiveDateTime>,
+    pub blocks_mined: HashMap<i64, i64>,
+    pub share: HashMap<String, MinerShare>,
+    pub rewards: HashMap<i64, BigDecimal>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::hashrate_buckets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct HashrateBucket {
+    pub chain_id: i64,
+    pub bucket: NaiveDateTime,
+    pub blocks: i64,
+    pub difficulty: BigDecimal,
+    pub first_block_time: NaiveDateTime,
+    pub last_block_time: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkStats {
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub chains: i64,
+    pub blocks: i64,
+    pub difficulty: f64,
+    pub block_time: f64,
+    pub hashrate: f64,
+}
+
+#[derive(Queryable, Selectable, Debug, Clone, Serialize)]
+#[diesel(table_name = crate::schema::crosschain_transfers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CrossChainTransfer {
+    pub pact_id: String,
+    pub module_name: String,
+    pub source_chain_id: i64,
+    pub target_chain_id: Option<i64>,
+    pub sender: String,
+    pub receiver: Option<String>,
+    pub amount: BigDecimal,
+    pub source_request_key: String,
+    pub target_request_key: Option<String>,
+    pub source_height: i64,
+    pub target_height: Option<i64>,
+    pub initiation_time: NaiveDateTime,
+    pub completion_time: Option<NaiveDateTime>,
+    pub status: String,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
+#[diesel(table_name = crate::schema::stuck_crosschain_transfers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct StuckCrossChainTransfer {
+    pub pact_id: String,
+    pub request_key: String,
+    pub chain_id: i64,
+    pub height: i64,
+    pub module_name: String,
+    pub sender: String,
+    pub receiver: Option<String>,
+    pub target_chain_id: Option<i64>,
+    pub amount: BigDecimal,
+    pub initiation_time: NaiveDateTime,
+    pub detected_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::balances)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Balance {
+    pub account: String,
+    pub module: String,
+    pub chain_id: i64,
+    pub balance: BigDecimal,
+}
+
+#[derive(QueryableByName, Debug, Clone)]
+pub struct BalanceMismatch {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub account: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub module: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub chain_id: i64,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Numeric>)]
+    pub stored: Option<BigDecimal>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Numeric>)]
+    pub computed: Option<BigDecimal>,
+}
+
+#[derive(QueryableByName, Debug, Clone)]
+pub struct HistoricalBalance {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub chain_id: i64,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub balance: BigDecimal,
+}
+
+#[derive(QueryableByName, Debug, Clone, Serialize)]
+pub struct BalanceChange {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub module: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub chain_id: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub height: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub idx: i64,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub block: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub request_key: String,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub creation_time: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub amount: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub balance: BigDecimal,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::balance_mismatches)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ReconciliationMismatch {
+    pub account: String,
+    pub module: String,
+    pub chain_id: i64,
+    pub indexed_balance: BigDecimal,
+    pub node_balance: Option<BigDecimal>,
+    pub checked_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
+#[diesel(table_name = crate::schema::tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Token {
+    pub module: String,
+    pub namespace: Option<String>,
+    pub first_seen_height: i64,
+    pub first_seen_chain_id: i64,
+    pub first_seen_time: NaiveDateTime,
+    pub precision: Option<i32>,
+    pub transfer_count: i64,
+    pub volume: BigDecimal,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize)]
+#[diesel(table_name = crate::schema::token_module_hashes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TokenModuleHash {
+    pub module: String,
+    pub module_hash: String,
+    pub first_seen_height: i64,
+    pub first_seen_chain_id: i64,
+    pub first_seen_time: NaiveDateTime,
+    pub transfer_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenSummary {
+    #[serde(flatten)]
+    pub token: Token,
+    pub holders: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenDetails {
+    #[serde(flatten)]
+    pub token: Token,
+    pub holders: i64,
+    pub module_hashes: Vec<TokenModuleHash>,
+}
+
+#[derive(QueryableByName, Debug, Clone, Serialize)]
+pub struct TokenHolder {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub account: String,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub balance: BigDecimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenHolders {
+    pub module: String,
+    pub chain_id: Option<i64>,
+    pub total_supply: BigDecimal,
+    pub holders: Vec<TokenHolder>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = crate::schema::supply_snapshots)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SupplySnapshot {
+    pub module: String,
+    pub chain_id: i64,
+    pub bucket: NaiveDateTime,
+    pub minted: BigDecimal,
+    pub burned: BigDecimal,
+    pub crosschain_in: BigDecimal,
+    pub crosschain_out: BigDecimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SupplyBucket {
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub minted: BigDecimal,
+    pub burned: BigDecimal,
+    pub net: BigDecimal,
+    pub crosschain_in: BigDecimal,
+    pub crosschain_out: BigDecimal,
+    pub supply: BigDecimal,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::nft_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NftToken {
+    pub chain_id: i64,
+    pub token_id: String,
+    pub module: Option<String>,
+    pub precision: Option<i32>,
+    pub uri: Option<String>,
+    pub policies: Option<serde_json::Value>,
+    pub supply: BigDecimal,
+    pub block: Option<String>,
+    pub height: Option<i64>,
+    pub request_key: Option<String>,
+    pub creation_time: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::nft_balances)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NftBalance {
+    pub chain_id: i64,
+    pub token_id: String,
+    pub account: String,
+    pub balance: BigDecimal,
+    pub block: String,
+    pub request_key: String,
+    pub height: i64,
+    pub idx: i64,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::nft_sales)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NftSale {
+    pub chain_id: i64,
+    pub sale_id: String,
+    pub token_id: String,
+    pub seller: String,
+    pub amount: BigDecimal,
+    pub timeout: Option<i64>,
+    pub buyer: Option<String>,
+    pub offer_block: Option<String>,
+    pub offer_request_key: Option<String>,
+    pub offer_height: Option<i64>,
+    pub offer_time: Option<NaiveDateTime>,
+    pub buy_block: Option<String>,
+    pub buy_request_key: Option<String>,
+    pub buy_height: Option<i64>,
+    pub buy_time: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::swaps)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Swap {
+    pub block: String,
+    pub chain_id: i64,
+    pub height: i64,
+    pub idx: i64,
+    pub request_key: String,
+    pub creation_time: NaiveDateTime,
+    pub module: String,
+    pub pair: String,
+    pub trader: String,
+    pub receiver: String,
+    pub token_in: String,
+    pub amount_in: BigDecimal,
+    pub token_out: String,
+    pub amount_out: BigDecimal,
+    pub price: Option<BigDecimal>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::pool_reserves)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PoolReserve {
+    pub block: String,
+    pub chain_id: i64,
+    pub height: i64,
+    pub idx: i64,
+    pub request_key: String,
+    pub creation_time: NaiveDateTime,
+    pub module: String,
+    pub pair: String,
+    pub reserve0: BigDecimal,
+    pub reserve1: BigDecimal,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::liquidity_changes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LiquidityChange {
+    pub block: String,
+    pub chain_id: i64,
+    pub height: i64,
+    pub idx: i64,
+    pub request_key: String,
+    pub creation_time: NaiveDateTime,
+    pub module: String,
+    pub pair: String,
+    pub kind: String,
+    pub account: String,
+    pub amount0: BigDecimal,
+    pub amount1: BigDecimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candle {
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub open: BigDecimal,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub close: BigDecimal,
+    /// Amount of token0 traded
+    pub volume: BigDecimal,
+    pub swaps: i64,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::token_holding_stats)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TokenHoldingStats {
+    pub module: String,
+    pub computed_at: NaiveDateTime,
+    pub holders: i64,
+    pub held_amount: f64,
+    pub p25_days: Option<f64>,
+    pub median_days: Option<f64>,
+    pub p75_days: Option<f64>,
+    pub p90_days: Option<f64>,
+    pub held_30d_share: f64,
+    pub held_90d_share: f64,
+    pub held_365d_share: f64,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::token_holding_stats_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TokenHoldingStatsPeriod {
+    pub module: String,
+    pub period_start: NaiveDateTime,
+    pub held_amount: f64,
+    pub p25_days: Option<f64>,
+    pub median_days: Option<f64>,
+    pub p75_days: Option<f64>,
+    pub p90_days: Option<f64>,
+    pub held_30d_share: f64,
+    pub held_90d_share: f64,
+    pub held_365d_share: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HoldingStatsSummary {
+    #[serde(flatten)]
+    pub stats: TokenHoldingStats,
+    pub history: Vec<TokenHoldingStatsPeriod>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::clusters)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Cluster {
+    pub account: String,
+    pub cluster_id: String,
+}
+
+/// `account` and `linked_account` are likely controlled by the same entity. `evidence` is one
+/// of `gas_payer`, `shared_keyset`, `public_key` or `funding_source`.
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::cluster_links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ClusterLink {
+    pub account: String,
+    pub linked_account: String,
+    pub evidence: String,
+    pub detail: Option<String>,
+}
+
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct AccountPair {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub account: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub linked_account: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Varchar>)]
+    pub detail: Option<String>,
+}
+
+/// Keyset found in the data of a transaction of the sender
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct DataKeyset {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub sender: String,
+    #[diesel(sql_type = diesel::sql_types::Jsonb)]
+    pub keys: serde_json::Value,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Varchar>)]
+    pub pred: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub request_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entity {
+    pub cluster_id: String,
+    pub accounts: Vec<String>,
+    pub links: Vec<ClusterLink>,
+}
+
+/// Activity of a scope in a rollup bucket, summed over chains
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct ActivityRollup {
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub bucket: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub transaction_count: i64,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub total_amount: BigDecimal,
+}
+
+/// Owner of an account according to `source`, eg. `Binance` with the `exchange` category.
+/// `confidence` goes from 0 to 1.
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq, Serialize)]
+#[diesel(table_name = crate::schema::labels)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Label {
+    pub account: String,
+    pub label: String,
+    pub category: String,
+    pub source: String,
+    pub confidence: f64,
+}
+
+/// Transfers between two account groups. A group is a label, a cluster or `other`, as
+/// given by `kind`, with the category of the label.
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct GroupFlow {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub from_group: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub from_kind: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Varchar>)]
+    pub from_category: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub to_group: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub to_kind: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Varchar>)]
+    pub to_category: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub transfers: i64,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub amount: BigDecimal,
+}
+
+/// Accounts first seen in the `cohort_interval` (`day`, `week` or `month`) starting at
+/// `cohort_start` that transferred the module, and how many of them transferred it `period`
+/// intervals later.
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::cohort_retention)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CohortRetention {
+    pub module: String,
+    pub cohort_interval: String,
+    pub cohort_start: NaiveDateTime,
+    pub period: i64,
+    pub cohort_size: i64,
+    pub active_accounts: i64,
+}
+
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct CohortSize {
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub cohort_start: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub accounts: i64,
+}
+
+/// Accounts of a cohort that sent or received a transfer in the interval starting at
+/// `period_start`
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct CohortActivity {
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub cohort_start: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub period_start: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub accounts: i64,
+}
@@ -0,0 +1,263 @@
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use chrono::{Duration, DurationRound, NaiveDateTime};
+use diesel::{Connection, PgConnection};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+
+use crate::chainweb_client::{BlockHeader, Bounds, ChainwebClient, Hash};
+use crate::db::DbError;
+use crate::indexer::decode_pow_value;
+use crate::models::{Block, HashrateBucket, NetworkStats};
+use crate::repository::{BlocksRepository, HashrateRepository};
+
+const TARGETS_BATCH_SIZE: i64 = 50;
+
+/// Recomputes the hourly hashrate buckets touched by the given blocks.
+pub fn refresh_hashrate(blocks: &[Block], repository: &HashrateRepository) -> Result<(), DbError> {
+    for (chain_id, from, to) in hourly_ranges(blocks) {
+        repository.refresh(chain_id, from, to)?;
+    }
+    Ok(())
+}
+
+pub fn refresh_hashrate_with_conn(
+    blocks: &[Block],
+    repository: &HashrateRepository,
+    conn: &mut PgConnection,
+) -> Result<(), DbError> {
+    for (chain_id, from, to) in hourly_ranges(blocks) {
+        repository.refresh_with_conn(chain_id, from, to, conn)?;
+    }
+    Ok(())
+}
+
+/// Decodes the targets and weights of the blocks indexed before they were stored, using the
+/// headers of the current branch of every chain, and recomputes the hashrate of their hours.
+/// Returns the number of updated blocks.
+pub async fn backfill_targets(
+    chainweb_client: &ChainwebClient,
+    blocks: &BlocksRepository,
+    hashrate: &HashrateRepository,
+) -> Result<usize, Box<dyn Error>> {
+    let cut = chainweb_client.get_cut().await?;
+    let mut updated = 0;
+    for (chain, latest) in cut.hashes.iter() {
+        let bounds = Bounds {
+            lower: vec![],
+            upper: vec![Hash(latest.hash.to_string())],
+        };
+        let mut min_height = -1;
+        loop {
+            let batch = blocks
+                .find_without_target(chain.0 as i64, min_height, TARGETS_BATCH_SIZE)
+                .map_err(|e| e as Box<dyn Error>)?;
+            let (first, last) = match (batch.first(), batch.last()) {
+                (Some(first), Some(last)) => (first.height, last.height),
+                _ => break,
+            };
+            let mut headers: HashMap<String, BlockHeader> = HashMap::new();
+            let mut next = None;
+            loop {
+                // Headers are returned above the min height, not from it
+                let response = chainweb_client
+                    .get_block_headers_branches(
+                        chain,
+                        &bounds,
+                        &next,
+                        (first > 0).then_some((first - 1) as u64),
+                        Some(last as u64),
+                    )
+                    .await?;
+                for header in response.items {
+                    headers.insert(header.hash.clone(), header);
+                }
+                match response.next {
+                    Some(cursor) => next = Some(cursor),
+                    None => break,
+                }
+            }
+            // Orphaned blocks aren't on the branch and keep their target
+            let decoded = batch
+                .into_iter()
+                .filter_map(|block| {
+                    headers.get(&block.hash).map(|header| Block {
+                        target: decode_pow_value(&header.target),
+                        weight: decode_pow_value(&header.weight),
+                        ..block
+                    })
+                })
+                .collect::<Vec<Block>>();
+            let mut conn = blocks.pool.get().unwrap();
+            conn.transaction(|conn| -> Result<(), DbError> {
+                for block in decoded.iter() {
+                    blocks.update_pow_values_with_conn(block, conn)?;
+                }
+                refresh_hashrate_with_conn(&decoded, hashrate, conn)
+            })
+            .map_err(|e| e as Box<dyn Error>)?;
+            log::info!(
+                "Chain {}: decoded the targets of {} blocks up to height {}",
+                chain.0,
+                decoded.len(),
+                last
+            );
+            updated += decoded.len();
+            min_height = last;
+        }
+    }
+    Ok(updated)
+}
+
+/// Estimates difficulty, block time and hashrate over buckets of the given interval,
+/// which has to be a multiple of one hour.
+pub fn hashrate_series(
+    repository: &HashrateRepository,
+    interval: Duration,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    chain_id: Option<i64>,
+    now: NaiveDateTime,
+) -> Result<Vec<NetworkStats>, DbError> {
+    let buckets = repository.find_by_range(from, to, chain_id)?;
+    Ok(aggregate_buckets(&buckets, interval, now))
+}
+
+/// For every chain returns the range of hours (start inclusive, end exclusive)
+/// the blocks were created in.
+fn hourly_ranges(blocks: &[Block]) -> Vec<(i64, NaiveDateTime, NaiveDateTime)> {
+    let mut ranges: HashMap<i64, (NaiveDateTime, NaiveDateTime)> = HashMap::new();
+    for block in blocks {
+        let hour = block
+            .creation_time
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        ranges
+            .entry(block.chain_id)
+            .and_modify(|(from, to)| {
+                *from = (*from).min(hour);
+                *to = (*to).max(hour + Duration::hours(1));
+            })
+            .or_insert((hour, hour + Duration::hours(1)));
+    }
+    ranges
+        .into_iter()
+        .map(|(chain_id, (from, to))| (chain_id, from, to))
+        .collect()
+}
+
+/// Groups the hourly buckets of all chains into buckets of `interval`. The hashrate is the
+/// expected number of hashes needed to mine all blocks in a bucket divided by its duration;
+/// a bucket that is still in progress only counts the time elapsed until `now`.
+fn aggregate_buckets(
+    buckets: &[HashrateBucket],
+    interval: Duration,
+    now: NaiveDateTime,
+) -> Vec<NetworkStats> {
+    let interval_secs = interval.num_seconds();
+    let mut grouped: BTreeMap<i64, (i64, BigDecimal, HashSet<i64>)> = BTreeMap::new();
+    for bucket in buckets {
+        let timestamp = bucket.bucket.timestamp();
+        let start = timestamp - timestamp.rem_euclid(interval_secs);
+        let (blocks, difficulty, chains) =
+            grouped
+                .entry(start)
+                .or_insert((0, BigDecimal::zero(), HashSet::new()));
+        *blocks += bucket.blocks;
+        *difficulty += &bucket.difficulty;
+        chains.insert(bucket.chain_id);
+    }
+    grouped
+        .into_iter()
+        .filter(|(_, (blocks, _, _))| *blocks > 0)
+        .map(|(start, (blocks, difficulty, chains))| {
+            let start_time = NaiveDateTime::from_timestamp_opt(start, 0).unwrap();
+            let end_time = start_time + interval;
+            let elapsed = (now.min(end_time) - start_time).num_seconds().max(1) as f64;
+            let difficulty = difficulty.to_f64().unwrap_or(0.0);
+            NetworkStats {
+                start_time,
+                end_time,
+                chains: chains.len() as i64,
+                blocks,
+                difficulty: difficulty / blocks as f64,
+                block_time: elapsed * chains.len() as f64 / blocks as f64,
+                hashrate: difficulty / elapsed,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bucket(chain_id: i64, bucket: &str, blocks: i64, difficulty: i64) -> HashrateBucket {
+        let bucket = NaiveDateTime::parse_from_str(bucket, "%Y-%m-%d %H:%M:%S").unwrap();
+        HashrateBucket {
+            chain_id,
+            bucket,
+            blocks,
+            difficulty: BigDecimal::from(difficulty),
+            first_block_time: bucket,
+            last_block_time: bucket + Duration::minutes(59),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_buckets() {
+        let buckets = vec![
+            make_bucket(0, "2023-10-01 00:00:00", 120, 3_600_000),
+            make_bucket(1, "2023-10-01 00:00:00", 120, 3_600_000),
+            make_bucket(0, "2023-10-01 01:00:00", 120, 7_200_000),
+            make_bucket(0, "2023-10-01 02:00:00", 60, 3_600_000),
+        ];
+        let now =
+            NaiveDateTime::parse_from_str("2023-10-01 02:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let stats = aggregate_buckets(&buckets, Duration::hours(2), now);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].chains, 2);
+        assert_eq!(stats[0].blocks, 360);
+        assert_eq!(stats[0].hashrate, 14_400_000.0 / 7200.0);
+        assert_eq!(stats[0].difficulty, 40_000.0);
+        assert_eq!(stats[0].block_time, 40.0);
+        // The last bucket is still in progress, only 30 minutes have elapsed
+        assert_eq!(stats[1].blocks, 60);
+        assert_eq!(stats[1].hashrate, 3_600_000.0 / 1800.0);
+        assert_eq!(stats[1].block_time, 30.0);
+    }
+
+    #[test]
+    fn test_hourly_ranges() {
+        use chrono::Utc;
+        let creation_time =
+            NaiveDateTime::parse_from_str("2023-10-01 10:15:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let make_block = |chain_id: i64, creation_time: NaiveDateTime| Block {
+            chain_id,
+            hash: "hash".to_string(),
+            height: 0,
+            parent: "parent".to_string(),
+            weight: BigDecimal::from(0),
+            creation_time,
+            epoch: Utc::now().naive_utc(),
+            flags: BigDecimal::from(0),
+            miner: "miner".to_string(),
+            nonce: BigDecimal::from(0),
+            payload: "payload".to_string(),
+            pow_hash: "".to_string(),
+            predicate: "predicate".to_string(),
+            target: BigDecimal::from(1),
+        };
+        let ranges = hourly_ranges(&[
+            make_block(0, creation_time),
+            make_block(0, creation_time + Duration::hours(2)),
+        ]);
+        assert_eq!(
+            ranges,
+            vec![(
+                0,
+                creation_time - Duration::minutes(15),
+                creation_time + Duration::minutes(165)
+            )]
+        );
+    }
+}
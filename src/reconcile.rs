@@ -0,0 +1,228 @@
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::chainweb_client::tx_result::Status;
+use crate::chainweb_client::{ChainId, ChainwebClient};
+use crate::models::ReconciliationMismatch;
+use crate::repository::{BalanceMismatchesRepository, BalancesRepository};
+use crate::tokens::parse_decimal;
+
+const MODULE: &str = "coin";
+
+/// Compares the indexed `coin` balances of the accounts on every chain with the ones
+/// returned by `(coin.details account)` on the node and stores the differences in the
+/// `balance_mismatches` table. Returns the number of mismatches found.
+pub async fn reconcile_accounts(
+    client: &ChainwebClient,
+    balances: &BalancesRepository,
+    mismatches: &BalanceMismatchesRepository,
+    accounts: &[String],
+) -> Result<usize, Box<dyn Error>> {
+    let cut = client.get_cut().await?;
+    let mut chains = cut.hashes.keys().cloned().collect::<Vec<ChainId>>();
+    chains.sort_by_key(|chain| chain.0);
+    let mut found = 0;
+    for account in accounts {
+        let indexed = balances
+            .find_by_account_module(account, MODULE)
+            .map_err(|e| e as Box<dyn Error>)?;
+        let mut node = HashMap::new();
+        for chain in chains.iter() {
+            if let Some(balance) = fetch_node_balance(client, chain, account).await? {
+                node.insert(chain.0 as i64, balance);
+            }
+        }
+        let chain_ids = chains
+            .iter()
+            .map(|chain| chain.0 as i64)
+            .collect::<Vec<i64>>();
+        let account_mismatches =
+            compare_balances(account, &chain_ids, &indexed, &node, Utc::now().naive_utc());
+        for mismatch in account_mismatches.iter() {
+            log::warn!(
+                "Balance mismatch for {} on chain {}: indexed {}, node {:?}",
+                mismatch.account,
+                mismatch.chain_id,
+                mismatch.indexed_balance,
+                mismatch.node_balance
+            );
+        }
+        found += account_mismatches.len();
+        mismatches
+            .replace(account, MODULE, &account_mismatches)
+            .map_err(|e| e as Box<dyn Error>)?;
+    }
+    log::info!(
+        "Reconciled {} accounts, found {} mismatches",
+        accounts.len(),
+        found
+    );
+    Ok(found)
+}
+
+/// Returns the `coin` balance of the account according to the node, or `None` if the
+/// account doesn't exist on the chain.
+pub async fn fetch_node_balance(
+    client: &ChainwebClient,
+    chain: &ChainId,
+    account: &str,
+) -> Result<Option<BigDecimal>, Box<dyn Error>> {
+    let code = format!("(coin.details {})", serde_json::to_string(account)?);
+    let response = client.local(chain, &code).await?;
+    let result = response.result;
+    match result.status {
+        Status::Success => {
+            let data = result.data.unwrap_or_default();
+            match parse_decimal(&data["balance"]) {
+                Some(balance) => Ok(Some(balance)),
+                None => Err(format!("Unexpected coin.details result: {}", data).into()),
+            }
+        }
+        Status::Failure => {
+            let error = result.error.unwrap_or_default();
+            match error["message"].as_str() {
+                Some(message) if message.contains("row not found") => Ok(None),
+                _ => Err(format!("coin.details failed: {}", error).into()),
+            }
+        }
+    }
+}
+
+/// A missing balance on either side counts as zero, so accounts that only exist on one
+/// side are reported unless their balance is zero.
+fn compare_balances(
+    account: &str,
+    chains: &[i64],
+    indexed: &HashMap<i64, BigDecimal>,
+    node: &HashMap<i64, BigDecimal>,
+    checked_at: NaiveDateTime,
+) -> Vec<ReconciliationMismatch> {
+    let zero = BigDecimal::from(0);
+    chains
+        .iter()
+        .filter_map(|chain_id| {
+            let indexed_balance = indexed.get(chain_id).unwrap_or(&zero);
+            let node_balance = node.get(chain_id);
+            if indexed_balance == node_balance.unwrap_or(&zero) {
+                return None;
+            }
+            Some(ReconciliationMismatch {
+                account: account.to_string(),
+                module: MODULE.to_string(),
+                chain_id: *chain_id,
+                indexed_balance: indexed_balance.clone(),
+                node_balance: node_balance.cloned(),
+                checked_at,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const LOCAL_PATH: &str = "/chainweb/0.0/mainnet01/chain/1/pact/api/v1/local";
+
+    #[tokio::test]
+    async fn test_fetch_node_balance() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", LOCAL_PATH)
+            .match_body(mockito::Matcher::PartialJson(
+                serde_json::json!({ "sigs": [] }),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "gas": 20,
+                    "result": {
+                        "status": "success",
+                        "data": {
+                            "account": "alice",
+                            "balance": { "decimal": "12.345678901234" },
+                            "guard": { "keys": [], "pred": "keys-all" }
+                        }
+                    },
+                    "reqKey": "request-key",
+                    "logs": "logs",
+                    "metaData": {
+                        "blockHeight": 4000000,
+                        "blockTime": 1700000000000000i64,
+                        "prevBlockHash": "prev-block-hash"
+                    },
+                    "continuation": null,
+                    "txId": null
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = ChainwebClient::with_host(&server.url());
+        let balance = fetch_node_balance(&client, &ChainId(1), "alice")
+            .await
+            .unwrap();
+        assert_eq!(balance, BigDecimal::from_str("12.345678901234").ok());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_node_balance_missing_account() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", LOCAL_PATH)
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "gas": 20,
+                    "result": {
+                        "status": "failure",
+                        "error": { "message": "with-read: row not found: bob", "type": "TxFailure" }
+                    },
+                    "reqKey": "request-key",
+                    "logs": "logs",
+                    "metaData": {
+                        "blockHeight": 4000000,
+                        "blockTime": 1700000000000000i64,
+                        "prevBlockHash": "prev-block-hash"
+                    },
+                    "continuation": null,
+                    "txId": null
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = ChainwebClient::with_host(&server.url());
+        let balance = fetch_node_balance(&client, &ChainId(1), "bob")
+            .await
+            .unwrap();
+        assert_eq!(balance, None);
+    }
+
+    #[test]
+    fn test_compare_balances() {
+        let checked_at = Utc::now().naive_utc();
+        let indexed = HashMap::from([
+            (0, BigDecimal::from(10)),
+            (1, BigDecimal::from(5)),
+            (2, BigDecimal::from(0)),
+        ]);
+        let node = HashMap::from([(0, BigDecimal::from(10)), (1, BigDecimal::from(7))]);
+        let mismatches = compare_balances("alice", &[0, 1, 2, 3], &indexed, &node, checked_at);
+        assert_eq!(
+            mismatches,
+            vec![ReconciliationMismatch {
+                account: "alice".to_string(),
+                module: "coin".to_string(),
+                chain_id: 1,
+                indexed_balance: BigDecimal::from(5),
+                node_balance: Some(BigDecimal::from(7)),
+                checked_at,
+            }]
+        );
+    }
+}
@@ -1,11 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::vec;
 
+use crate::analytics::{GraphFilter, WalletConnection};
 use crate::db::DbError;
+use crate::handlers::{like_pattern, EventFilter};
 
 use super::db::DbPool;
 use super::models::*;
 use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
 use diesel::dsl::sum;
 use diesel::prelude::*;
 use diesel::PgConnection;  // Added for transaction support
@@ -128,6 +131,42 @@ impl BlocksRepository {
         Ok((min_block, max_block))
     }
 
+    /// Returns up to `limit` blocks of the chain above `min_height` that were indexed before
+    /// targets were decoded, lowest first.
+    pub fn find_without_target(
+        &self,
+        chain_id: i64,
+        min_height: i64,
+        limit: i64,
+    ) -> Result<Vec<Block>, DbError> {
+        use crate::schema::blocks::dsl::{
+            blocks as blocks_table, chain_id as chain_id_column, height as height_column, target,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let results = blocks_table
+            .filter(chain_id_column.eq(chain_id))
+            .filter(height_column.gt(min_height))
+            .filter(target.le(BigDecimal::from(1)))
+            .order(height_column.asc())
+            .limit(limit)
+            .select(Block::as_select())
+            .load::<Block>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Stores the decoded target and weight of the block.
+    pub fn update_pow_values_with_conn(
+        &self,
+        block: &Block,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::blocks::dsl::{blocks as blocks_table, hash, target, weight};
+        let updated = diesel::update(blocks_table.filter(hash.eq(&block.hash)))
+            .set((target.eq(&block.target), weight.eq(&block.weight)))
+            .execute(conn)?;
+        Ok(updated)
+    }
+
     pub fn count(&self, chain_id: i64) -> Result<i64, DbError> {
         use crate::schema::blocks::dsl::{blocks, chain_id as chain_id_col, height};
         use diesel::dsl::count;
@@ -183,16 +222,25 @@ impl BlocksRepository {
     }
 
     pub fn delete_by_hash(&self, hash: &str, chain_id: i64) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        self.delete_by_hash_with_conn(hash, chain_id, &mut conn)
+    }
+
+    pub fn delete_by_hash_with_conn(
+        &self,
+        hash: &str,
+        chain_id: i64,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
         use crate::schema::blocks::dsl::{
             blocks as blocks_table, chain_id as chain_id_col, hash as hash_col,
         };
-        let mut conn = self.pool.get().unwrap();
         let deleted = diesel::delete(
             blocks_table
                 .filter(hash_col.eq(hash))
                 .filter(chain_id_col.eq(chain_id)),
         )
-        .execute(&mut conn)?;
+        .execute(conn)?;
         Ok(deleted)
     }
 }
@@ -258,6 +306,72 @@ impl EventsRepository {
         Ok(results)
     }
 
+    pub fn find_chain_ids(&self) -> Result<Vec<i64>, DbError> {
+        use crate::schema::events::dsl::{chain_id, events};
+        let mut conn = self.pool.get().unwrap();
+        let results = events
+            .select(chain_id)
+            .distinct()
+            .order(chain_id.asc())
+            .load::<i64>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the events matching the filter within the height range, in the order they
+    /// were executed.
+    pub fn find_by_filter(
+        &self,
+        filter: &EventFilter,
+        min_height: i64,
+        max_height: i64,
+        chain_id: i64,
+    ) -> Result<Vec<Event>, DbError> {
+        use crate::schema::events::dsl::{
+            block, chain_id as chain_id_col, events, height as height_col, idx, module, name,
+            request_key,
+        };
+        use crate::schema::transactions::dsl::{
+            block as tx_block, request_key as tx_request_key, transactions,
+        };
+        use diesel::dsl::sql;
+        use diesel::sql_types::{Array, BigInt, Bool, Nullable, Text};
+        let mut conn = self.pool.get().unwrap();
+        let mut query = events
+            .left_join(transactions.on(tx_block.eq(block).and(tx_request_key.eq(request_key))))
+            .filter(chain_id_col.eq(chain_id))
+            .filter(height_col.ge(min_height))
+            .filter(height_col.le(max_height))
+            .into_boxed();
+        if !filter.modules.is_empty() {
+            query = query.filter(module.eq_any(filter.modules.clone()));
+        }
+        if !filter.names.is_empty() {
+            query = query.filter(name.eq_any(filter.names.clone()));
+        }
+        if !filter.qual_names.is_empty() {
+            let patterns = filter
+                .qual_names
+                .iter()
+                .map(|pattern| like_pattern(pattern))
+                .collect::<Vec<String>>();
+            query = query.filter(
+                sql::<Bool>("events.qual_name LIKE ANY(")
+                    .bind::<Array<Text>, _>(patterns)
+                    .sql(")"),
+            );
+        }
+        let results = query
+            .select(Event::as_select())
+            .order((
+                height_col.asc(),
+                sql::<Nullable<BigInt>>(TX_POSITION).asc(),
+                request_key.asc(),
+                idx.asc(),
+            ))
+            .load::<Event>(&mut conn)?;
+        Ok(results)
+    }
+
     #[allow(dead_code)]
     pub fn insert(&self, event: &Event) -> Result<Event, DbError> {
         use crate::schema::events::dsl::*;
@@ -310,35 +424,2640 @@ impl EventsRepository {
     }
 
     pub fn delete_all_by_block(&self, hash: &str) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        self.delete_all_by_block_with_conn(hash, &mut conn)
+    }
+
+    pub fn delete_all_by_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
         use crate::schema::events::dsl::{block as block_col, events};
+        let deleted = diesel::delete(events.filter(block_col.eq(hash))).execute(conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct MinersRepository {
+    pub pool: DbPool,
+}
+
+impl MinersRepository {
+    /// Returns the miners of the blocks created since `since`, ordered by the number of blocks
+    /// mined. The share is relative to all blocks created in the same window.
+    pub fn find_all(&self, since: NaiveDateTime, limit: i64) -> Result<Vec<MinerSummary>, DbError> {
+        use crate::schema::blocks::dsl::{blocks, creation_time, miner};
+        use diesel::dsl::{count_star, max, min};
+        let mut conn = self.pool.get().unwrap();
+        let total_blocks: i64 = blocks
+            .filter(creation_time.ge(since))
+            .count()
+            .get_result(&mut conn)?;
+        let results = blocks
+            .filter(creation_time.ge(since))
+            .group_by(miner)
+            .select((miner, count_star(), min(creation_time), max(creation_time)))
+            .order(count_star().desc())
+            .limit(limit)
+            .load::<(String, i64, Option<NaiveDateTime>, Option<NaiveDateTime>)>(&mut conn)?;
+        Ok(results
+            .into_iter()
+            .map(
+                |(account, blocks_mined, first_seen, last_seen)| MinerSummary {
+                    miner: account,
+                    blocks_mined,
+                    share: share(blocks_mined, total_blocks),
+                    first_seen,
+                    last_seen,
+                },
+            )
+            .collect())
+    }
+
+    /// Returns the blocks mined by `account` per chain, its share of the blocks created in each
+    /// of the given windows and the rewards received through coinbase transfers.
+    pub fn find_by_account(
+        &self,
+        account: &str,
+        windows: &[(String, NaiveDateTime)],
+    ) -> Result<Option<MinerDetails>, DbError> {
+        let (first_seen, last_seen) = self.find_first_last_seen(account)?;
+        if first_seen.is_none() {
+            return Ok(None);
+        }
+        let mut shares = HashMap::new();
+        for (window, since) in windows {
+            let mined = self.count_miner_blocks_by_chain(account, *since)?;
+            let totals = self.count_blocks_by_chain(*since)?;
+            let blocks_mined = mined.values().sum();
+            let total_blocks = totals.values().sum();
+            let chains = totals
+                .iter()
+                .map(|(chain, total)| (*chain, share(*mined.get(chain).unwrap_or(&0), *total)))
+                .collect();
+            shares.insert(
+                window.to_string(),
+                MinerShare {
+                    blocks_mined,
+                    total_blocks,
+                    share: share(blocks_mined, total_blocks),
+                    chains,
+                },
+            );
+        }
+        Ok(Some(MinerDetails {
+            miner: account.to_string(),
+            first_seen,
+            last_seen,
+            blocks_mined: self.count_miner_blocks_by_chain(
+                account,
+                NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+            )?,
+            share: shares,
+            rewards: self.find_rewards(account)?,
+        }))
+    }
+
+    pub fn find_first_last_seen(
+        &self,
+        account: &str,
+    ) -> Result<(Option<NaiveDateTime>, Option<NaiveDateTime>), DbError> {
+        use crate::schema::blocks::dsl::{blocks, creation_time, miner};
+        use diesel::dsl::{max, min};
+        let mut conn = self.pool.get().unwrap();
+        let result = blocks
+            .filter(miner.eq(account))
+            .select((min(creation_time), max(creation_time)))
+            .first::<(Option<NaiveDateTime>, Option<NaiveDateTime>)>(&mut conn)?;
+        Ok(result)
+    }
+
+    pub fn count_blocks_by_chain(
+        &self,
+        since: NaiveDateTime,
+    ) -> Result<HashMap<i64, i64>, DbError> {
+        use crate::schema::blocks::dsl::{blocks, chain_id, creation_time};
+        use diesel::dsl::count_star;
+        let mut conn = self.pool.get().unwrap();
+        let results = blocks
+            .filter(creation_time.ge(since))
+            .group_by(chain_id)
+            .select((chain_id, count_star()))
+            .load::<(i64, i64)>(&mut conn)?;
+        Ok(results.into_iter().collect())
+    }
+
+    pub fn count_miner_blocks_by_chain(
+        &self,
+        account: &str,
+        since: NaiveDateTime,
+    ) -> Result<HashMap<i64, i64>, DbError> {
+        use crate::schema::blocks::dsl::{blocks, chain_id, creation_time, miner};
+        use diesel::dsl::count_star;
+        let mut conn = self.pool.get().unwrap();
+        let results = blocks
+            .filter(miner.eq(account))
+            .filter(creation_time.ge(since))
+            .group_by(chain_id)
+            .select((chain_id, count_star()))
+            .load::<(i64, i64)>(&mut conn)?;
+        Ok(results.into_iter().collect())
+    }
+
+    /// Sums the coinbase transfers (transfers without a sender) received by `account`
+    /// in the blocks it mined.
+    pub fn find_rewards(&self, account: &str) -> Result<HashMap<i64, BigDecimal>, DbError> {
+        use crate::schema::{blocks, transfers};
+        let mut conn = self.pool.get().unwrap();
+        let results = transfers::table
+            .inner_join(blocks::table)
+            .filter(blocks::miner.eq(account))
+            .filter(transfers::from_account.eq(""))
+            .filter(transfers::to_account.eq(account))
+            .group_by(transfers::chain_id)
+            .select((transfers::chain_id, sum(transfers::amount)))
+            .load::<(i64, Option<BigDecimal>)>(&mut conn)?;
+        Ok(results
+            .into_iter()
+            .map(|(chain, amount)| (chain, amount.unwrap_or_default()))
+            .collect())
+    }
+}
+
+fn share(blocks_mined: i64, total_blocks: i64) -> f64 {
+    if total_blocks == 0 {
+        return 0.0;
+    }
+    blocks_mined as f64 / total_blocks as f64 * 100.0
+}
+
+#[derive(Clone)]
+pub struct HashrateRepository {
+    pub pool: DbPool,
+}
+
+impl HashrateRepository {
+    /// Recomputes the hourly buckets of `chain_id` between `from` (inclusive) and `to` (exclusive)
+    /// from the blocks table. Blocks indexed before targets were decoded have a target of 1 and
+    /// are skipped.
+    pub fn refresh_with_conn(
+        &self,
+        chain: i64,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::hashrate_buckets::dsl::{bucket, chain_id, hashrate_buckets};
+        use diesel::sql_types::{BigInt, Timestamptz};
+        diesel::delete(
+            hashrate_buckets
+                .filter(chain_id.eq(chain))
+                .filter(bucket.ge(from))
+                .filter(bucket.lt(to)),
+        )
+        .execute(conn)?;
+        let inserted = diesel::sql_query(
+            "INSERT INTO hashrate_buckets
+             SELECT chain_id,
+                    date_trunc('hour', creation_time),
+                    count(*),
+                    sum(power(2::numeric, 256) / target),
+                    min(creation_time),
+                    max(creation_time)
+             FROM blocks
+             WHERE chain_id = $1 AND creation_time >= $2 AND creation_time < $3 AND target > 1
+             GROUP BY chain_id, date_trunc('hour', creation_time)",
+        )
+        .bind::<BigInt, _>(chain)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .execute(conn)?;
+        Ok(inserted)
+    }
+
+    pub fn refresh(
+        &self,
+        chain: i64,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<usize, DbError> {
         let mut conn = self.pool.get().unwrap();
-        let deleted = diesel::delete(events.filter(block_col.eq(hash))).execute(&mut conn)?;
+        conn.transaction(|conn| self.refresh_with_conn(chain, from, to, conn))
+    }
+
+    pub fn find_by_range(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        chain: Option<i64>,
+    ) -> Result<Vec<HashrateBucket>, DbError> {
+        use crate::schema::hashrate_buckets::dsl::{bucket, chain_id, hashrate_buckets};
+        let mut conn = self.pool.get().unwrap();
+        let mut query = hashrate_buckets
+            .filter(bucket.ge(from))
+            .filter(bucket.lt(to))
+            .into_boxed();
+        if let Some(chain) = chain {
+            query = query.filter(chain_id.eq(chain));
+        }
+        let results = query
+            .order(bucket.asc())
+            .select(HashrateBucket::as_select())
+            .load::<HashrateBucket>(&mut conn)?;
+        Ok(results)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::hashrate_buckets::dsl::*;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(hashrate_buckets).execute(&mut conn)?;
         Ok(deleted)
     }
 }
 
 #[derive(Clone)]
-pub struct TransactionsRepository {
+pub struct CrossChainTransfersRepository {
     pub pool: DbPool,
 }
 
-impl TransactionsRepository {
-    // Transaction-aware version of insert_batch
-    pub fn insert_batch_with_conn(
+impl CrossChainTransfersRepository {
+    pub fn find_by_pact_id(&self, pact_id: &str) -> Result<Option<CrossChainTransfer>, DbError> {
+        use crate::schema::crosschain_transfers::dsl::{
+            crosschain_transfers, pact_id as pact_id_col,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let result = crosschain_transfers
+            .filter(pact_id_col.eq(pact_id))
+            .select(CrossChainTransfer::as_select())
+            .first::<CrossChainTransfer>(&mut conn)
+            .optional()?;
+        Ok(result)
+    }
+
+    /// Returns the cross-chain transfers sent by `account` whose continuation
+    /// has not been indexed on the target chain yet, latest first.
+    pub fn find_pending(&self, account: &str) -> Result<Vec<CrossChainTransfer>, DbError> {
+        use crate::schema::crosschain_transfers::dsl::{
+            crosschain_transfers, initiation_time, sender, status,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let results = crosschain_transfers
+            .filter(sender.eq(account))
+            .filter(status.eq("pending"))
+            .order(initiation_time.desc())
+            .select(CrossChainTransfer::as_select())
+            .load::<CrossChainTransfer>(&mut conn)?;
+        Ok(results)
+    }
+}
+
+#[derive(Clone)]
+pub struct StuckCrossChainTransfersRepository {
+    pub pool: DbPool,
+}
+
+impl StuckCrossChainTransfersRepository {
+    /// Replaces the stuck transfers with the step 0 cross-chain transactions created before
+    /// `initiated_before` that have no successful continuation. The continuation lookup goes
+    /// through `transactions_pactid_index`.
+    pub fn refresh(&self, initiated_before: NaiveDateTime) -> Result<usize, DbError> {
+        use crate::schema::stuck_crosschain_transfers::dsl::stuck_crosschain_transfers;
+        use diesel::sql_types::Timestamptz;
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(stuck_crosschain_transfers).execute(conn)?;
+            let inserted = diesel::sql_query(
+                "INSERT INTO stuck_crosschain_transfers
+                 SELECT DISTINCT ON (tx.pact_id)
+                        tx.pact_id,
+                        tx.request_key,
+                        tx.chain_id,
+                        tx.height,
+                        burn.module_name,
+                        burn.from_account,
+                        xchain.params ->> 1,
+                        (xchain.params ->> 3)::bigint,
+                        burn.amount,
+                        tx.creation_time,
+                        now()
+                 FROM transactions tx
+                 JOIN transfers burn
+                   ON burn.block = tx.block
+                   AND burn.request_key = tx.request_key
+                   AND burn.pact_id = tx.pact_id
+                   AND burn.to_account = ''
+                   AND burn.from_account <> ''
+                 LEFT JOIN events xchain
+                   ON xchain.block = tx.block
+                   AND xchain.request_key = tx.request_key
+                   AND xchain.module = burn.module_name
+                   AND xchain.name = 'TRANSFER_XCHAIN'
+                 WHERE tx.pact_id IS NOT NULL
+                   AND tx.step = 0
+                   AND tx.good_result IS NOT NULL
+                   AND tx.creation_time < $1
+                   AND NOT EXISTS (
+                     SELECT 1 FROM transactions cont
+                     WHERE cont.pact_id = tx.pact_id
+                       AND cont.good_result IS NOT NULL
+                       AND cont.step > 0
+                   )
+                 ORDER BY tx.pact_id, tx.height DESC",
+            )
+            .bind::<Timestamptz, _>(initiated_before)
+            .execute(conn)?;
+            Ok(inserted)
+        })
+    }
+
+    /// Returns the stuck transfers initiated before `initiated_before`, oldest first,
+    /// optionally only the ones sent or to be received by `account`.
+    pub fn find(
         &self,
-        transactions: &[Transaction],
-        conn: &mut PgConnection
+        account: Option<String>,
+        initiated_before: NaiveDateTime,
+    ) -> Result<Vec<StuckCrossChainTransfer>, DbError> {
+        use crate::schema::stuck_crosschain_transfers::dsl::{
+            initiation_time, receiver, sender, stuck_crosschain_transfers,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = stuck_crosschain_transfers
+            .filter(initiation_time.lt(initiated_before))
+            .into_boxed();
+        if let Some(account) = account {
+            query = query.filter(sender.eq(account.clone()).or(receiver.eq(account)));
+        }
+        let results = query
+            .order(initiation_time.asc())
+            .select(StuckCrossChainTransfer::as_select())
+            .load::<StuckCrossChainTransfer>(&mut conn)?;
+        Ok(results)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::stuck_crosschain_transfers::dsl::*;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(stuck_crosschain_transfers).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+/// Position of a transaction in its block. Transactions indexed before positions were
+/// stored fall back to their id, which follows the same order but is missing for failures.
+const TX_POSITION: &str = "coalesce(transactions.position, transactions.tx_id)";
+
+const BALANCE_DELTAS_QUERY: &str =
+    "SELECT account, module_name AS module, chain_id, sum(amount) AS balance
+     FROM (
+         SELECT to_account AS account, module_name, chain_id, amount
+         FROM transfers WHERE to_account <> ''
+         UNION ALL
+         SELECT from_account AS account, module_name, chain_id, -amount
+         FROM transfers WHERE from_account <> ''
+     ) AS deltas
+     GROUP BY account, module_name, chain_id";
+
+#[derive(Clone)]
+pub struct BalancesRepository {
+    pub pool: DbPool,
+}
+
+impl BalancesRepository {
+    /// Adds (`sign` = 1) or subtracts (`sign` = -1) the transfers of the given blocks
+    /// to the stored balances.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        sign: i32,
+        conn: &mut PgConnection,
     ) -> Result<usize, DbError> {
-        use crate::schema::transactions::dsl::transactions as transactions_table;
-        
-        let mut inserted = 0;
-        for chunk in transactions.chunks(1000) {
-            inserted += diesel::insert_into(transactions_table)
-                .values(chunk)
-                .on_conflict_do_nothing()
+        use diesel::sql_types::{Array, Integer, Text};
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+        let updated = diesel::sql_query(
+            "INSERT INTO balances
+             SELECT account, module_name, chain_id, sum(amount) * $2
+             FROM (
+                 SELECT to_account AS account, module_name, chain_id, amount
+                 FROM transfers WHERE block = ANY($1) AND to_account <> ''
+                 UNION ALL
+                 SELECT from_account AS account, module_name, chain_id, -amount
+                 FROM transfers WHERE block = ANY($1) AND from_account <> ''
+             ) AS deltas
+             GROUP BY account, module_name, chain_id
+             ON CONFLICT (account, module, chain_id)
+             DO UPDATE SET balance = balances.balance + EXCLUDED.balance",
+        )
+        .bind::<Array<Text>, _>(hashes)
+        .bind::<Integer, _>(sign)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Subtracts the transfers of a block that is about to be deleted.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        self.apply_blocks_with_conn(&[hash.to_string()], -1, conn)
+    }
+
+    pub fn find_by_account_module(
+        &self,
+        account: &str,
+        module: &str,
+    ) -> Result<HashMap<i64, BigDecimal>, DbError> {
+        use crate::schema::balances::dsl::{
+            account as account_col, balances, module as module_col,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let results = balances
+            .filter(account_col.eq(account))
+            .filter(module_col.eq(module))
+            .select(Balance::as_select())
+            .load(&mut conn)?;
+        Ok(results
+            .into_iter()
+            .map(|balance| (balance.chain_id, balance.balance))
+            .collect())
+    }
+
+    pub fn find_by_account(
+        &self,
+        account: &str,
+    ) -> Result<HashMap<String, HashMap<i64, BigDecimal>>, DbError> {
+        use crate::schema::balances::dsl::{account as account_col, balances};
+        let mut conn = self.pool.get().unwrap();
+        let results = balances
+            .filter(account_col.eq(account))
+            .select(Balance::as_select())
+            .load(&mut conn)?;
+        let mut all: HashMap<String, HashMap<i64, BigDecimal>> = HashMap::new();
+        for balance in results {
+            all.entry(balance.module)
+                .or_default()
+                .insert(balance.chain_id, balance.balance);
+        }
+        Ok(all)
+    }
+
+    /// Recomputes all balances from the transfers table.
+    pub fn rebuild(&self) -> Result<usize, DbError> {
+        use crate::schema::balances::dsl::balances;
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(balances).execute(conn)?;
+            let inserted =
+                diesel::sql_query(format!("INSERT INTO balances {}", BALANCE_DELTAS_QUERY))
+                    .execute(conn)?;
+            Ok(inserted)
+        })
+    }
+
+    /// Returns the balances that differ from the ones computed from the transfers table.
+    pub fn find_mismatches(&self) -> Result<Vec<BalanceMismatch>, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(format!(
+            "SELECT coalesce(b.account, c.account) AS account,
+                    coalesce(b.module, c.module) AS module,
+                    coalesce(b.chain_id, c.chain_id) AS chain_id,
+                    b.balance AS stored,
+                    c.balance AS computed
+             FROM balances b
+             FULL OUTER JOIN ({}) AS c
+             ON b.account = c.account AND b.module = c.module AND b.chain_id = c.chain_id
+             WHERE b.balance IS DISTINCT FROM c.balance",
+            BALANCE_DELTAS_QUERY
+        ))
+        .load::<BalanceMismatch>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the balance per chain computed from the transfers up to and including
+    /// `max_height` or `max_time`.
+    pub fn find_historical(
+        &self,
+        account: &str,
+        module: &str,
+        max_height: Option<i64>,
+        max_time: Option<NaiveDateTime>,
+    ) -> Result<HashMap<i64, BigDecimal>, DbError> {
+        use diesel::sql_types::{BigInt, Nullable, Text, Timestamptz};
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT chain_id,
+                    sum(CASE WHEN to_account = $1 THEN amount ELSE 0 END)
+                    - sum(CASE WHEN from_account = $1 THEN amount ELSE 0 END) AS balance
+             FROM transfers
+             WHERE (to_account = $1 OR from_account = $1)
+             AND module_name = $2
+             AND ($3 IS NULL OR height <= $3)
+             AND ($4 IS NULL OR creation_time <= $4)
+             GROUP BY chain_id",
+        )
+        .bind::<Text, _>(account)
+        .bind::<Text, _>(module)
+        .bind::<Nullable<BigInt>, _>(max_height)
+        .bind::<Nullable<Timestamptz>, _>(max_time)
+        .load::<HistoricalBalance>(&mut conn)?;
+        Ok(results
+            .into_iter()
+            .map(|balance| (balance.chain_id, balance.balance))
+            .collect())
+    }
+
+    /// Returns the balance of the account after every transfer, ordered by chain, height,
+    /// position of the transaction in the block and event index.
+    pub fn find_history(
+        &self,
+        account: &str,
+        module: Option<String>,
+        chain: Option<i64>,
+    ) -> Result<Vec<BalanceChange>, DbError> {
+        use diesel::sql_types::{BigInt, Nullable, Text};
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(format!(
+            "SELECT module_name AS module, chain_id, height, idx, block, request_key,
+                    creation_time, amount,
+                    sum(amount) OVER (
+                        PARTITION BY module_name, chain_id
+                        ORDER BY height, position, request_key, idx
+                        ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW
+                    ) AS balance
+             FROM (
+                 SELECT module_name, transfers.chain_id, transfers.height, idx,
+                        transfers.block, transfers.request_key, transfers.creation_time,
+                        {} AS position,
+                        CASE WHEN to_account = $1 THEN amount ELSE 0 END
+                        - CASE WHEN from_account = $1 THEN amount ELSE 0 END AS amount
+                 FROM transfers
+                 LEFT JOIN transactions ON transactions.block = transfers.block
+                 AND transactions.request_key = transfers.request_key
+                 WHERE (to_account = $1 OR from_account = $1)
+                 AND ($2 IS NULL OR module_name = $2)
+                 AND ($3 IS NULL OR transfers.chain_id = $3)
+             ) AS changes
+             ORDER BY module_name, chain_id, height, position, request_key, idx",
+            TX_POSITION
+        ))
+        .bind::<Text, _>(account)
+        .bind::<Nullable<Text>, _>(module)
+        .bind::<Nullable<BigInt>, _>(chain)
+        .load::<BalanceChange>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns up to `limit` random accounts holding a balance of the module.
+    pub fn sample_accounts(&self, module: &str, limit: i64) -> Result<Vec<String>, DbError> {
+        use crate::schema::balances::dsl::{account, balances, module as module_col};
+        use diesel::dsl::sql;
+        use diesel::sql_types::Double;
+        let mut conn = self.pool.get().unwrap();
+        let results = balances
+            .filter(module_col.eq(module))
+            .group_by(account)
+            .select(account)
+            .order(sql::<Double>("random()"))
+            .limit(limit)
+            .load::<String>(&mut conn)?;
+        Ok(results)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::balances::dsl::balances;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(balances).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct BalanceMismatchesRepository {
+    pub pool: DbPool,
+}
+
+impl BalanceMismatchesRepository {
+    /// Replaces the previous report for the account and module with the given mismatches.
+    pub fn replace(
+        &self,
+        account: &str,
+        module: &str,
+        mismatches: &[ReconciliationMismatch],
+    ) -> Result<usize, DbError> {
+        use crate::schema::balance_mismatches::dsl::{
+            account as account_col, balance_mismatches, module as module_col,
+        };
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(
+                balance_mismatches
+                    .filter(account_col.eq(account))
+                    .filter(module_col.eq(module)),
+            )
+            .execute(conn)?;
+            let inserted = diesel::insert_into(balance_mismatches)
+                .values(mismatches)
                 .execute(conn)?;
+            Ok(inserted)
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn find_all(&self) -> Result<Vec<ReconciliationMismatch>, DbError> {
+        use crate::schema::balance_mismatches::dsl::{account, balance_mismatches, chain_id};
+        let mut conn = self.pool.get().unwrap();
+        let results = balance_mismatches
+            .order((account.asc(), chain_id.asc()))
+            .select(ReconciliationMismatch::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::balance_mismatches::dsl::balance_mismatches;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(balance_mismatches).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct TokensRepository {
+    pub pool: DbPool,
+}
+
+impl TokensRepository {
+    /// Registers the modules and module hashes seen in the transfers of the given blocks
+    /// and adds (`sign` = 1) or subtracts (`sign` = -1) their transfers to the token stats.
+    /// The first seen fields keep the earliest transfer.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        sign: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{Array, Integer, Text};
+        if hashes.is_empty() {
+            return Ok(0);
         }
-        Ok(inserted)
+        if sign > 0 {
+            diesel::sql_query(
+                "INSERT INTO tokens (module, namespace, first_seen_height, first_seen_chain_id, first_seen_time)
+                 SELECT DISTINCT ON (module_name)
+                        module_name,
+                        CASE WHEN position('.' in module_name) > 0 THEN split_part(module_name, '.', 1) END,
+                        height,
+                        chain_id,
+                        creation_time
+                 FROM transfers
+                 WHERE block = ANY($1)
+                 ORDER BY module_name, creation_time
+                 ON CONFLICT (module) DO UPDATE
+                 SET first_seen_height = EXCLUDED.first_seen_height,
+                     first_seen_chain_id = EXCLUDED.first_seen_chain_id,
+                     first_seen_time = EXCLUDED.first_seen_time
+                 WHERE EXCLUDED.first_seen_time < tokens.first_seen_time",
+            )
+            .bind::<Array<Text>, _>(hashes)
+            .execute(conn)?;
+            diesel::sql_query(
+                "INSERT INTO token_module_hashes
+                        (module, module_hash, first_seen_height, first_seen_chain_id, first_seen_time)
+                 SELECT DISTINCT ON (module_name, module_hash)
+                        module_name, module_hash, height, chain_id, creation_time
+                 FROM transfers
+                 WHERE block = ANY($1)
+                 ORDER BY module_name, module_hash, creation_time
+                 ON CONFLICT (module, module_hash) DO UPDATE
+                 SET first_seen_height = EXCLUDED.first_seen_height,
+                     first_seen_chain_id = EXCLUDED.first_seen_chain_id,
+                     first_seen_time = EXCLUDED.first_seen_time
+                 WHERE EXCLUDED.first_seen_time < token_module_hashes.first_seen_time",
+            )
+            .bind::<Array<Text>, _>(hashes)
+            .execute(conn)?;
+        }
+        let updated = diesel::sql_query(
+            "UPDATE tokens
+             SET transfer_count = tokens.transfer_count + stats.transfer_count * $2,
+                 volume = tokens.volume + stats.volume * $2
+             FROM (
+                 SELECT module_name, count(*) AS transfer_count, sum(amount) AS volume
+                 FROM transfers
+                 WHERE block = ANY($1)
+                 GROUP BY module_name
+             ) AS stats
+             WHERE tokens.module = stats.module_name",
+        )
+        .bind::<Array<Text>, _>(hashes)
+        .bind::<Integer, _>(sign)
+        .execute(conn)?;
+        diesel::sql_query(
+            "UPDATE token_module_hashes
+             SET transfer_count = token_module_hashes.transfer_count + stats.transfer_count * $2
+             FROM (
+                 SELECT module_name, module_hash, count(*) AS transfer_count
+                 FROM transfers
+                 WHERE block = ANY($1)
+                 GROUP BY module_name, module_hash
+             ) AS stats
+             WHERE token_module_hashes.module = stats.module_name
+             AND token_module_hashes.module_hash = stats.module_hash",
+        )
+        .bind::<Array<Text>, _>(hashes)
+        .bind::<Integer, _>(sign)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Subtracts the transfers of a block that is about to be deleted from the token stats,
+    /// then removes the tokens and module hashes that have no transfers left.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::Text;
+        let updated = self.apply_blocks_with_conn(&[hash.to_string()], -1, conn)?;
+        diesel::sql_query(
+            "DELETE FROM token_module_hashes
+             USING (
+                 SELECT DISTINCT module_name, module_hash FROM transfers WHERE block = $1
+             ) AS reverted
+             WHERE token_module_hashes.module = reverted.module_name
+             AND token_module_hashes.module_hash = reverted.module_hash
+             AND token_module_hashes.transfer_count <= 0",
+        )
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+        diesel::sql_query(
+            "DELETE FROM tokens
+             USING (SELECT DISTINCT module_name FROM transfers WHERE block = $1) AS reverted
+             WHERE tokens.module = reverted.module_name
+             AND tokens.transfer_count <= 0",
+        )
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Recomputes the transfer count and volume of all tokens, and the transfer count of
+    /// their module hashes, from the transfers table.
+    pub fn rebuild_stats(&self) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        diesel::sql_query(
+            "UPDATE token_module_hashes
+             SET transfer_count = coalesce(stats.transfer_count, 0)
+             FROM token_module_hashes AS h
+             LEFT JOIN (
+                 SELECT module_name, module_hash, count(*) AS transfer_count
+                 FROM transfers
+                 GROUP BY module_name, module_hash
+             ) AS stats ON stats.module_name = h.module AND stats.module_hash = h.module_hash
+             WHERE token_module_hashes.module = h.module
+             AND token_module_hashes.module_hash = h.module_hash",
+        )
+        .execute(&mut conn)?;
+        let updated = diesel::sql_query(
+            "UPDATE tokens
+             SET transfer_count = coalesce(stats.transfer_count, 0),
+                 volume = coalesce(stats.volume, 0)
+             FROM tokens AS t
+             LEFT JOIN (
+                 SELECT module_name, count(*) AS transfer_count, sum(amount) AS volume
+                 FROM transfers
+                 GROUP BY module_name
+             ) AS stats ON stats.module_name = t.module
+             WHERE tokens.module = t.module",
+        )
+        .execute(&mut conn)?;
+        Ok(updated)
+    }
+
+    pub fn find_all(&self) -> Result<Vec<TokenSummary>, DbError> {
+        use crate::schema::tokens::dsl::{tokens, transfer_count};
+        let mut conn = self.pool.get().unwrap();
+        let results = tokens
+            .order(transfer_count.desc())
+            .select(Token::as_select())
+            .load(&mut conn)?;
+        let holders = self.count_holders(None)?;
+        Ok(results
+            .into_iter()
+            .map(|token| TokenSummary {
+                holders: *holders.get(&token.module).unwrap_or(&0),
+                token,
+            })
+            .collect())
+    }
+
+    pub fn find_by_module(&self, module: &str) -> Result<Option<TokenDetails>, DbError> {
+        use crate::schema::token_module_hashes::dsl::{
+            first_seen_time, module as hash_module_col, token_module_hashes,
+        };
+        use crate::schema::tokens::dsl::{module as module_col, tokens};
+        let mut conn = self.pool.get().unwrap();
+        let token = match tokens
+            .filter(module_col.eq(module))
+            .select(Token::as_select())
+            .first::<Token>(&mut conn)
+            .optional()?
+        {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let module_hashes = token_module_hashes
+            .filter(hash_module_col.eq(module))
+            .order(first_seen_time.asc())
+            .select(TokenModuleHash::as_select())
+            .load(&mut conn)?;
+        let holders = self.count_holders(Some(module))?;
+        Ok(Some(TokenDetails {
+            holders: *holders.get(module).unwrap_or(&0),
+            token,
+            module_hashes,
+        }))
+    }
+
+    /// Counts the accounts with a positive balance on any chain per module.
+    fn count_holders(&self, module: Option<&str>) -> Result<HashMap<String, i64>, DbError> {
+        use crate::schema::balances::dsl::{account, balance, balances, module as module_col};
+        use diesel::dsl::count_distinct;
+        let mut conn = self.pool.get().unwrap();
+        let query = balances
+            .filter(balance.gt(BigDecimal::from(0)))
+            .group_by(module_col)
+            .select((module_col, count_distinct(account)));
+        let results = match module {
+            Some(module) => query
+                .filter(module_col.eq(module))
+                .load::<(String, i64)>(&mut conn)?,
+            None => query.load::<(String, i64)>(&mut conn)?,
+        };
+        Ok(results.into_iter().collect())
+    }
+
+    /// Returns the accounts with a positive balance of the module ranked by balance, summed
+    /// over all chains unless `chain` is given.
+    pub fn find_holders(
+        &self,
+        module: &str,
+        chain: Option<i64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TokenHolder>, DbError> {
+        use diesel::sql_types::{BigInt, Nullable, Text};
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT account, sum(balance) AS balance
+             FROM balances
+             WHERE module = $1 AND ($2 IS NULL OR chain_id = $2)
+             GROUP BY account
+             HAVING sum(balance) > 0
+             ORDER BY balance DESC, account
+             LIMIT $3 OFFSET $4",
+        )
+        .bind::<Text, _>(module)
+        .bind::<Nullable<BigInt>, _>(chain)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<TokenHolder>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Computes the supply of the module as mints (transfers without a sender) minus burns
+    /// (transfers without a receiver), on all chains unless `chain` is given.
+    pub fn find_total_supply(
+        &self,
+        module: &str,
+        chain: Option<i64>,
+    ) -> Result<BigDecimal, DbError> {
+        use crate::schema::transfers::dsl::{
+            amount, chain_id, from_account, module_name, to_account, transfers,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut minted = transfers
+            .filter(module_name.eq(module))
+            .filter(from_account.eq(""))
+            .select(sum(amount))
+            .into_boxed();
+        let mut burned = transfers
+            .filter(module_name.eq(module))
+            .filter(to_account.eq(""))
+            .select(sum(amount))
+            .into_boxed();
+        if let Some(chain) = chain {
+            minted = minted.filter(chain_id.eq(chain));
+            burned = burned.filter(chain_id.eq(chain));
+        }
+        let minted = minted.first::<Option<BigDecimal>>(&mut conn)?;
+        let burned = burned.first::<Option<BigDecimal>>(&mut conn)?;
+        Ok(minted.unwrap_or_default() - burned.unwrap_or_default())
+    }
+
+    pub fn find_without_precision(&self) -> Result<Vec<String>, DbError> {
+        use crate::schema::tokens::dsl::{module, precision, tokens};
+        let mut conn = self.pool.get().unwrap();
+        let results = tokens
+            .filter(precision.is_null())
+            .select(module)
+            .order(module.asc())
+            .load::<String>(&mut conn)?;
+        Ok(results)
+    }
+
+    pub fn update_precision(&self, module: &str, value: i32) -> Result<usize, DbError> {
+        use crate::schema::tokens::dsl::{module as module_col, precision, tokens};
+        let mut conn = self.pool.get().unwrap();
+        let updated = diesel::update(tokens.filter(module_col.eq(module)))
+            .set(precision.eq(value))
+            .execute(&mut conn)?;
+        Ok(updated)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::token_module_hashes::dsl::token_module_hashes;
+        use crate::schema::tokens::dsl::tokens;
+        let mut conn = self.pool.get().unwrap();
+        diesel::delete(token_module_hashes).execute(&mut conn)?;
+        let deleted = diesel::delete(tokens).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct SupplySnapshotsRepository {
+    pub pool: DbPool,
+}
+
+impl SupplySnapshotsRepository {
+    /// Adds (`sign` = 1) or subtracts (`sign` = -1) the mints and burns of the given blocks
+    /// to the hourly snapshots. Transfers made by the `transfer-crosschain` pact of their
+    /// module, or next to its `TRANSFER_XCHAIN` event, are counted as cross-chain.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        sign: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{Array, Integer, Text};
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+        let updated = diesel::sql_query(
+            "INSERT INTO supply_snapshots
+             SELECT module_name,
+                    chain_id,
+                    date_trunc('hour', creation_time),
+                    sum(CASE WHEN from_account = '' AND NOT crosschain THEN amount ELSE 0 END) * $2,
+                    sum(CASE WHEN to_account = '' AND NOT crosschain THEN amount ELSE 0 END) * $2,
+                    sum(CASE WHEN from_account = '' AND crosschain THEN amount ELSE 0 END) * $2,
+                    sum(CASE WHEN to_account = '' AND crosschain THEN amount ELSE 0 END) * $2
+             FROM (
+                 SELECT transfers.module_name, transfers.chain_id, transfers.creation_time,
+                        transfers.from_account, transfers.to_account, transfers.amount,
+                        (transfers.pact_id IS NOT NULL AND (
+                            tx.continuation -> 'continuation' ->> 'def'
+                                = transfers.module_name || '.transfer-crosschain'
+                            OR EXISTS (
+                                SELECT 1 FROM events xchain
+                                WHERE xchain.block = transfers.block
+                                AND xchain.request_key = transfers.request_key
+                                AND xchain.module = transfers.module_name
+                                AND xchain.name = 'TRANSFER_XCHAIN'
+                            )
+                        )) IS TRUE AS crosschain
+                 FROM transfers
+                 LEFT JOIN transactions tx
+                   ON tx.block = transfers.block AND tx.request_key = transfers.request_key
+                 WHERE transfers.block = ANY($1)
+                 AND (transfers.from_account = '' OR transfers.to_account = '')
+             ) AS supply_transfers
+             GROUP BY module_name, chain_id, date_trunc('hour', creation_time)
+             ON CONFLICT (module, chain_id, bucket) DO UPDATE
+             SET minted = supply_snapshots.minted + EXCLUDED.minted,
+                 burned = supply_snapshots.burned + EXCLUDED.burned,
+                 crosschain_in = supply_snapshots.crosschain_in + EXCLUDED.crosschain_in,
+                 crosschain_out = supply_snapshots.crosschain_out + EXCLUDED.crosschain_out",
+        )
+        .bind::<Array<Text>, _>(hashes)
+        .bind::<Integer, _>(sign)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Subtracts the mints and burns of a block that is about to be deleted.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        self.apply_blocks_with_conn(&[hash.to_string()], -1, conn)
+    }
+
+    pub fn find_by_range(
+        &self,
+        module: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        chain: Option<i64>,
+    ) -> Result<Vec<SupplySnapshot>, DbError> {
+        use crate::schema::supply_snapshots::dsl::{
+            bucket, chain_id, module as module_col, supply_snapshots,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = supply_snapshots
+            .filter(module_col.eq(module))
+            .filter(bucket.ge(from))
+            .filter(bucket.lt(to))
+            .into_boxed();
+        if let Some(chain) = chain {
+            query = query.filter(chain_id.eq(chain));
+        }
+        let results = query
+            .order(bucket.asc())
+            .select(SupplySnapshot::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the supply of the module accumulated before `before`, including tokens
+    /// moved across chains.
+    pub fn find_supply_before(
+        &self,
+        module: &str,
+        before: NaiveDateTime,
+        chain: Option<i64>,
+    ) -> Result<BigDecimal, DbError> {
+        use crate::schema::supply_snapshots::dsl::{
+            bucket, burned, chain_id, crosschain_in, crosschain_out, minted, module as module_col,
+            supply_snapshots,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = supply_snapshots
+            .filter(module_col.eq(module))
+            .filter(bucket.lt(before))
+            .select(sum(minted - burned + crosschain_in - crosschain_out))
+            .into_boxed();
+        if let Some(chain) = chain {
+            query = query.filter(chain_id.eq(chain));
+        }
+        let supply = query.first::<Option<BigDecimal>>(&mut conn)?;
+        Ok(supply.unwrap_or_default())
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::supply_snapshots::dsl::supply_snapshots;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(supply_snapshots).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct NftRepository {
+    pub pool: DbPool,
+}
+
+impl NftRepository {
+    /// Inserts a token or fills in its metadata if it was already created by a MINT.
+    pub fn upsert_token_with_conn(
+        &self,
+        token: &NftToken,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_tokens::dsl::{
+            block, chain_id, creation_time, height, module, nft_tokens, policies, precision,
+            request_key, token_id, uri,
+        };
+        use diesel::upsert::excluded;
+        let inserted = diesel::insert_into(nft_tokens)
+            .values(token)
+            .on_conflict((chain_id, token_id))
+            .do_update()
+            .set((
+                module.eq(excluded(module)),
+                precision.eq(excluded(precision)),
+                uri.eq(excluded(uri)),
+                policies.eq(excluded(policies)),
+                block.eq(excluded(block)),
+                height.eq(excluded(height)),
+                request_key.eq(excluded(request_key)),
+                creation_time.eq(excluded(creation_time)),
+            ))
+            .execute(conn)?;
+        Ok(inserted)
+    }
+
+    /// Adds `delta` to the supply of a token, the token is created without metadata if its
+    /// TOKEN event hasn't been indexed yet.
+    pub fn add_supply_with_conn(
+        &self,
+        chain: i64,
+        token: &str,
+        delta: &BigDecimal,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_tokens::dsl::{chain_id, nft_tokens, supply, token_id};
+        use diesel::upsert::excluded;
+        let updated = diesel::insert_into(nft_tokens)
+            .values((chain_id.eq(chain), token_id.eq(token), supply.eq(delta)))
+            .on_conflict((chain_id, token_id))
+            .do_update()
+            .set(supply.eq(supply + excluded(supply)))
+            .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Removes the metadata set by the TOKEN events of a block.
+    pub fn clear_token_metadata_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_tokens::dsl::{
+            block, creation_time, height, module, nft_tokens, policies, precision, request_key, uri,
+        };
+        let updated = diesel::update(nft_tokens.filter(block.eq(hash)))
+            .set((
+                module.eq(None::<String>),
+                precision.eq(None::<i32>),
+                uri.eq(None::<String>),
+                policies.eq(None::<serde_json::Value>),
+                block.eq(None::<String>),
+                height.eq(None::<i64>),
+                request_key.eq(None::<String>),
+                creation_time.eq(None::<NaiveDateTime>),
+            ))
+            .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Stores the balance unless a balance set by a later event is already stored. Events
+    /// are compared by height, position of their transaction in the block and index.
+    pub fn update_balance_with_conn(
+        &self,
+        balance: &NftBalance,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{BigInt, Numeric, Text};
+        let updated = diesel::sql_query(
+            "INSERT INTO nft_balances VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (chain_id, token_id, account) DO UPDATE
+             SET balance = EXCLUDED.balance,
+                 block = EXCLUDED.block,
+                 request_key = EXCLUDED.request_key,
+                 height = EXCLUDED.height,
+                 idx = EXCLUDED.idx
+             WHERE (
+                 EXCLUDED.height,
+                 coalesce((
+                     SELECT coalesce(position, tx_id) FROM transactions
+                     WHERE block = EXCLUDED.block AND request_key = EXCLUDED.request_key
+                 ), -1),
+                 EXCLUDED.idx
+             ) > (
+                 nft_balances.height,
+                 coalesce((
+                     SELECT coalesce(position, tx_id) FROM transactions
+                     WHERE block = nft_balances.block AND request_key = nft_balances.request_key
+                 ), -1),
+                 nft_balances.idx
+             )",
+        )
+        .bind::<BigInt, _>(balance.chain_id)
+        .bind::<Text, _>(&balance.token_id)
+        .bind::<Text, _>(&balance.account)
+        .bind::<Numeric, _>(&balance.balance)
+        .bind::<Text, _>(&balance.block)
+        .bind::<Text, _>(&balance.request_key)
+        .bind::<BigInt, _>(balance.height)
+        .bind::<BigInt, _>(balance.idx)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Replaces a balance that was last set by an event of the block `reverted`.
+    pub fn restore_balance_with_conn(
+        &self,
+        reverted: &str,
+        balance: &NftBalance,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_balances::dsl::{
+            account, balance as balance_col, block, chain_id, height, idx, nft_balances,
+            request_key, token_id,
+        };
+        let updated = diesel::update(
+            nft_balances
+                .filter(chain_id.eq(balance.chain_id))
+                .filter(token_id.eq(&balance.token_id))
+                .filter(account.eq(&balance.account))
+                .filter(block.eq(reverted)),
+        )
+        .set((
+            balance_col.eq(&balance.balance),
+            block.eq(&balance.block),
+            request_key.eq(&balance.request_key),
+            height.eq(balance.height),
+            idx.eq(balance.idx),
+        ))
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Stores a sale offered with a SALE (or OFFER) event.
+    pub fn upsert_offer_with_conn(
+        &self,
+        sale: &NftSale,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_sales::dsl::{
+            amount, chain_id, nft_sales, offer_block, offer_height, offer_request_key, offer_time,
+            sale_id, seller, timeout,
+        };
+        use diesel::upsert::excluded;
+        let inserted = diesel::insert_into(nft_sales)
+            .values(sale)
+            .on_conflict((chain_id, sale_id))
+            .do_update()
+            .set((
+                seller.eq(excluded(seller)),
+                amount.eq(excluded(amount)),
+                timeout.eq(excluded(timeout)),
+                offer_block.eq(excluded(offer_block)),
+                offer_request_key.eq(excluded(offer_request_key)),
+                offer_height.eq(excluded(offer_height)),
+                offer_time.eq(excluded(offer_time)),
+            ))
+            .execute(conn)?;
+        Ok(inserted)
+    }
+
+    /// Marks a sale as bought, the sale is created if its offer hasn't been indexed yet.
+    pub fn record_buy_with_conn(
+        &self,
+        sale: &NftSale,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_sales::dsl::{
+            buy_block, buy_height, buy_request_key, buy_time, buyer, chain_id, nft_sales, sale_id,
+        };
+        use diesel::upsert::excluded;
+        let inserted = diesel::insert_into(nft_sales)
+            .values(sale)
+            .on_conflict((chain_id, sale_id))
+            .do_update()
+            .set((
+                buyer.eq(excluded(buyer)),
+                buy_block.eq(excluded(buy_block)),
+                buy_request_key.eq(excluded(buy_request_key)),
+                buy_height.eq(excluded(buy_height)),
+                buy_time.eq(excluded(buy_time)),
+            ))
+            .execute(conn)?;
+        Ok(inserted)
+    }
+
+    /// Undoes the offers and purchases of a block. Sales with neither left are deleted.
+    pub fn revert_sales_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::nft_sales::dsl::{
+            buy_block, buy_height, buy_request_key, buy_time, buyer, nft_sales, offer_block,
+            offer_height, offer_request_key, offer_time,
+        };
+        let mut updated = diesel::update(nft_sales.filter(offer_block.eq(hash)))
+            .set((
+                offer_block.eq(None::<String>),
+                offer_request_key.eq(None::<String>),
+                offer_height.eq(None::<i64>),
+                offer_time.eq(None::<NaiveDateTime>),
+            ))
+            .execute(conn)?;
+        updated += diesel::update(nft_sales.filter(buy_block.eq(hash)))
+            .set((
+                buyer.eq(None::<String>),
+                buy_block.eq(None::<String>),
+                buy_request_key.eq(None::<String>),
+                buy_height.eq(None::<i64>),
+                buy_time.eq(None::<NaiveDateTime>),
+            ))
+            .execute(conn)?;
+        diesel::delete(
+            nft_sales
+                .filter(offer_block.is_null())
+                .filter(buy_block.is_null()),
+        )
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Returns the events of the modules emitted in the block, in block order.
+    pub fn find_ledger_events_with_conn(
+        &self,
+        hash: &str,
+        modules: &[&str],
+        conn: &mut PgConnection,
+    ) -> Result<Vec<Event>, DbError> {
+        use crate::schema::events::dsl::{block, events, idx, module, request_key};
+        use crate::schema::transactions::dsl::{
+            block as tx_block, request_key as tx_request_key, transactions,
+        };
+        use diesel::dsl::sql;
+        use diesel::sql_types::{BigInt, Nullable};
+        let results = events
+            .left_join(transactions.on(tx_block.eq(block).and(tx_request_key.eq(request_key))))
+            .filter(block.eq(hash))
+            .filter(module.eq_any(modules.to_vec()))
+            .order((
+                sql::<Nullable<BigInt>>(TX_POSITION).asc(),
+                request_key.asc(),
+                idx.asc(),
+            ))
+            .select(Event::as_select())
+            .load(conn)?;
+        Ok(results)
+    }
+
+    pub fn find_tokens(&self, token: &str) -> Result<Vec<NftToken>, DbError> {
+        use crate::schema::nft_tokens::dsl::{chain_id, nft_tokens, token_id};
+        let mut conn = self.pool.get().unwrap();
+        let results = nft_tokens
+            .filter(token_id.eq(token))
+            .order(chain_id.asc())
+            .select(NftToken::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    pub fn find_owners(&self, token: &str, chain: Option<i64>) -> Result<Vec<NftBalance>, DbError> {
+        use crate::schema::nft_balances::dsl::{balance, chain_id, nft_balances, token_id};
+        let mut conn = self.pool.get().unwrap();
+        let mut query = nft_balances
+            .filter(token_id.eq(token))
+            .filter(balance.gt(BigDecimal::from(0)))
+            .into_boxed();
+        if let Some(chain) = chain {
+            query = query.filter(chain_id.eq(chain));
+        }
+        let results = query
+            .order((balance.desc(), chain_id.asc()))
+            .select(NftBalance::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    pub fn find_sales(&self, token: &str, chain: Option<i64>) -> Result<Vec<NftSale>, DbError> {
+        use crate::schema::nft_sales::dsl::{chain_id, nft_sales, offer_height, token_id};
+        let mut conn = self.pool.get().unwrap();
+        let mut query = nft_sales.filter(token_id.eq(token)).into_boxed();
+        if let Some(chain) = chain {
+            query = query.filter(chain_id.eq(chain));
+        }
+        // Sales whose offer wasn't indexed have no offer height and come first
+        let results = query
+            .order(offer_height.desc())
+            .select(NftSale::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::nft_balances::dsl::nft_balances;
+        use crate::schema::nft_sales::dsl::nft_sales;
+        use crate::schema::nft_tokens::dsl::nft_tokens;
+        let mut conn = self.pool.get().unwrap();
+        diesel::delete(nft_balances).execute(&mut conn)?;
+        diesel::delete(nft_sales).execute(&mut conn)?;
+        let deleted = diesel::delete(nft_tokens).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+/// Tables created from the event mappings config. Table and column names are validated when
+/// the config is loaded, they can't be bound as parameters.
+#[derive(Clone)]
+pub struct MappedEventsRepository {
+    pub pool: DbPool,
+}
+
+impl MappedEventsRepository {
+    /// Creates the table if it doesn't exist and adds the columns missing from it, with the
+    /// event location as primary key.
+    pub fn create_table(&self, table: &str, columns: &[(String, &str)]) -> Result<(), DbError> {
+        use diesel::connection::SimpleConnection;
+        let mut conn = self.pool.get().unwrap();
+        let mut statements = vec![format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (
+                 block VARCHAR NOT NULL,
+                 chain_id BIGINT NOT NULL,
+                 height BIGINT NOT NULL,
+                 idx BIGINT NOT NULL,
+                 request_key VARCHAR NOT NULL,
+                 creation_time TIMESTAMPTZ NOT NULL,
+                 PRIMARY KEY (block, idx, request_key)
+             )",
+            table
+        )];
+        for (column, sql_type) in columns.iter() {
+            statements.push(format!(
+                "ALTER TABLE \"{}\" ADD COLUMN IF NOT EXISTS \"{}\" {}",
+                table, column, sql_type
+            ));
+        }
+        conn.batch_execute(&statements.join(";\n"))?;
+        Ok(())
+    }
+
+    /// Inserts a JSON array of objects keyed by column name, values are cast by Postgres to
+    /// the column types.
+    pub fn insert_rows_with_conn(
+        &self,
+        table: &str,
+        rows: &serde_json::Value,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::Jsonb;
+        let inserted = diesel::sql_query(format!(
+            "INSERT INTO \"{0}\"
+             SELECT * FROM jsonb_populate_recordset(NULL::\"{0}\", $1)
+             ON CONFLICT DO NOTHING",
+            table
+        ))
+        .bind::<Jsonb, _>(rows)
+        .execute(conn)?;
+        Ok(inserted)
+    }
+
+    pub fn delete_by_block(&self, table: &str, hash: &str) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        self.delete_by_block_with_conn(table, hash, &mut conn)
+    }
+
+    pub fn delete_by_block_with_conn(
+        &self,
+        table: &str,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::Text;
+        let deleted = diesel::sql_query(format!("DELETE FROM \"{}\" WHERE block = $1", table))
+            .bind::<Text, _>(hash)
+            .execute(conn)?;
+        Ok(deleted)
+    }
+
+    pub fn delete_all(&self, table: &str) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::sql_query(format!("DELETE FROM \"{}\"", table)).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct DexRepository {
+    pub pool: DbPool,
+}
+
+impl DexRepository {
+    pub fn insert_swaps_with_conn(
+        &self,
+        swaps: &[Swap],
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::swaps::dsl::swaps as swaps_table;
+        let mut inserted = 0;
+        for chunk in swaps.chunks(1000) {
+            inserted += diesel::insert_into(swaps_table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(inserted)
+    }
+
+    pub fn insert_reserves_with_conn(
+        &self,
+        reserves: &[PoolReserve],
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::pool_reserves::dsl::pool_reserves;
+        let mut inserted = 0;
+        for chunk in reserves.chunks(1000) {
+            inserted += diesel::insert_into(pool_reserves)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(inserted)
+    }
+
+    pub fn insert_liquidity_changes_with_conn(
+        &self,
+        changes: &[LiquidityChange],
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::liquidity_changes::dsl::liquidity_changes;
+        let mut inserted = 0;
+        for chunk in changes.chunks(1000) {
+            inserted += diesel::insert_into(liquidity_changes)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(inserted)
+    }
+
+    /// Returns the swaps of the pair that have a price, oldest first.
+    pub fn find_swaps(
+        &self,
+        pair: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        module: Option<&str>,
+    ) -> Result<Vec<Swap>, DbError> {
+        use crate::schema::swaps::dsl::{
+            creation_time, height, idx, module as module_col, pair as pair_col, price, swaps,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = swaps
+            .filter(pair_col.eq(pair))
+            .filter(creation_time.ge(from))
+            .filter(creation_time.lt(to))
+            .filter(price.is_not_null())
+            .into_boxed();
+        if let Some(module) = module {
+            query = query.filter(module_col.eq(module));
+        }
+        let results = query
+            .select(Swap::as_select())
+            .order((creation_time.asc(), height.asc(), idx.asc()))
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    pub fn delete_all_by_block(&self, hash: &str) -> Result<usize, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| self.delete_all_by_block_with_conn(hash, conn))
+    }
+
+    pub fn delete_all_by_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::liquidity_changes::dsl::{block as liquidity_block, liquidity_changes};
+        use crate::schema::pool_reserves::dsl::{block as reserves_block, pool_reserves};
+        use crate::schema::swaps::dsl::{block as swaps_block, swaps};
+        let mut deleted = diesel::delete(swaps.filter(swaps_block.eq(hash))).execute(conn)?;
+        deleted += diesel::delete(pool_reserves.filter(reserves_block.eq(hash))).execute(conn)?;
+        deleted +=
+            diesel::delete(liquidity_changes.filter(liquidity_block.eq(hash))).execute(conn)?;
+        Ok(deleted)
+    }
+
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::liquidity_changes::dsl::liquidity_changes;
+        use crate::schema::pool_reserves::dsl::pool_reserves;
+        use crate::schema::swaps::dsl::swaps;
+        let mut conn = self.pool.get().unwrap();
+        diesel::delete(liquidity_changes).execute(&mut conn)?;
+        diesel::delete(pool_reserves).execute(&mut conn)?;
+        let deleted = diesel::delete(swaps).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct AnalyticsRepository {
+    pub pool: DbPool,
+}
+
+impl AnalyticsRepository {
+    /// Returns the transfers sent or received by the account on all chains, oldest first.
+    /// Transfers of the same block are in the order their transactions were executed.
+    pub fn find_account_transfers(
+        &self,
+        account: &str,
+        module: &str,
+    ) -> Result<Vec<Transfer>, DbError> {
+        use crate::schema::transactions::dsl::{
+            block as tx_block, request_key as tx_request_key, transactions,
+        };
+        use crate::schema::transfers::dsl::{
+            block, creation_time, from_account, height, idx, module_name, request_key, to_account,
+            transfers,
+        };
+        use diesel::dsl::sql;
+        use diesel::sql_types::{BigInt, Nullable};
+        let mut conn = self.pool.get().unwrap();
+        let results = transfers
+            .left_join(transactions.on(tx_block.eq(block).and(tx_request_key.eq(request_key))))
+            .filter(module_name.eq(module))
+            .filter(from_account.eq(account).or(to_account.eq(account)))
+            .select(Transfer::as_select())
+            .order((
+                creation_time.asc(),
+                height.asc(),
+                sql::<Nullable<BigInt>>(TX_POSITION).asc(),
+                request_key.asc(),
+                idx.asc(),
+            ))
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the accounts that sent or received the module, ordered by name.
+    pub fn find_accounts(&self, module: &str) -> Result<Vec<String>, DbError> {
+        use crate::schema::transfers::dsl::{from_account, module_name, to_account, transfers};
+        use std::collections::BTreeSet;
+        let mut conn = self.pool.get().unwrap();
+        let senders = transfers
+            .filter(module_name.eq(module))
+            .filter(from_account.ne(""))
+            .select(from_account)
+            .distinct()
+            .load::<String>(&mut conn)?;
+        let receivers = transfers
+            .filter(module_name.eq(module))
+            .filter(to_account.ne(""))
+            .select(to_account)
+            .distinct()
+            .load::<String>(&mut conn)?;
+        let accounts = senders
+            .into_iter()
+            .chain(receivers)
+            .collect::<BTreeSet<String>>();
+        Ok(accounts.into_iter().collect())
+    }
+
+    /// Returns the transfers sent or received by any of the accounts, oldest first, in the
+    /// same order as `find_account_transfers`.
+    pub fn find_accounts_transfers(
+        &self,
+        accounts: &[String],
+        module: &str,
+    ) -> Result<Vec<Transfer>, DbError> {
+        use crate::schema::transactions::dsl::{
+            block as tx_block, request_key as tx_request_key, transactions,
+        };
+        use crate::schema::transfers::dsl::{
+            block, creation_time, from_account, height, idx, module_name, request_key, to_account,
+            transfers,
+        };
+        use diesel::dsl::sql;
+        use diesel::sql_types::{BigInt, Nullable};
+        let mut conn = self.pool.get().unwrap();
+        let results = transfers
+            .left_join(transactions.on(tx_block.eq(block).and(tx_request_key.eq(request_key))))
+            .filter(module_name.eq(module))
+            .filter(
+                from_account
+                    .eq_any(accounts.to_vec())
+                    .or(to_account.eq_any(accounts.to_vec())),
+            )
+            .select(Transfer::as_select())
+            .order((
+                creation_time.asc(),
+                height.asc(),
+                sql::<Nullable<BigInt>>(TX_POSITION).asc(),
+                request_key.asc(),
+                idx.asc(),
+            ))
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the creation time, gas price and code of the latest transactions sent by the
+    /// account, newest first.
+    pub fn find_sender_transactions(
+        &self,
+        account: &str,
+        limit: i64,
+    ) -> Result<Vec<(NaiveDateTime, f64, Option<String>)>, DbError> {
+        use crate::schema::transactions::dsl::{
+            code, creation_time, gas_price, sender, transactions,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let results = transactions
+            .filter(sender.eq(account))
+            .select((creation_time, gas_price, code))
+            .order(creation_time.desc())
+            .limit(limit)
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the receivers of the latest transfers sent by the account, newest first. Gas
+    /// payments to miners and cross-chain transfers are left out.
+    pub fn find_sent_transfer_receivers(
+        &self,
+        account: &str,
+        limit: i64,
+    ) -> Result<Vec<String>, DbError> {
+        use crate::schema::blocks::dsl::{blocks, miner};
+        use crate::schema::transfers::dsl::{from_account, height, to_account, transfers};
+        let mut conn = self.pool.get().unwrap();
+        let results = transfers
+            .inner_join(blocks)
+            .filter(from_account.eq(account))
+            .filter(to_account.ne(""))
+            .filter(to_account.ne(miner))
+            .select(to_account)
+            .order(height.desc())
+            .limit(limit)
+            .load(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the transfers of the module between account groups in the window. Accounts are
+    /// grouped by their most confident label, the most confident label of an account of their
+    /// cluster, their cluster or `other`, in that order. Mints, burns, cross-chain legs and
+    /// transfers to self are left out.
+    pub fn find_group_flows(
+        &self,
+        module: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        chain: Option<i64>,
+    ) -> Result<Vec<GroupFlow>, DbError> {
+        use diesel::sql_types::{BigInt, Nullable, Text, Timestamptz};
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "WITH window_transfers AS (
+               SELECT from_account, to_account, amount
+               FROM transfers
+               WHERE module_name = $1 AND creation_time >= $2 AND creation_time < $3
+                 AND ($4 IS NULL OR chain_id = $4)
+                 AND from_account <> '' AND to_account <> '' AND from_account <> to_account
+             ), window_accounts AS (
+               SELECT from_account AS account FROM window_transfers
+               UNION
+               SELECT to_account FROM window_transfers
+             ), best_labels AS (
+               SELECT DISTINCT ON (account) account, label, category
+               FROM labels
+               WHERE account IN (SELECT account FROM window_accounts)
+               ORDER BY account, confidence DESC, source
+             ), cluster_labels AS (
+               SELECT DISTINCT ON (clusters.cluster_id) clusters.cluster_id, labels.label,
+                      labels.category
+               FROM clusters
+               JOIN labels ON labels.account = clusters.account
+               ORDER BY clusters.cluster_id, labels.confidence DESC, labels.label
+             ), account_groups AS (
+               SELECT window_accounts.account,
+                      coalesce(labels.label, cluster_labels.label, clusters.cluster_id, 'other')
+                        AS name,
+                      CASE
+                        WHEN labels.label IS NOT NULL OR cluster_labels.label IS NOT NULL
+                          THEN 'label'
+                        WHEN clusters.cluster_id IS NOT NULL THEN 'cluster'
+                        ELSE 'other'
+                      END AS kind,
+                      CASE
+                        WHEN labels.label IS NOT NULL THEN labels.category
+                        ELSE cluster_labels.category
+                      END AS category
+               FROM window_accounts
+               LEFT JOIN best_labels AS labels ON labels.account = window_accounts.account
+               LEFT JOIN clusters ON clusters.account = window_accounts.account
+               LEFT JOIN cluster_labels ON cluster_labels.cluster_id = clusters.cluster_id
+             )
+             SELECT from_groups.name AS from_group, from_groups.kind AS from_kind,
+                    from_groups.category AS from_category, to_groups.name AS to_group,
+                    to_groups.kind AS to_kind, to_groups.category AS to_category,
+                    count(*) AS transfers, sum(window_transfers.amount) AS amount
+             FROM window_transfers
+             JOIN account_groups AS from_groups
+               ON from_groups.account = window_transfers.from_account
+             JOIN account_groups AS to_groups
+               ON to_groups.account = window_transfers.to_account
+             GROUP BY 1, 2, 3, 4, 5, 6",
+        )
+        .bind::<Text, _>(module)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .bind::<Nullable<BigInt>, _>(chain)
+        .load::<GroupFlow>(&mut conn)?;
+        Ok(results)
+    }
+}
+
+#[derive(Clone)]
+pub struct HoldingStatsRepository {
+    pub pool: DbPool,
+}
+
+impl HoldingStatsRepository {
+    /// Replaces the stats and the history of the module.
+    pub fn replace(
+        &self,
+        stats: &TokenHoldingStats,
+        history: &[TokenHoldingStatsPeriod],
+    ) -> Result<usize, DbError> {
+        use crate::schema::token_holding_stats::dsl::{module, token_holding_stats};
+        use crate::schema::token_holding_stats_history::dsl::{
+            module as history_module, token_holding_stats_history,
+        };
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(token_holding_stats.filter(module.eq(&stats.module))).execute(conn)?;
+            diesel::delete(token_holding_stats_history.filter(history_module.eq(&stats.module)))
+                .execute(conn)?;
+            diesel::insert_into(token_holding_stats)
+                .values(stats)
+                .execute(conn)?;
+            let mut inserted = 0;
+            for chunk in history.chunks(1000) {
+                inserted += diesel::insert_into(token_holding_stats_history)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    pub fn find_by_module(&self, module: &str) -> Result<Option<HoldingStatsSummary>, DbError> {
+        use crate::schema::token_holding_stats::dsl::{module as module_col, token_holding_stats};
+        use crate::schema::token_holding_stats_history::dsl::{
+            module as history_module, period_start, token_holding_stats_history,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let stats = token_holding_stats
+            .filter(module_col.eq(module))
+            .select(TokenHoldingStats::as_select())
+            .first(&mut conn)
+            .optional()?;
+        let stats = match stats {
+            Some(stats) => stats,
+            None => return Ok(None),
+        };
+        let history = token_holding_stats_history
+            .filter(history_module.eq(module))
+            .select(TokenHoldingStatsPeriod::as_select())
+            .order(period_start.asc())
+            .load(&mut conn)?;
+        Ok(Some(HoldingStatsSummary { stats, history }))
+    }
+
+    #[allow(dead_code)]
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::token_holding_stats::dsl::token_holding_stats;
+        use crate::schema::token_holding_stats_history::dsl::token_holding_stats_history;
+        let mut conn = self.pool.get().unwrap();
+        diesel::delete(token_holding_stats_history).execute(&mut conn)?;
+        let deleted = diesel::delete(token_holding_stats).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct WalletConnectionsRepository {
+    pub pool: DbPool,
+}
+
+impl WalletConnectionsRepository {
+    /// Adds the transfers of the given blocks to the connections.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{Array, Text};
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+        let updated = diesel::sql_query(
+            "INSERT INTO wallet_connections
+             SELECT module_name, from_account, to_account, count(*), sum(amount), max(creation_time)
+             FROM transfers
+             WHERE block = ANY($1)
+               AND from_account <> '' AND to_account <> '' AND from_account <> to_account
+             GROUP BY module_name, from_account, to_account
+             ON CONFLICT (module, from_account, to_account) DO UPDATE
+             SET total_transfers = wallet_connections.total_transfers + EXCLUDED.total_transfers,
+                 total_amount = wallet_connections.total_amount + EXCLUDED.total_amount,
+                 last_transfer_time = greatest(wallet_connections.last_transfer_time, EXCLUDED.last_transfer_time)",
+        )
+        .bind::<Array<Text>, _>(hashes)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Subtracts the transfers of a block that is about to be deleted. The last transfer
+    /// time of the affected connections is looked up again in the other blocks and
+    /// connections without transfers left are deleted.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::Text;
+        let updated = diesel::sql_query(
+            "UPDATE wallet_connections c
+             SET total_transfers = c.total_transfers - d.total_transfers,
+                 total_amount = c.total_amount - d.total_amount,
+                 last_transfer_time = coalesce(
+                     (SELECT max(t.creation_time) FROM transfers t
+                      WHERE t.from_account = c.from_account
+                        AND t.to_account = c.to_account
+                        AND t.module_name = c.module
+                        AND t.block <> $1),
+                     c.last_transfer_time)
+             FROM (
+                 SELECT module_name, from_account, to_account,
+                        count(*) AS total_transfers, sum(amount) AS total_amount
+                 FROM transfers
+                 WHERE block = $1
+                   AND from_account <> '' AND to_account <> '' AND from_account <> to_account
+                 GROUP BY module_name, from_account, to_account
+             ) AS d
+             WHERE c.module = d.module_name
+               AND c.from_account = d.from_account
+               AND c.to_account = d.to_account",
+        )
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+        diesel::sql_query("DELETE FROM wallet_connections WHERE total_transfers <= 0")
+            .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Returns the connections from or to any of the accounts, or all the connections of the
+    /// module without accounts, largest first and up to `limit` if given.
+    pub fn find_connections(
+        &self,
+        accounts: Option<&[String]>,
+        filter: &GraphFilter,
+        limit: Option<i64>,
+    ) -> Result<Vec<WalletConnection>, DbError> {
+        use crate::schema::wallet_connections::dsl::{
+            from_account, module as module_col, to_account, total_amount, wallet_connections,
+        };
+        use diesel::sql_types::{Array, BigInt, Nullable, Numeric, Text, Timestamptz};
+        let mut conn = self.pool.get().unwrap();
+        if filter.from.is_some() || filter.to.is_some() {
+            let results = diesel::sql_query(
+                "SELECT from_account, to_account, count(*) AS total_transfers,
+                        sum(amount) AS total_amount, max(creation_time) AS last_transfer_time
+                 FROM transfers
+                 WHERE module_name = $1
+                   AND from_account <> '' AND to_account <> '' AND from_account <> to_account
+                   AND ($2 IS NULL OR creation_time >= $2)
+                   AND ($3 IS NULL OR creation_time < $3)
+                   AND ($4 IS NULL OR from_account = ANY($4) OR to_account = ANY($4))
+                 GROUP BY from_account, to_account
+                 HAVING $5 IS NULL OR sum(amount) >= $5
+                 ORDER BY total_amount DESC
+                 LIMIT $6",
+            )
+            .bind::<Text, _>(&filter.module)
+            .bind::<Nullable<Timestamptz>, _>(filter.from)
+            .bind::<Nullable<Timestamptz>, _>(filter.to)
+            .bind::<Nullable<Array<Text>>, _>(accounts.map(|accounts| accounts.to_vec()))
+            .bind::<Nullable<Numeric>, _>(filter.min_amount.clone())
+            .bind::<Nullable<BigInt>, _>(limit)
+            .load::<WalletConnection>(&mut conn)?;
+            return Ok(results);
+        }
+        let mut query = wallet_connections
+            .filter(module_col.eq(&filter.module))
+            .into_boxed();
+        if let Some(accounts) = accounts {
+            query = query.filter(
+                from_account
+                    .eq_any(accounts.to_vec())
+                    .or(to_account.eq_any(accounts.to_vec())),
+            );
+        }
+        if let Some(min_amount) = &filter.min_amount {
+            query = query.filter(total_amount.ge(min_amount.clone()));
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        let results = query
+            .order(total_amount.desc())
+            .select(WalletConnection::as_select())
+            .load(&mut conn)?;
+        Ok(results)
+    }
+}
+
+#[derive(Clone)]
+pub struct ClustersRepository {
+    pub pool: DbPool,
+}
+
+impl ClustersRepository {
+    /// Returns the accounts that sent transfers in transactions sent by another account, with
+    /// the sender as `account` and one of the request keys as `detail`.
+    pub fn find_gas_payers(&self) -> Result<Vec<AccountPair>, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT t.sender AS account, f.from_account AS linked_account,
+                    min(t.request_key) AS detail
+             FROM transactions t
+             JOIN transfers f ON f.block = t.block AND f.request_key = t.request_key
+             WHERE f.from_account <> '' AND f.from_account <> t.sender
+             GROUP BY t.sender, f.from_account",
+        )
+        .load::<AccountPair>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the keysets found in the data of transactions that don't transfer anything to
+    /// other accounts than the sender, the miner (gas) or another chain, so the keysets
+    /// likely belong to the sender.
+    pub fn find_data_keysets(&self) -> Result<Vec<DataKeyset>, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT t.sender, d.value->'keys' AS keys, d.value->>'pred' AS pred,
+                    min(t.request_key) AS request_key
+             FROM transactions t
+             CROSS JOIN LATERAL jsonb_each(
+                 CASE WHEN jsonb_typeof(t.data) = 'object' THEN t.data ELSE '{}' END
+             ) AS d
+             WHERE jsonb_typeof(d.value->'keys') = 'array'
+               AND NOT EXISTS (
+                   SELECT 1 FROM transfers f JOIN blocks b ON b.hash = f.block
+                   WHERE f.block = t.block AND f.request_key = t.request_key
+                     AND f.to_account NOT IN ('', t.sender, b.miner)
+               )
+             GROUP BY t.sender, d.value->'keys', d.value->>'pred'",
+        )
+        .load::<DataKeyset>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the accounts that only ever transferred to and received from a single other
+    /// account, in both directions, with that account as `linked_account`.
+    pub fn find_funding_sources(&self) -> Result<Vec<AccountPair>, DbError> {
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT account, min(counterpart) AS linked_account, NULL::varchar AS detail
+             FROM (
+                 SELECT from_account AS account, to_account AS counterpart, 1 AS direction
+                 FROM wallet_connections
+                 UNION ALL
+                 SELECT to_account AS account, from_account AS counterpart, -1 AS direction
+                 FROM wallet_connections
+             ) AS connections
+             GROUP BY account
+             HAVING count(DISTINCT counterpart) = 1 AND count(DISTINCT direction) = 2",
+        )
+        .load::<AccountPair>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the given accounts that hold or held any token.
+    pub fn find_existing_accounts(&self, accounts: &[String]) -> Result<HashSet<String>, DbError> {
+        use crate::schema::balances::dsl::{account, balances};
+        let mut conn = self.pool.get().unwrap();
+        let mut existing = HashSet::new();
+        for chunk in accounts.chunks(10000) {
+            let results = balances
+                .filter(account.eq_any(chunk))
+                .select(account)
+                .distinct()
+                .load::<String>(&mut conn)?;
+            existing.extend(results);
+        }
+        Ok(existing)
+    }
+
+    /// Replaces all the clusters and their links.
+    pub fn replace(&self, clusters: &[Cluster], links: &[ClusterLink]) -> Result<usize, DbError> {
+        use crate::schema::cluster_links::dsl::cluster_links;
+        use crate::schema::clusters::dsl::clusters as clusters_table;
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(cluster_links).execute(conn)?;
+            diesel::delete(clusters_table).execute(conn)?;
+            let mut inserted = 0;
+            for chunk in clusters.chunks(10000) {
+                inserted += diesel::insert_into(clusters_table)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+            for chunk in links.chunks(10000) {
+                diesel::insert_into(cluster_links)
+                    .values(chunk)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    /// Returns the entity of the account with the links between its accounts, `None` if the
+    /// account isn't linked to any other account.
+    pub fn find_entity(&self, account: &str) -> Result<Option<Entity>, DbError> {
+        use crate::schema::cluster_links::dsl::{
+            account as link_account, cluster_links, evidence, linked_account,
+        };
+        use crate::schema::clusters::dsl::{account as account_col, cluster_id, clusters};
+        let mut conn = self.pool.get().unwrap();
+        let id = clusters
+            .filter(account_col.eq(account))
+            .select(cluster_id)
+            .first::<String>(&mut conn)
+            .optional()?;
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let accounts = clusters
+            .filter(cluster_id.eq(&id))
+            .select(account_col)
+            .order(account_col.asc())
+            .load::<String>(&mut conn)?;
+        let links = cluster_links
+            .filter(link_account.eq_any(&accounts))
+            .select(ClusterLink::as_select())
+            .order((link_account.asc(), linked_account.asc(), evidence.asc()))
+            .load(&mut conn)?;
+        Ok(Some(Entity {
+            cluster_id: id,
+            accounts,
+            links,
+        }))
+    }
+}
+
+#[derive(Clone)]
+pub struct ActivityRollupsRepository {
+    pub pool: DbPool,
+}
+
+impl ActivityRollupsRepository {
+    /// Adds (`sign` = 1) or subtracts (`sign` = -1) the transactions and transfers of the
+    /// given blocks to the network, module and account rollups. The network amount leaves
+    /// out transfers to the miner of the block (gas and rewards) and the incoming leg of
+    /// cross-chain transfers, so an amount is only counted once.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        sign: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{Array, Integer, Text};
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+        let statements = [
+            "INSERT INTO activity_rollups
+             SELECT 'network', '', chain_id, bucket, sum(transaction_count) * $2, sum(total_amount) * $2
+             FROM (
+                 SELECT chain_id, date_trunc('minute', creation_time) AS bucket,
+                        count(*) AS transaction_count, 0 AS total_amount
+                 FROM transactions WHERE block = ANY($1)
+                 GROUP BY chain_id, date_trunc('minute', creation_time)
+                 UNION ALL
+                 SELECT transfers.chain_id, date_trunc('minute', transfers.creation_time), 0,
+                        sum(amount)
+                 FROM transfers JOIN blocks ON blocks.hash = transfers.block
+                 WHERE transfers.block = ANY($1) AND module_name = 'coin'
+                   AND to_account <> blocks.miner
+                   AND NOT (from_account = '' AND pact_id IS NOT NULL)
+                 GROUP BY transfers.chain_id, date_trunc('minute', transfers.creation_time)
+             ) AS activity
+             GROUP BY chain_id, bucket",
+            "INSERT INTO activity_rollups
+             SELECT 'module', module_name, chain_id, date_trunc('minute', creation_time),
+                    count(DISTINCT request_key) * $2, sum(amount) * $2
+             FROM transfers WHERE block = ANY($1)
+             GROUP BY module_name, chain_id, date_trunc('minute', creation_time)",
+            "INSERT INTO activity_rollups
+             SELECT 'account', account, chain_id, date_trunc('minute', creation_time),
+                    count(DISTINCT request_key) * $2, sum(amount) * $2
+             FROM (
+                 SELECT from_account AS account, chain_id, creation_time, request_key,
+                        CASE WHEN module_name = 'coin' THEN amount ELSE 0 END AS amount
+                 FROM transfers WHERE block = ANY($1) AND from_account <> ''
+                 UNION ALL
+                 SELECT to_account, chain_id, creation_time, request_key,
+                        CASE WHEN module_name = 'coin' THEN amount ELSE 0 END
+                 FROM transfers
+                 WHERE block = ANY($1) AND to_account <> '' AND to_account <> from_account
+             ) AS activity
+             GROUP BY account, chain_id, date_trunc('minute', creation_time)",
+        ];
+        let mut updated = 0;
+        for statement in statements {
+            updated += diesel::sql_query(format!(
+                "{}
+                 ON CONFLICT (scope, id, chain_id, bucket) DO UPDATE
+                 SET transaction_count = activity_rollups.transaction_count + EXCLUDED.transaction_count,
+                     total_amount = activity_rollups.total_amount + EXCLUDED.total_amount",
+                statement
+            ))
+            .bind::<Array<Text>, _>(hashes)
+            .bind::<Integer, _>(sign)
+            .execute(conn)?;
+        }
+        Ok(updated)
+    }
+
+    /// Subtracts the transactions and transfers of a block that is about to be deleted.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        self.apply_blocks_with_conn(&[hash.to_string()], -1, conn)
+    }
+
+    /// Returns the rollups of the scope between `from` and `to`, summed over all chains
+    /// unless `chain` is set, oldest first.
+    pub fn find_by_range(
+        &self,
+        scope: &str,
+        id: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        chain: Option<i64>,
+    ) -> Result<Vec<ActivityRollup>, DbError> {
+        use diesel::sql_types::{BigInt, Nullable, Text, Timestamptz};
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT bucket, sum(transaction_count)::bigint AS transaction_count,
+                    sum(total_amount) AS total_amount
+             FROM activity_rollups
+             WHERE scope = $1 AND id = $2 AND bucket >= $3 AND bucket < $4
+               AND ($5 IS NULL OR chain_id = $5)
+             GROUP BY bucket
+             ORDER BY bucket",
+        )
+        .bind::<Text, _>(scope)
+        .bind::<Text, _>(id)
+        .bind::<Timestamptz, _>(from)
+        .bind::<Timestamptz, _>(to)
+        .bind::<Nullable<BigInt>, _>(chain)
+        .load::<ActivityRollup>(&mut conn)?;
+        Ok(results)
+    }
+
+    pub fn delete_all(&self) -> Result<usize, DbError> {
+        use crate::schema::activity_rollups::dsl::activity_rollups;
+        let mut conn = self.pool.get().unwrap();
+        let deleted = diesel::delete(activity_rollups).execute(&mut conn)?;
+        Ok(deleted)
+    }
+}
+
+#[derive(Clone)]
+pub struct LabelsRepository {
+    pub pool: DbPool,
+}
+
+impl LabelsRepository {
+    /// Inserts the labels, replacing the label of an account given by the same source. With
+    /// `replace` the other labels of the imported sources are deleted first.
+    pub fn import(&self, labels: &[Label], replace: bool) -> Result<usize, DbError> {
+        use crate::schema::labels::dsl::{
+            account, category, confidence, label, labels as labels_table, source,
+        };
+        use diesel::upsert::excluded;
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            if replace {
+                let sources = labels
+                    .iter()
+                    .map(|e| e.source.clone())
+                    .collect::<HashSet<String>>()
+                    .into_iter()
+                    .collect::<Vec<String>>();
+                diesel::delete(labels_table.filter(source.eq_any(sources))).execute(conn)?;
+            }
+            let mut inserted = 0;
+            for chunk in labels.chunks(10000) {
+                inserted += diesel::insert_into(labels_table)
+                    .values(chunk)
+                    .on_conflict((account, source))
+                    .do_update()
+                    .set((
+                        label.eq(excluded(label)),
+                        category.eq(excluded(category)),
+                        confidence.eq(excluded(confidence)),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    /// Returns the labels of the accounts, most confident first.
+    pub fn find_by_accounts(
+        &self,
+        accounts: &[String],
+    ) -> Result<HashMap<String, Vec<Label>>, DbError> {
+        use crate::schema::labels::dsl::{account, confidence, labels, source};
+        let mut conn = self.pool.get().unwrap();
+        let results = labels
+            .filter(account.eq_any(accounts))
+            .order((confidence.desc(), source.asc()))
+            .select(Label::as_select())
+            .load::<Label>(&mut conn)?;
+        let mut by_account: HashMap<String, Vec<Label>> = HashMap::new();
+        for result in results {
+            by_account
+                .entry(result.account.clone())
+                .or_default()
+                .push(result);
+        }
+        Ok(by_account)
+    }
+
+    /// Returns the labels matching all the given filters, ordered by account.
+    pub fn find(
+        &self,
+        label: Option<String>,
+        category: Option<String>,
+        source: Option<String>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Label>, DbError> {
+        use crate::schema::labels::dsl::{
+            account, category as category_col, label as label_col, labels, source as source_col,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = labels.into_boxed();
+        if let Some(label) = label {
+            query = query.filter(label_col.eq(label));
+        }
+        if let Some(category) = category {
+            query = query.filter(category_col.eq(category));
+        }
+        if let Some(source) = source {
+            query = query.filter(source_col.eq(source));
+        }
+        let results = query
+            .order((account.asc(), source_col.asc()))
+            .limit(limit)
+            .offset(offset)
+            .select(Label::as_select())
+            .load::<Label>(&mut conn)?;
+        Ok(results)
+    }
+}
+
+const ACCOUNTS_SEEN_QUERY: &str =
+    "SELECT DISTINCT ON (account) account, height, chain_id, creation_time, block, request_key
+     FROM (
+         SELECT from_account AS account, height, chain_id, creation_time, block, request_key
+         FROM transfers WHERE block = ANY($1) AND from_account <> ''
+         UNION ALL
+         SELECT to_account, height, chain_id, creation_time, block, request_key
+         FROM transfers WHERE block = ANY($1) AND to_account <> ''
+         UNION ALL
+         SELECT sender, height, chain_id, creation_time, block, request_key
+         FROM transactions WHERE block = ANY($1) AND sender <> ''
+     ) AS seen
+     ORDER BY account, creation_time, chain_id";
+
+#[derive(Clone)]
+pub struct AccountsRepository {
+    pub pool: DbPool,
+}
+
+impl AccountsRepository {
+    /// Records the accounts of the transfers and transactions of the given blocks, unless
+    /// they were already seen earlier.
+    pub fn apply_blocks_with_conn(
+        &self,
+        hashes: &[String],
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::{Array, Text};
+        if hashes.is_empty() {
+            return Ok(0);
+        }
+        let updated = diesel::sql_query(format!(
+            "INSERT INTO accounts {}
+             ON CONFLICT (account) DO UPDATE
+             SET first_height = EXCLUDED.first_height,
+                 first_chain_id = EXCLUDED.first_chain_id,
+                 first_time = EXCLUDED.first_time,
+                 first_block = EXCLUDED.first_block,
+                 first_request_key = EXCLUDED.first_request_key
+             WHERE EXCLUDED.first_time < accounts.first_time",
+            ACCOUNTS_SEEN_QUERY
+        ))
+        .bind::<Array<Text>, _>(hashes)
+        .execute(conn)?;
+        Ok(updated)
+    }
+
+    /// Looks up the accounts first seen in a block that is about to be deleted again in the
+    /// other blocks. Accounts that aren't found anywhere else are deleted.
+    pub fn revert_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use diesel::sql_types::Text;
+        let updated = diesel::sql_query(
+            "WITH removed AS (
+                 DELETE FROM accounts WHERE first_block = $1 RETURNING account
+             )
+             INSERT INTO accounts
+             SELECT DISTINCT ON (account) account, height, chain_id, creation_time, block,
+                    request_key
+             FROM (
+                 SELECT from_account AS account, height, chain_id, creation_time, block,
+                        request_key
+                 FROM transfers
+                 WHERE from_account IN (SELECT account FROM removed) AND block <> $1
+                 UNION ALL
+                 SELECT to_account, height, chain_id, creation_time, block, request_key
+                 FROM transfers
+                 WHERE to_account IN (SELECT account FROM removed) AND block <> $1
+                 UNION ALL
+                 SELECT sender, height, chain_id, creation_time, block, request_key
+                 FROM transactions
+                 WHERE sender IN (SELECT account FROM removed) AND block <> $1
+             ) AS seen
+             ORDER BY account, creation_time, chain_id",
+        )
+        .bind::<Text, _>(hash)
+        .execute(conn)?;
+        Ok(updated)
+    }
+}
+
+#[derive(Clone)]
+pub struct CohortsRepository {
+    pub pool: DbPool,
+}
+
+impl CohortsRepository {
+    /// Returns the number of accounts first seen in every `interval` (`day`, `week` or
+    /// `month`, in UTC) that ever sent or received a transfer of the module.
+    pub fn find_cohort_sizes(
+        &self,
+        module: &str,
+        interval: &str,
+    ) -> Result<Vec<CohortSize>, DbError> {
+        use diesel::sql_types::Text;
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT date_trunc($2, first_time AT TIME ZONE 'UTC') AS cohort_start,
+                    count(*) AS accounts
+             FROM accounts
+             WHERE account IN (
+                 SELECT from_account FROM transfers WHERE module_name = $1
+                 UNION
+                 SELECT to_account FROM transfers WHERE module_name = $1
+             )
+             GROUP BY 1
+             ORDER BY 1",
+        )
+        .bind::<Text, _>(module)
+        .bind::<Text, _>(interval)
+        .load::<CohortSize>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Returns the number of accounts of every cohort that sent or received a transfer of
+    /// the module in every `interval`, in UTC.
+    pub fn find_cohort_activity(
+        &self,
+        module: &str,
+        interval: &str,
+    ) -> Result<Vec<CohortActivity>, DbError> {
+        use diesel::sql_types::Text;
+        let mut conn = self.pool.get().unwrap();
+        let results = diesel::sql_query(
+            "SELECT date_trunc($2, accounts.first_time AT TIME ZONE 'UTC') AS cohort_start,
+                    active.period_start, count(*) AS accounts
+             FROM (
+                 SELECT DISTINCT account, period_start
+                 FROM (
+                     SELECT from_account AS account,
+                            date_trunc($2, creation_time AT TIME ZONE 'UTC') AS period_start
+                     FROM transfers WHERE module_name = $1 AND from_account <> ''
+                     UNION ALL
+                     SELECT to_account, date_trunc($2, creation_time AT TIME ZONE 'UTC')
+                     FROM transfers WHERE module_name = $1 AND to_account <> ''
+                 ) AS transfer_accounts
+             ) AS active
+             JOIN accounts ON accounts.account = active.account
+             GROUP BY 1, 2
+             ORDER BY 1, 2",
+        )
+        .bind::<Text, _>(module)
+        .bind::<Text, _>(interval)
+        .load::<CohortActivity>(&mut conn)?;
+        Ok(results)
+    }
+
+    /// Replaces the cohorts of the module for the interval.
+    pub fn replace(
+        &self,
+        module: &str,
+        interval: &str,
+        rows: &[CohortRetention],
+    ) -> Result<usize, DbError> {
+        use crate::schema::cohort_retention::dsl::{
+            cohort_interval, cohort_retention, module as module_col,
+        };
+        let mut conn = self.pool.get().unwrap();
+        conn.transaction(|conn| {
+            diesel::delete(
+                cohort_retention
+                    .filter(module_col.eq(module))
+                    .filter(cohort_interval.eq(interval)),
+            )
+            .execute(conn)?;
+            let mut inserted = 0;
+            for chunk in rows.chunks(10000) {
+                inserted += diesel::insert_into(cohort_retention)
+                    .values(chunk)
+                    .execute(conn)?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    /// Returns the cohorts of the module for the interval, optionally only the ones starting
+    /// between `from` (inclusive) and `to` (exclusive), ordered by cohort and period.
+    pub fn find(
+        &self,
+        module: &str,
+        interval: &str,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+    ) -> Result<Vec<CohortRetention>, DbError> {
+        use crate::schema::cohort_retention::dsl::{
+            cohort_interval, cohort_retention, cohort_start, module as module_col, period,
+        };
+        let mut conn = self.pool.get().unwrap();
+        let mut query = cohort_retention
+            .filter(module_col.eq(module))
+            .filter(cohort_interval.eq(interval))
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(cohort_start.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(cohort_start.lt(to));
+        }
+        let results = query
+            .order((cohort_start.asc(), period.asc()))
+            .select(CohortRetention::as_select())
+            .load::<CohortRetention>(&mut conn)?;
+        Ok(results)
+    }
+}
+
+#[derive(Clone)]
+pub struct TransactionsRepository {
+    pub pool: DbPool,
+}
+
+impl TransactionsRepository {
+    // Transaction-aware version of insert_batch
+    pub fn insert_batch_with_conn(
+        &self,
+        transactions: &[Transaction],
+        conn: &mut PgConnection
+    ) -> Result<usize, DbError> {
+        use crate::schema::transactions::dsl::transactions as transactions_table;
+        
+        let mut inserted = 0;
+        for chunk in transactions.chunks(1000) {
+            inserted += diesel::insert_into(transactions_table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(inserted)
+    }
+
+    pub fn delete_all_by_block_with_conn(
+        &self,
+        hash: &str,
+        conn: &mut PgConnection,
+    ) -> Result<usize, DbError> {
+        use crate::schema::transactions::dsl::{block as block_col, transactions};
+        let deleted = diesel::delete(transactions.filter(block_col.eq(hash))).execute(conn)?;
+        Ok(deleted)
     }
 
     // Existing methods remain unchanged
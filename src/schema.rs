@@ -1,5 +1,47 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    accounts (account) {
+        account -> Varchar,
+        first_height -> Int8,
+        first_chain_id -> Int8,
+        first_time -> Timestamptz,
+        first_block -> Varchar,
+        first_request_key -> Varchar,
+    }
+}
+
+diesel::table! {
+    activity_rollups (scope, id, chain_id, bucket) {
+        scope -> Varchar,
+        id -> Varchar,
+        chain_id -> Int8,
+        bucket -> Timestamptz,
+        transaction_count -> Int8,
+        total_amount -> Numeric,
+    }
+}
+
+diesel::table! {
+    balance_mismatches (account, module, chain_id) {
+        account -> Varchar,
+        module -> Varchar,
+        chain_id -> Int8,
+        indexed_balance -> Numeric,
+        node_balance -> Nullable<Numeric>,
+        checked_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    balances (account, module, chain_id) {
+        account -> Varchar,
+        module -> Varchar,
+        chain_id -> Int8,
+        balance -> Numeric,
+    }
+}
+
 diesel::table! {
     blocks (hash) {
         chain_id -> Int8,
@@ -19,6 +61,53 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    cluster_links (account, linked_account, evidence) {
+        account -> Varchar,
+        linked_account -> Varchar,
+        evidence -> Varchar,
+        detail -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    clusters (account) {
+        account -> Varchar,
+        cluster_id -> Varchar,
+    }
+}
+
+diesel::table! {
+    cohort_retention (module, cohort_interval, cohort_start, period) {
+        module -> Varchar,
+        cohort_interval -> Varchar,
+        cohort_start -> Timestamptz,
+        period -> Int8,
+        cohort_size -> Int8,
+        active_accounts -> Int8,
+    }
+}
+
+// `crosschain_transfers` is a view, Diesel CLI doesn't generate it.
+diesel::table! {
+    crosschain_transfers (pact_id) {
+        pact_id -> Varchar,
+        module_name -> Varchar,
+        source_chain_id -> Int8,
+        target_chain_id -> Nullable<Int8>,
+        sender -> Varchar,
+        receiver -> Nullable<Varchar>,
+        amount -> Numeric,
+        source_request_key -> Varchar,
+        target_request_key -> Nullable<Varchar>,
+        source_height -> Int8,
+        target_height -> Nullable<Int8>,
+        initiation_time -> Timestamptz,
+        completion_time -> Nullable<Timestamptz>,
+        status -> Varchar,
+    }
+}
+
 diesel::table! {
     events (block, idx, request_key) {
         block -> Varchar,
@@ -36,6 +125,211 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    hashrate_buckets (chain_id, bucket) {
+        chain_id -> Int8,
+        bucket -> Timestamptz,
+        blocks -> Int8,
+        difficulty -> Numeric,
+        first_block_time -> Timestamptz,
+        last_block_time -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    labels (account, source) {
+        account -> Varchar,
+        label -> Varchar,
+        category -> Varchar,
+        source -> Varchar,
+        confidence -> Float8,
+    }
+}
+
+diesel::table! {
+    liquidity_changes (block, idx, request_key) {
+        block -> Varchar,
+        chain_id -> Int8,
+        height -> Int8,
+        idx -> Int8,
+        request_key -> Varchar,
+        creation_time -> Timestamptz,
+        module -> Varchar,
+        pair -> Varchar,
+        kind -> Varchar,
+        account -> Varchar,
+        amount0 -> Numeric,
+        amount1 -> Numeric,
+    }
+}
+
+diesel::table! {
+    nft_balances (chain_id, token_id, account) {
+        chain_id -> Int8,
+        token_id -> Varchar,
+        account -> Varchar,
+        balance -> Numeric,
+        block -> Varchar,
+        request_key -> Varchar,
+        height -> Int8,
+        idx -> Int8,
+    }
+}
+
+diesel::table! {
+    nft_sales (chain_id, sale_id) {
+        chain_id -> Int8,
+        sale_id -> Varchar,
+        token_id -> Varchar,
+        seller -> Varchar,
+        amount -> Numeric,
+        timeout -> Nullable<Int8>,
+        buyer -> Nullable<Varchar>,
+        offer_block -> Nullable<Varchar>,
+        offer_request_key -> Nullable<Varchar>,
+        offer_height -> Nullable<Int8>,
+        offer_time -> Nullable<Timestamptz>,
+        buy_block -> Nullable<Varchar>,
+        buy_request_key -> Nullable<Varchar>,
+        buy_height -> Nullable<Int8>,
+        buy_time -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    nft_tokens (chain_id, token_id) {
+        chain_id -> Int8,
+        token_id -> Varchar,
+        module -> Nullable<Varchar>,
+        precision -> Nullable<Int4>,
+        uri -> Nullable<Varchar>,
+        policies -> Nullable<Jsonb>,
+        supply -> Numeric,
+        block -> Nullable<Varchar>,
+        height -> Nullable<Int8>,
+        request_key -> Nullable<Varchar>,
+        creation_time -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    pool_reserves (block, idx, request_key) {
+        block -> Varchar,
+        chain_id -> Int8,
+        height -> Int8,
+        idx -> Int8,
+        request_key -> Varchar,
+        creation_time -> Timestamptz,
+        module -> Varchar,
+        pair -> Varchar,
+        reserve0 -> Numeric,
+        reserve1 -> Numeric,
+    }
+}
+
+diesel::table! {
+    stuck_crosschain_transfers (pact_id) {
+        pact_id -> Varchar,
+        request_key -> Varchar,
+        chain_id -> Int8,
+        height -> Int8,
+        module_name -> Varchar,
+        sender -> Varchar,
+        receiver -> Nullable<Varchar>,
+        target_chain_id -> Nullable<Int8>,
+        amount -> Numeric,
+        initiation_time -> Timestamptz,
+        detected_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    supply_snapshots (module, chain_id, bucket) {
+        module -> Varchar,
+        chain_id -> Int8,
+        bucket -> Timestamptz,
+        minted -> Numeric,
+        burned -> Numeric,
+        crosschain_in -> Numeric,
+        crosschain_out -> Numeric,
+    }
+}
+
+diesel::table! {
+    swaps (block, idx, request_key) {
+        block -> Varchar,
+        chain_id -> Int8,
+        height -> Int8,
+        idx -> Int8,
+        request_key -> Varchar,
+        creation_time -> Timestamptz,
+        module -> Varchar,
+        pair -> Varchar,
+        trader -> Varchar,
+        receiver -> Varchar,
+        token_in -> Varchar,
+        amount_in -> Numeric,
+        token_out -> Varchar,
+        amount_out -> Numeric,
+        price -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    token_holding_stats (module) {
+        module -> Varchar,
+        computed_at -> Timestamptz,
+        holders -> Int8,
+        held_amount -> Float8,
+        p25_days -> Nullable<Float8>,
+        median_days -> Nullable<Float8>,
+        p75_days -> Nullable<Float8>,
+        p90_days -> Nullable<Float8>,
+        held_30d_share -> Float8,
+        held_90d_share -> Float8,
+        held_365d_share -> Float8,
+    }
+}
+
+diesel::table! {
+    token_holding_stats_history (module, period_start) {
+        module -> Varchar,
+        period_start -> Timestamptz,
+        held_amount -> Float8,
+        p25_days -> Nullable<Float8>,
+        median_days -> Nullable<Float8>,
+        p75_days -> Nullable<Float8>,
+        p90_days -> Nullable<Float8>,
+        held_30d_share -> Float8,
+        held_90d_share -> Float8,
+        held_365d_share -> Float8,
+    }
+}
+
+diesel::table! {
+    token_module_hashes (module, module_hash) {
+        module -> Varchar,
+        module_hash -> Varchar,
+        first_seen_height -> Int8,
+        first_seen_chain_id -> Int8,
+        first_seen_time -> Timestamptz,
+        transfer_count -> Int8,
+    }
+}
+
+diesel::table! {
+    tokens (module) {
+        module -> Varchar,
+        namespace -> Nullable<Varchar>,
+        first_seen_height -> Int8,
+        first_seen_chain_id -> Int8,
+        first_seen_time -> Timestamptz,
+        precision -> Nullable<Int4>,
+        transfer_count -> Int8,
+        volume -> Numeric,
+    }
+}
+
 diesel::table! {
     transactions (block, request_key) {
         bad_result -> Nullable<Jsonb>,
@@ -62,6 +356,7 @@ diesel::table! {
         step -> Nullable<Int8>,
         ttl -> Int8,
         tx_id -> Nullable<Int8>,
+        position -> Nullable<Int8>,
     }
 }
 
@@ -82,8 +377,46 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    wallet_connections (module, from_account, to_account) {
+        module -> Varchar,
+        from_account -> Varchar,
+        to_account -> Varchar,
+        total_transfers -> Int8,
+        total_amount -> Numeric,
+        last_transfer_time -> Timestamptz,
+    }
+}
+
 diesel::joinable!(events -> blocks (block));
 diesel::joinable!(transactions -> blocks (block));
 diesel::joinable!(transfers -> blocks (block));
 
-diesel::allow_tables_to_appear_in_same_query!(blocks, events, transactions, transfers,);
+diesel::allow_tables_to_appear_in_same_query!(
+    accounts,
+    activity_rollups,
+    balance_mismatches,
+    balances,
+    blocks,
+    cluster_links,
+    clusters,
+    cohort_retention,
+    events,
+    hashrate_buckets,
+    labels,
+    liquidity_changes,
+    nft_balances,
+    nft_sales,
+    nft_tokens,
+    pool_reserves,
+    stuck_crosschain_transfers,
+    supply_snapshots,
+    swaps,
+    token_holding_stats,
+    token_holding_stats_history,
+    token_module_hashes,
+    tokens,
+    transactions,
+    transfers,
+    wallet_connections,
+);
@@ -0,0 +1,215 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::BTreeMap;
+
+use crate::db::DbError;
+use crate::models::{SupplyBucket, SupplySnapshot};
+use crate::repository::SupplySnapshotsRepository;
+
+/// Returns minted, burned and net supply of the module over buckets of the given interval,
+/// which has to be a multiple of one hour. Supply is cumulative since the first transfer.
+pub fn supply_series(
+    repository: &SupplySnapshotsRepository,
+    module: &str,
+    interval: Duration,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    chain_id: Option<i64>,
+) -> Result<Vec<SupplyBucket>, DbError> {
+    let initial = repository.find_supply_before(module, from, chain_id)?;
+    let snapshots = repository.find_by_range(module, from, to, chain_id)?;
+    Ok(aggregate_snapshots(initial, &snapshots, interval))
+}
+
+/// Groups the hourly snapshots of all chains into buckets of `interval`. Mints and burns
+/// that are part of a cross-chain transfer are reported separately and don't change the
+/// net supply, but they do change the supply of a single chain.
+fn aggregate_snapshots(
+    initial: BigDecimal,
+    snapshots: &[SupplySnapshot],
+    interval: Duration,
+) -> Vec<SupplyBucket> {
+    let interval_secs = interval.num_seconds();
+    let mut grouped: BTreeMap<i64, [BigDecimal; 4]> = BTreeMap::new();
+    for snapshot in snapshots {
+        let timestamp = snapshot.bucket.timestamp();
+        let start = timestamp - timestamp.rem_euclid(interval_secs);
+        let [minted, burned, crosschain_in, crosschain_out] = grouped
+            .entry(start)
+            .or_insert_with(|| std::array::from_fn(|_| BigDecimal::zero()));
+        *minted += &snapshot.minted;
+        *burned += &snapshot.burned;
+        *crosschain_in += &snapshot.crosschain_in;
+        *crosschain_out += &snapshot.crosschain_out;
+    }
+    let mut supply = initial;
+    grouped
+        .into_iter()
+        .map(|(start, [minted, burned, crosschain_in, crosschain_out])| {
+            let start_time = NaiveDateTime::from_timestamp_opt(start, 0).unwrap();
+            let net = &minted - &burned;
+            supply = &supply + &net + &crosschain_in - &crosschain_out;
+            SupplyBucket {
+                start_time,
+                end_time: start_time + interval,
+                minted,
+                burned,
+                net,
+                crosschain_in,
+                crosschain_out,
+                supply: supply.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::{Event, Transaction, Transfer};
+    use crate::repository::{
+        BlocksRepository, EventsRepository, TransactionsRepository, TransfersRepository,
+    };
+    use crate::test_utils::{make_block, make_event, make_transaction, make_transfer, time};
+    use serial_test::serial;
+
+    fn make_snapshot(
+        chain_id: i64,
+        bucket: &str,
+        minted: i64,
+        burned: i64,
+        crosschain_in: i64,
+        crosschain_out: i64,
+    ) -> SupplySnapshot {
+        SupplySnapshot {
+            module: "coin".to_string(),
+            chain_id,
+            bucket: NaiveDateTime::parse_from_str(bucket, "%Y-%m-%d %H:%M:%S").unwrap(),
+            minted: BigDecimal::from(minted),
+            burned: BigDecimal::from(burned),
+            crosschain_in: BigDecimal::from(crosschain_in),
+            crosschain_out: BigDecimal::from(crosschain_out),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_snapshots() {
+        let snapshots = vec![
+            make_snapshot(0, "2023-10-01 00:00:00", 100, 0, 0, 30),
+            make_snapshot(1, "2023-10-01 05:00:00", 0, 10, 30, 0),
+            make_snapshot(0, "2023-10-03 12:00:00", 50, 5, 0, 0),
+        ];
+        let buckets = aggregate_snapshots(BigDecimal::from(1000), &snapshots, Duration::days(1));
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].minted, BigDecimal::from(100));
+        assert_eq!(buckets[0].burned, BigDecimal::from(10));
+        // The cross-chain transfer from chain 0 to chain 1 doesn't change the supply
+        assert_eq!(buckets[0].net, BigDecimal::from(90));
+        assert_eq!(buckets[0].supply, BigDecimal::from(1090));
+        assert_eq!(
+            buckets[1].start_time,
+            NaiveDateTime::parse_from_str("2023-10-03 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+        );
+        assert_eq!(buckets[1].net, BigDecimal::from(45));
+        assert_eq!(buckets[1].supply, BigDecimal::from(1135));
+    }
+
+    #[test]
+    #[serial]
+    fn test_apply_blocks_classifies_crosschain_transfers() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let events_repository = EventsRepository { pool: pool.clone() };
+        let transactions_repository = TransactionsRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let supply_repository = SupplySnapshotsRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 0, "block-0"), make_block(1, 0, "block-1")])
+            .unwrap();
+        let transfer =
+            |chain_id: i64, block: &str, idx: i64, from: &str, to: &str, amount: &str| Transfer {
+                chain_id,
+                creation_time: time("2023-10-01 00:10:00"),
+                pact_id: Some(format!("pact-{}", idx)),
+                ..make_transfer(block, 0, idx, from, to, amount)
+            };
+        transfers_repository
+            .insert_batch(&[
+                // A coinbase, then the first step of a cross-chain transfer to chain 1
+                Transfer {
+                    pact_id: None,
+                    ..transfer(0, "block-0", 0, "", "alice", "100")
+                },
+                transfer(0, "block-0", 1, "alice", "", "10"),
+                // A mint made by another pact, then the continuation of the cross-chain transfer
+                transfer(1, "block-1", 0, "", "carol", "3"),
+                transfer(1, "block-1", 1, "", "bob", "10"),
+            ])
+            .unwrap();
+        events_repository
+            .insert_batch(&[Event {
+                block: "block-0".to_string(),
+                request_key: "request-key-0-1".to_string(),
+                ..make_event(
+                    "coin",
+                    "TRANSFER_XCHAIN",
+                    serde_json::json!(["alice", "bob", 10, "1"]),
+                )
+            }])
+            .unwrap();
+        let continuation = |def: &str, idx: i64| Transaction {
+            chain_id: 1,
+            continuation: Some(serde_json::json!({ "continuation": { "def": def, "args": [] } })),
+            ..make_transaction("block-1", 0, &format!("request-key-0-{}", idx))
+        };
+        let mut conn = pool.get().unwrap();
+        transactions_repository
+            .insert_batch_with_conn(
+                &[
+                    continuation("free.escrow.release", 0),
+                    continuation("coin.transfer-crosschain", 1),
+                ],
+                &mut conn,
+            )
+            .unwrap();
+        supply_repository
+            .apply_blocks_with_conn(
+                &["block-0".to_string(), "block-1".to_string()],
+                1,
+                &mut conn,
+            )
+            .unwrap();
+
+        let (from, to) = (time("2023-10-01 00:00:00"), time("2023-10-02 00:00:00"));
+        let snapshots = supply_repository
+            .find_by_range("coin", from, to, Some(0))
+            .unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].bucket, from);
+        assert_eq!(snapshots[0].minted, BigDecimal::from(100));
+        assert_eq!(snapshots[0].burned, BigDecimal::from(0));
+        assert_eq!(snapshots[0].crosschain_out, BigDecimal::from(10));
+        let snapshots = supply_repository
+            .find_by_range("coin", from, to, Some(1))
+            .unwrap();
+        assert_eq!(snapshots[0].minted, BigDecimal::from(3));
+        assert_eq!(snapshots[0].crosschain_in, BigDecimal::from(10));
+
+        supply_repository
+            .revert_block_with_conn("block-1", &mut conn)
+            .unwrap();
+        let snapshots = supply_repository
+            .find_by_range("coin", from, to, Some(1))
+            .unwrap();
+        assert_eq!(snapshots[0].minted, BigDecimal::from(0));
+        assert_eq!(snapshots[0].crosschain_in, BigDecimal::from(0));
+
+        supply_repository.delete_all().unwrap();
+        events_repository.delete_all().unwrap();
+        transactions_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}
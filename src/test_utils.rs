@@ -0,0 +1,100 @@
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::models::{Block, Event, Transaction, Transfer};
+
+pub fn time(value: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
+pub fn make_block(chain_id: i64, height: i64, hash: &str) -> Block {
+    Block {
+        chain_id,
+        hash: hash.to_string(),
+        height,
+        parent: "parent".to_string(),
+        weight: BigDecimal::from(0),
+        creation_time: Utc::now().naive_utc(),
+        epoch: Utc::now().naive_utc(),
+        flags: BigDecimal::from(0),
+        miner: "miner".to_string(),
+        nonce: BigDecimal::from(0),
+        payload: "payload".to_string(),
+        pow_hash: "".to_string(),
+        predicate: "predicate".to_string(),
+        target: BigDecimal::from(1),
+    }
+}
+
+pub fn make_transfer(
+    block: &str,
+    height: i64,
+    idx: i64,
+    from: &str,
+    to: &str,
+    amount: &str,
+) -> Transfer {
+    Transfer {
+        amount: BigDecimal::from_str(amount).unwrap(),
+        block: block.to_string(),
+        chain_id: 0,
+        creation_time: Utc::now().naive_utc(),
+        from_account: from.to_string(),
+        height,
+        idx,
+        module_hash: "module-hash".to_string(),
+        module_name: "coin".to_string(),
+        pact_id: None,
+        request_key: format!("request-key-{}-{}", height, idx),
+        to_account: to.to_string(),
+    }
+}
+
+pub fn make_event(module: &str, name: &str, params: Value) -> Event {
+    Event {
+        block: "block".to_string(),
+        chain_id: 0,
+        height: 0,
+        idx: 0,
+        module: module.to_string(),
+        module_hash: "module-hash".to_string(),
+        name: name.to_string(),
+        param_text: params.to_string(),
+        params,
+        qual_name: format!("{}.{}", module, name),
+        request_key: "request-key".to_string(),
+        pact_id: None,
+    }
+}
+
+pub fn make_transaction(block: &str, height: i64, request_key: &str) -> Transaction {
+    Transaction {
+        bad_result: None,
+        block: block.to_string(),
+        chain_id: 0,
+        code: None,
+        continuation: None,
+        creation_time: Utc::now().naive_utc(),
+        data: None,
+        gas: 0,
+        gas_limit: 0,
+        gas_price: 0.0,
+        good_result: Some(serde_json::json!("Write succeeded")),
+        height,
+        logs: None,
+        metadata: None,
+        nonce: "nonce".to_string(),
+        num_events: None,
+        pact_id: None,
+        proof: None,
+        request_key: request_key.to_string(),
+        rollback: None,
+        sender: "sender".to_string(),
+        step: None,
+        ttl: 0,
+        tx_id: None,
+        position: None,
+    }
+}
@@ -0,0 +1,263 @@
+use bigdecimal::BigDecimal;
+use serde_json::Value;
+use std::error::Error;
+use std::str::FromStr;
+
+use crate::chainweb_client::tx_result::Status;
+use crate::chainweb_client::{ChainId, ChainwebClient};
+use crate::repository::TokensRepository;
+
+/// Asks the node for the precision of every registered token that doesn't have one yet.
+/// Modules that don't implement `fungible-v2` are skipped and retried on the next run.
+pub async fn update_precisions(
+    client: &ChainwebClient,
+    repository: &TokensRepository,
+) -> Result<usize, Box<dyn Error>> {
+    let modules = repository
+        .find_without_precision()
+        .map_err(|e| e as Box<dyn Error>)?;
+    let mut updated = 0;
+    for module in modules.iter() {
+        match fetch_precision(client, &ChainId(0), module).await {
+            Ok(Some(precision)) => {
+                repository
+                    .update_precision(module, precision)
+                    .map_err(|e| e as Box<dyn Error>)?;
+                updated += 1;
+            }
+            Ok(None) => log::warn!("Unable to get precision of {}", module),
+            Err(e) => log::warn!("Unable to get precision of {}: {}", module, e),
+        }
+    }
+    log::info!(
+        "Updated precision of {} out of {} tokens",
+        updated,
+        modules.len()
+    );
+    Ok(updated)
+}
+
+/// Calls `(module.precision)` on the node, returns `None` if the call fails.
+pub async fn fetch_precision(
+    client: &ChainwebClient,
+    chain: &ChainId,
+    module: &str,
+) -> Result<Option<i32>, Box<dyn Error>> {
+    let response = client
+        .local(chain, &format!("({}.precision)", module))
+        .await?;
+    Ok(match response.result.status {
+        Status::Success => response
+            .result
+            .data
+            .as_ref()
+            .and_then(parse_integer)
+            .and_then(|precision| i32::try_from(precision).ok()),
+        Status::Failure => None,
+    })
+}
+
+/// Pact integers are returned either as plain numbers or as `{"int": ...}`.
+pub fn parse_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.as_i64(),
+        Value::Object(object) => object.get("int").and_then(parse_integer),
+        _ => None,
+    }
+}
+
+/// Pact decimals are returned either as plain numbers or as `{"decimal": "..."}`.
+pub fn parse_decimal(value: &Value) -> Option<BigDecimal> {
+    match value {
+        Value::Number(number) => BigDecimal::from_str(&number.to_string()).ok(),
+        Value::Object(object) => object
+            .get("decimal")
+            .and_then(|decimal| decimal.as_str())
+            .and_then(|decimal| BigDecimal::from_str(decimal).ok()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::Transfer;
+    use crate::repository::{BalancesRepository, BlocksRepository, TransfersRepository};
+    use crate::test_utils::{make_block, make_transfer};
+    use serial_test::serial;
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer(&serde_json::json!(12)), Some(12));
+        assert_eq!(parse_integer(&serde_json::json!({ "int": 8 })), Some(8));
+        assert_eq!(parse_integer(&serde_json::json!("12")), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_precision() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/chainweb/0.0/mainnet01/chain/0/pact/api/v1/local")
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "gas": 5,
+                    "result": { "status": "success", "data": 12 },
+                    "reqKey": "request-key",
+                    "logs": "logs",
+                    "metaData": {
+                        "blockHeight": 4000000,
+                        "blockTime": 1700000000000000i64,
+                        "prevBlockHash": "prev-block-hash"
+                    },
+                    "continuation": null,
+                    "txId": null
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = ChainwebClient::with_host(&server.url());
+        let precision = fetch_precision(&client, &ChainId(0), "coin").await.unwrap();
+        assert_eq!(precision, Some(12));
+    }
+
+    #[test]
+    #[serial]
+    fn test_apply_and_revert_blocks() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let tokens_repository = TokensRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 0, "block-0"), make_block(0, 1, "block-1")])
+            .unwrap();
+        transfers_repository
+            .insert_batch(&[
+                make_transfer("block-0", 0, 0, "alice", "bob", "10"),
+                Transfer {
+                    module_hash: "module-hash-2".to_string(),
+                    ..make_transfer("block-1", 1, 0, "alice", "bob", "5")
+                },
+                Transfer {
+                    module_name: "free.token".to_string(),
+                    ..make_transfer("block-1", 1, 1, "", "alice", "1")
+                },
+            ])
+            .unwrap();
+        let mut conn = pool.get().unwrap();
+        tokens_repository
+            .apply_blocks_with_conn(
+                &["block-0".to_string(), "block-1".to_string()],
+                1,
+                &mut conn,
+            )
+            .unwrap();
+        let coin = tokens_repository.find_by_module("coin").unwrap().unwrap();
+        assert_eq!(coin.token.transfer_count, 2);
+        assert_eq!(coin.token.volume, BigDecimal::from(15));
+        assert_eq!(coin.token.first_seen_height, 0);
+        assert_eq!(coin.module_hashes.len(), 2);
+        let token = tokens_repository
+            .find_by_module("free.token")
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.token.namespace, Some("free".to_string()));
+        assert_eq!(token.token.transfer_count, 1);
+
+        tokens_repository
+            .revert_block_with_conn("block-1", &mut conn)
+            .unwrap();
+        let coin = tokens_repository.find_by_module("coin").unwrap().unwrap();
+        assert_eq!(coin.token.transfer_count, 1);
+        assert_eq!(coin.token.volume, BigDecimal::from(10));
+        assert_eq!(coin.module_hashes.len(), 1);
+        assert_eq!(coin.module_hashes[0].module_hash, "module-hash");
+        // The token was only transferred in the reverted block
+        assert!(tokens_repository
+            .find_by_module("free.token")
+            .unwrap()
+            .is_none());
+
+        tokens_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_find_holders_and_total_supply() {
+        dotenvy::from_filename(".env.test").ok();
+        let pool = db::initialize_db_pool();
+        let blocks_repository = BlocksRepository { pool: pool.clone() };
+        let transfers_repository = TransfersRepository { pool: pool.clone() };
+        let balances_repository = BalancesRepository { pool: pool.clone() };
+        let tokens_repository = TokensRepository { pool: pool.clone() };
+        blocks_repository
+            .insert_batch(&[make_block(0, 0, "block-0"), make_block(1, 0, "block-1")])
+            .unwrap();
+        transfers_repository
+            .insert_batch(&[
+                make_transfer("block-0", 0, 0, "", "alice", "100"),
+                make_transfer("block-0", 0, 1, "alice", "bob", "30"),
+                make_transfer("block-0", 0, 2, "bob", "", "5"),
+                Transfer {
+                    chain_id: 1,
+                    ..make_transfer("block-1", 0, 0, "", "carol", "7")
+                },
+            ])
+            .unwrap();
+        let mut conn = pool.get().unwrap();
+        balances_repository
+            .apply_blocks_with_conn(
+                &["block-0".to_string(), "block-1".to_string()],
+                1,
+                &mut conn,
+            )
+            .unwrap();
+
+        let holders = tokens_repository
+            .find_holders("coin", None, 10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|holder| (holder.account, holder.balance))
+            .collect::<Vec<(String, BigDecimal)>>();
+        assert_eq!(
+            holders,
+            vec![
+                ("alice".to_string(), BigDecimal::from(70)),
+                ("bob".to_string(), BigDecimal::from(25)),
+                ("carol".to_string(), BigDecimal::from(7)),
+            ]
+        );
+        let holders = tokens_repository
+            .find_holders("coin", Some(1), 10, 0)
+            .unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].account, "carol");
+        let holders = tokens_repository.find_holders("coin", None, 1, 1).unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].account, "bob");
+
+        assert_eq!(
+            tokens_repository.find_total_supply("coin", None).unwrap(),
+            BigDecimal::from(102)
+        );
+        assert_eq!(
+            tokens_repository
+                .find_total_supply("coin", Some(0))
+                .unwrap(),
+            BigDecimal::from(95)
+        );
+        assert_eq!(
+            tokens_repository.find_total_supply("other", None).unwrap(),
+            BigDecimal::from(0)
+        );
+
+        balances_repository.delete_all().unwrap();
+        transfers_repository.delete_all().unwrap();
+        blocks_repository.delete_all().unwrap();
+    }
+}